@@ -3,34 +3,72 @@
 
   起動引数を取得して、
   場合に応じたオプションをつける
-  
+
+  引数はいくつでも並べられる。値を取るものは
+  `--seed 42`と`--seed=42`のどちらの書き方でも良い。
+  知らない引数があれば、エラーとUSAGEを表示して終了する
+
   * print_usage()  : ヘルプ表示用の関数
   * print_version(): version表示用の関数
-  
+  * take_value()   : 値を取る引数の値を読む
+  * exit_with_error(): エラーとUSAGEを表示して終了する
+
   * impl Args
-    * new()           : 外部から呼び出す関数。内部でargs_check()を呼ぶ。
-    * args_check()    : env::args()の値を見て、適切なモードを指定する
-    * set_to_env_var(): デバッグモードかどうかを、環境変数に指定する
-  
+    * new()           : 外部から呼び出す関数。内部でparse()を呼ぶ。
+    * parse()         : 引数の一覧を見て、適切なモードを指定する
+    * apply_to()      : 起動引数で指定された値で、設定を上書きする
+
   * struct Args
-    * flag_debug   : debug modeかどうかを判定する変数
+    * run_mode     : 起動モード。デバッグ機能のオン・オフもここに
+    * config       : 設定ファイルのpath
+    * seed         : 起動引数で指定された乱数シード値
+    * fps          : 固定FPS数値
+    * lang         : 翻訳データのフォルダ
+    * windowed     : フルスクリーン設定を無視してウィンドウで起動するか
+    * replay       : 再生するリプレイファイルのpath
+    * print_config : 重ねた結果の設定を表示して終了するか
+    * check_config : 設定を調べるだけで終了するか
+    * difficulty   : 難易度
 
 -------------------------------*/
 use std::{self, env};
 
+use conf::GameConf;
+use difficulty::Difficulty;
+use run_mode::{ DebugOptions, RunMode };
+
 const USAGE: &'static str = "  \
   Description:
     dodge rock game
-  
+
   USAGE:
+    dodge_rock [options]
     dodge_rock (-h | --help)
     dodge_rock (-v | --version)
-    dodge_rock (-d | --debug)
 
   Options:
-    -h --help     Show this screen.
-    -v --version  Show version.
-    -d --debug    Run game with debug mode.";
+    -h --help               Show this screen.
+    -v --version            Show version.
+    -c --config <file>      Load settings from <file>.
+                            Default: game_config.toml
+    -d --debug [<features>] Run game with debug mode.
+                            <features> is a comma separated list of
+                            hitboxes, fps_log, spawn_key, invincible,
+                            spawn_stats.
+                            Default: hitboxes,fps_log,spawn_key
+    -s --seed <seed>        Use fixed random seed.
+    --fps <n>               Run game logic at <n> frames per second.
+    --lang <dir>            Load translated text from <dir> (e.g. tl/english).
+    -w --windowed           Start in a window even if fullscreen is set.
+    -r --replay <file>      Play back a recorded replay file.
+    --difficulty <name>     Start at difficulty <name>.
+                            easy, normal, hard or lunatic.
+                            Default: normal
+    --print-config          Print the effective settings and exit.
+                            (defaults < game_config.toml
+                             < user config dir < command line)
+    --check-config          Check the settings, report every problem and exit.
+                            Exit status is 1 if any problem was found.";
 
 // build時にCargo.tomlから名前とバージョンを組み込ませる
 const OWN_NAME: &'static str = env!("CARGO_PKG_NAME");
@@ -46,62 +84,257 @@ fn print_version() {
     println!("{} v{}", OWN_NAME, OWN_VERSION);
 }
 
+/// 値を取る引数の値を読む。内部用。
+///
+/// "--seed=42"なら"="の後を、"--seed 42"なら次の引数を値にする
+fn take_value<I>(iter: &mut I, inline_value: Option<String>, name: &str) -> Result<String, String>
+    where I: Iterator<Item = String> {
+    match inline_value {
+        Some(v) => Ok(v),
+        None => iter.next().ok_or_else(|| format!("{}には値を指定してください", name)),
+    }
+}
+
+/// エラーとUSAGEを表示して終了する。内部用。
+fn exit_with_error<'a>(msg: &'a str) -> ! {
+    println!("Error: {}", msg);
+    print_usage();
+    std::process::exit(1);
+}
+
 #[derive(Debug, Default)]
 pub struct Args {
-    pub flag_debug: bool,
+    pub run_mode: RunMode,
+    /// 設定ファイルのpath。Noneならgame_config.toml
+    pub config: Option<String>,
+    pub seed: Option<u64>,
+    /// 固定FPS数値。設定ファイルより優先する
+    pub fps: Option<u32>,
+    /// 翻訳データのフォルダ。指定したら翻訳を有効にする
+    pub lang: Option<String>,
+    /// trueなら、設定ファイルがフルスクリーンでもウィンドウで起動する
+    pub windowed: bool,
+    pub replay: Option<String>,
+    /// trueなら、重ねた結果の設定を表示して終了する
+    pub print_config: bool,
+    /// trueなら、設定を調べて結果を表示し、終了する
+    pub check_config: bool,
+    /// 難易度。設定ファイルより優先する
+    pub difficulty: Option<Difficulty>,
 }
 
 impl Args {
     /// 起動引数の読み込みと分析
-    pub fn new() {
-        // Args struct用の各種変数初期化
-        let mut args:Args = Default::default();
-
-        // 引数なしの場合は早期終了、エラー回避。
-        if env::args().len() == 1 {
-            // Do nothing
-        } else {
-            args.args_check();
+    ///
+    /// 引数がおかしければ、エラーとUSAGEを表示して終了する
+    pub fn new() -> Self {
+        match Args::parse(env::args().skip(1)) {
+            Ok(args) => args,
+            Err(e) => exit_with_error(&e),
         }
-        
-        args.set_to_env_var();
     }
-    
-    /// 内部用。env::args()を見て、適切な引数が使われていたら作動する。
-    fn args_check(&mut self) {
-        // 起動引数を取得
-        let env_args: Vec<String> = env::args().skip(1).collect();
-        let first_arg = env_args[0].as_str();
-        
-        match first_arg {
-            "-h" | "--help" => {
-                print_usage();
-                std::process::exit(0);
-            }
-            
-            "-v" | "--version" => {
-                print_version();
-                std::process::exit(0);
-            }
-            
-            "-d" | "--debug" => {
-                self.flag_debug = true;
-            }
-            
-            _ => (),
-        } // match end
+
+    /// 引数の一覧（プログラム名は除く）を見て、Argsを作る
+    ///
+    /// --helpと--versionは、その場で表示して終了する
+    pub fn parse<I>(env_args: I) -> Result<Self, String>
+        where I: Iterator<Item = String> {
+        let mut args: Args = Default::default();
+        let mut iter = env_args.peekable();
+
+        while let Some(arg) = iter.next() {
+            // "--seed=42"の形なら、名前と値に分けておく
+            let (flag, inline_value) = match arg.find('=') {
+                Some(i) if arg.starts_with("--") => {
+                    (arg[..i].to_string(), Some(arg[i + 1..].to_string()))
+                }
+                _ => (arg.clone(), None),
+            };
+
+            match flag.as_str() {
+                "-h" | "--help" => {
+                    print_usage();
+                    std::process::exit(0);
+                }
+
+                "-v" | "--version" => {
+                    print_version();
+                    std::process::exit(0);
+                }
+
+                "-c" | "--config" => {
+                    args.config = Some(take_value(&mut iter, inline_value, "--config")?);
+                }
+
+                "-d" | "--debug" => {
+                    // 機能名が続いていなければ、いつものデバッグ機能を全部使う
+                    let list = match inline_value {
+                        Some(v) => Some(v),
+                        None if iter.peek().map_or(false, |n| !n.starts_with('-')) => iter.next(),
+                        None => None,
+                    };
+
+                    let opts = match list {
+                        Some(l) => DebugOptions::from_list(&l)?,
+                        None => DebugOptions::all(),
+                    };
+
+                    args.run_mode = RunMode::Debug(opts);
+                }
+
+                "-s" | "--seed" => {
+                    let v = take_value(&mut iter, inline_value, "--seed")?;
+                    match v.parse::<u64>() {
+                        Ok(n) => args.seed = Some(n),
+                        Err(_) => return Err(format!("--seedには0以上の整数を指定してください（指定: {}）", v)),
+                    }
+                }
+
+                "--fps" => {
+                    let v = take_value(&mut iter, inline_value, "--fps")?;
+                    match v.parse::<u32>() {
+                        Ok(n) if n > 0 => args.fps = Some(n),
+                        _ => return Err(format!("--fpsには1以上の整数を指定してください（指定: {}）", v)),
+                    }
+                }
+
+                "--lang" => {
+                    args.lang = Some(take_value(&mut iter, inline_value, "--lang")?);
+                }
+
+                "-w" | "--windowed" => {
+                    if inline_value.is_some() {
+                        return Err("--windowedは値を取りません".to_string());
+                    }
+                    args.windowed = true;
+                }
+
+                "-r" | "--replay" => {
+                    args.replay = Some(take_value(&mut iter, inline_value, "--replay")?);
+                }
+
+                "--difficulty" => {
+                    let v = take_value(&mut iter, inline_value, "--difficulty")?;
+                    match Difficulty::from_name(&v) {
+                        Some(d) => args.difficulty = Some(d),
+                        None => {
+                            let names: Vec<&str> = Difficulty::all().iter().map(|d| d.name()).collect();
+                            return Err(format!("--difficultyには{}のどれかを指定してください（指定: {}）",
+                                               names.join(", "), v));
+                        }
+                    }
+                }
+
+                "--print-config" => {
+                    if inline_value.is_some() {
+                        return Err("--print-configは値を取りません".to_string());
+                    }
+                    args.print_config = true;
+                }
+
+                "--check-config" => {
+                    if inline_value.is_some() {
+                        return Err("--check-configは値を取りません".to_string());
+                    }
+                    args.check_config = true;
+                }
+
+                _ => return Err(format!("知らない引数\"{}\"が指定された", arg)),
+            } // match end
+        }
+
+        Ok(args)
     }
-    
-    /// ゲームの起動引数に応じて、起動モードを環境変数に指定
-    fn set_to_env_var(&self) {
-        let game_activate_mode = "GAME_ACTIVATE_MODE";
-        
-        if self.flag_debug {
-            let debug = "DEBUG_MODE";
-            env::set_var(game_activate_mode, debug);
-        } else {
-            let normal = "NORMAL_MODE";
-            env::set_var(game_activate_mode, normal);
+
+    /// 起動引数で指定された値で、設定ファイルの内容を上書きする
+    pub fn apply_to(&self, conf: &mut GameConf) {
+        if self.seed.is_some() {
+            conf.game_option.seed = self.seed;
+        }
+
+        if let Some(fps) = self.fps {
+            conf.game_option.constant_fps = fps;
+        }
+
+        if let Some(ref lang) = self.lang {
+            conf.translate.is_translate = true;
+            conf.translate.translate_data_dir = lang.clone();
+        }
+
+        if let Some(d) = self.difficulty {
+            conf.game_option.difficulty = d.name().to_string();
+        }
+
+        if self.windowed {
+            conf.window.fullscreen = false;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 空白区切りの文字列を、起動引数の一覧として読ませる
+    fn parse(line: &str) -> Result<Args, String> {
+        let list: Vec<String> = line.split_whitespace().map(|s| s.to_string()).collect();
+        Args::parse(list.into_iter())
+    }
+
+    #[test]
+    fn seed_accepts_both_forms() {
+        assert_eq!(parse("--seed 42").unwrap().seed, Some(42));
+        assert_eq!(parse("--seed=42").unwrap().seed, Some(42));
+        assert_eq!(parse("-s 42").unwrap().seed, Some(42));
+        assert_eq!(parse("--seed 18446744073709551615").unwrap().seed, Some(u64::max_value()));
+    }
+
+    #[test]
+    fn seed_rejects_negative_and_non_numeric() {
+        assert!(parse("--seed -5").is_err());
+        assert!(parse("--seed=-5").is_err());
+        assert!(parse("--seed abc").is_err());
+        assert!(parse("--seed=").is_err());
+        assert!(parse("--seed 18446744073709551616").is_err());
+    }
+
+    #[test]
+    fn missing_value_is_error() {
+        assert!(parse("--seed").is_err());
+        assert!(parse("--fps").is_err());
+        assert!(parse("--windowed --replay").is_err());
+    }
+
+    #[test]
+    fn unknown_flag_is_error() {
+        let err = parse("--seed 1 --sede 2").err().unwrap();
+        assert!(err.contains("--sede"));
+        assert!(parse("-x").is_err());
+        // 値を取らない引数に値を付けてもエラー
+        assert!(parse("--windowed=yes").is_err());
+    }
+
+    #[test]
+    fn multiple_flags_combine() {
+        let args = parse("--fps=30 -w --lang tl/english --difficulty hard \
+                          -d hitboxes,invincible -r last.replay")
+            .unwrap();
+
+        assert_eq!(args.fps, Some(30));
+        assert!(args.windowed);
+        assert_eq!(args.lang, Some("tl/english".to_string()));
+        assert_eq!(args.difficulty, Some(Difficulty::Hard));
+        assert_eq!(args.replay, Some("last.replay".to_string()));
+        let debug = args.run_mode.debug();
+        assert!(debug.hitboxes && debug.invincible && !debug.fps_log);
+    }
+
+    #[test]
+    fn debug_without_list_uses_defaults() {
+        // "-d"の後に別の引数が続く時は、機能名の一覧として読まない
+        let args = parse("-d --seed 3").unwrap();
+
+        assert_eq!(args.run_mode, RunMode::Debug(DebugOptions::all()));
+        assert_eq!(args.seed, Some(3));
+    }
+}
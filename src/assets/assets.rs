@@ -5,90 +5,139 @@
   主にassetsフォルダからの自動読み込み
   
   * struct Dialog
+    * new()         : ゲーム画面の大きさから、ダイアログの配置を計算する
+    * centered_box(): 配置基準の領域の中央にボックスを置く
   
   * Assets impl
     * new()               : 親フォルダ名から一括でデータ出力。外部用。
     * show_map()          :
     * debug_new()         : デバッグに役立つ簡易版
+    * load_hitbox()       : 画像と同じ名前のtomlから当たり判定を読み込む
+    * load_waves()        : waves.tomlから岩の出方を読み込む
+    * get_path()          : ファイル名からassets内のpathを引く。なければエラー
+    * fs_path()           : ggez用のpathを、普通のpathに戻す
     * set_assets_map()    : 再帰読み込みした結果物を出力するやつ
     * set_assets_dir()    : 再帰読み込みを始める親フォルダを指定
     * recursive_read_dir(): 再帰読み込みしたデータをHashmapに放り込む
 -------------------------------*/
 
 use std::{ self, env };
-use std::path::{ PathBuf };
+use std::path::{ Path, PathBuf };
 use std::collections::HashMap;
-use std::io::Result;
 
-use ggez::{ Context, GameResult };
+use ggez::Context;
 use ggez::graphics::{ self, Color, Font, Image, Rect,};
 
 use etc;
 use conf::GameConf;
+use enemy::EnemyKind;
+use error::{ Error, Result };
+use hitbox::Hitbox;
+use waves::Waves;
+
+/// ダイアログの配置基準にする縦横比（横 / 縦）。元の360x480に合わせる
+const LAYOUT_ASPECT: f32 = 0.75;
 
 /// ダイアログボックス用の変数まとめ
 pub struct Dialog {
+    /// ダイアログや文字を配置する基準の領域
+    ///
+    /// ゲーム画面の中央に収まる、3:4の最大の矩形。
+    /// 横長・縦長の画面でもダイアログの形が崩れないように、
+    /// 画面全体ではなくこの領域に対する割合で配置する
+    pub layout: Rect,
     pub title_box: Rect,
     pub go_box: Rect,
+    pub pause_box: Rect,
     pub go_box_color: Color,
     pub black_color: Color,
+    /// NEW RECORDなど、目立たせたい文字の色
+    pub highlight_color: Color,
+    /// ウィンドウが非アクティブな時に、画面全体を暗くする色
+    pub dim_color: Color,
+    /// 黒帯の色
+    pub letterbox_color: Color,
     pub default_color: Color,
 }
 
 impl Dialog {
-    fn new(ctx: &mut Context) -> Self {
+    fn new(ctx: &mut Context, window_w: u32, window_h: u32) -> Self {
+        let (window_w, window_h) = (window_w as f32, window_h as f32);
+        
+        // 配置基準の領域。画面に収まる3:4の矩形を中央に置く
+        let (layout_w, layout_h) = if window_w / window_h > LAYOUT_ASPECT {
+            (window_h * LAYOUT_ASPECT, window_h)
+        } else {
+            (window_w, window_w / LAYOUT_ASPECT)
+        };
         
-        let (window_w, window_h) = (
-            ctx.conf.window_mode.width as f32,
-            ctx.conf.window_mode.height as f32,
+        let layout = graphics::Rect::new(
+            ((window_w - layout_w) / 2.0).round(),
+            ((window_h - layout_h) / 2.0).round(),
+            layout_w.round(),
+            layout_h.round(),
         );
         
         // ゲームオーバーダイアログのサイズ設定
         // 非常にマジックナンバーじみてるので、
         // 次のプロジェクトだとどうにかしたい
-        let (go_box_w, go_box_h) = (window_w * 0.75,
-                                    window_h * 0.46); 
-        
-        // 画面中央に、画面の六割ほどのダイアログボックスを表示
-        let go_box = graphics::Rect::new(
-            (window_w - go_box_w) / 2.0,
-            (window_h - go_box_h) / 2.0,
-            go_box_w,
-            go_box_h,
-        );
+        let go_box = Dialog::centered_box(&layout, 0.75, 0.8);
         
-        // タイトルダイアログのサイズ設定
-        let (title_box_w, title_box_h) = (
-            window_w * 0.9,
-            window_h * 0.57,
-        );
+        // 一時停止ダイアログのサイズ設定
+        let pause_box = Dialog::centered_box(&layout, 0.75, 0.5);
         
-        let title_box = graphics::Rect::new(
-            (window_w - title_box_w) / 2.0,
-            (window_h - title_box_h) / 2.0,
-            title_box_w,
-            title_box_h,
-        );
+        // タイトルダイアログのサイズ設定
+        let title_box = Dialog::centered_box(&layout, 0.9, 0.57);
         
         let go_box_color = Color::from_rgba(255, 255, 255, 220);
         
         let black_color = Color::from_rgba(0, 0, 0, 255);
         
+        let highlight_color = Color::from_rgba(220, 60, 0, 255);
+        
+        let dim_color = Color::from_rgba(0, 0, 0, 170);
+        
+        let letterbox_color = Color::from_rgba(0, 0, 0, 255);
+        
         Dialog {
+            layout: layout,
             go_box: go_box,
+            pause_box: pause_box,
             go_box_color: go_box_color,
             title_box: title_box,
             black_color: black_color,
+            highlight_color: highlight_color,
+            dim_color: dim_color,
+            letterbox_color: letterbox_color,
             default_color: graphics::get_color(ctx),
         }
     }
+    
+    /// 内部用。配置基準の領域に対する割合で、中央寄せのボックスを作る
+    fn centered_box(layout: &Rect, w_ratio: f32, h_ratio: f32) -> Rect {
+        let (box_w, box_h) = (layout.w * w_ratio, layout.h * h_ratio);
+        
+        graphics::Rect::new(
+            layout.x + (layout.w - box_w) / 2.0,
+            layout.y + (layout.h - box_h) / 2.0,
+            box_w,
+            box_h,
+        )
+    }
 }
 
 pub struct Assets {
     assets_map: HashMap<String, PathBuf>,
     pub dialog: Dialog,
     pub player_ship: Image,
-    pub enemy_block: Image, 
+    /// 岩の画像。EnemyKind::all()の順に並ぶ
+    pub enemy_block: Vec<Image>,
+    /// 自機の当たり判定
+    pub player_hitbox: Hitbox,
+    /// 岩の当たり判定。enemy_blockと同じ順
+    pub enemy_hitbox: Vec<Hitbox>,
+    /// 岩の出方と速さの上がり方
+    pub waves: Waves,
     pub pixel_font: Font,
     pub pixel_font_small: Font,
     pub pixel_font_big: Font,
@@ -96,54 +145,121 @@ pub struct Assets {
 
 impl Assets {
     /// Assets structを生成する（あとで消すかも）
-    pub fn new<'a>(ctx: &mut Context, conf: &'a GameConf) -> GameResult<Self> {
+    pub fn new<'a>(ctx: &mut Context, conf: &'a GameConf) -> Result<Self> {
         let a_map = Assets::set_assets_map(conf)?;
-        let player_ship = Image::new(
-            ctx, 
-            a_map.get("player_ship_29x48.png").unwrap(),
-        )?; 
-        
-        let enemy_block = Image::new(
-            ctx, 
-            a_map.get("enemy_block_32x32.png").unwrap(),
-        )?; 
-        
-        let pixel_font = Font::new(
-            ctx,
-            a_map.get("JF-Dot-MPlus12.ttf").unwrap(),
-            18,
+        
+        // ggezのエラーにはファイル名が入らないので、ここで添える
+        let load_image = |ctx: &mut Context, name: &str| -> Result<Image> {
+            Image::new(ctx, Assets::get_path(&a_map, name)?)
+                .map_err(|e| Error::asset(name, e))
+        };
+        
+        let load_font = |ctx: &mut Context, name: &str, size: u32| -> Result<Font> {
+            Font::new(ctx, Assets::get_path(&a_map, name)?, size)
+                .map_err(|e| Error::asset(name, e))
+        };
+        
+        let player_ship = load_image(ctx, "player_ship_29x48.png")?;
+        
+        let player_hitbox = Assets::load_hitbox(
+            &a_map,
+            "player_ship_29x48.toml",
+            &player_ship,
         )?;
+        
+        // 岩は種類ごとに、画像と同じ名前のtomlから当たり判定を読む
+        let mut enemy_block = Vec::with_capacity(EnemyKind::all().len());
+        let mut enemy_hitbox = Vec::with_capacity(EnemyKind::all().len());
+        
+        for kind in EnemyKind::all() {
+            let image_name = kind.image_name();
+            let image = load_image(ctx, image_name)?;
+            
+            let hitbox_name = image_name.replace(".png", ".toml");
+            let hitbox = Assets::load_hitbox(&a_map, &hitbox_name, &image)?;
+            
+            enemy_block.push(image);
+            enemy_hitbox.push(hitbox);
+        }
+        
+        let waves = Assets::load_waves(&a_map)?;
+        
+        let pixel_font = load_font(ctx, "JF-Dot-MPlus12.ttf", 18)?;
 
-        let pixel_font_small = Font::new(
-            ctx,
-            a_map.get("JF-Dot-MPlus12.ttf").unwrap(),
-            15,
-        )?;
+        let pixel_font_small = load_font(ctx, "JF-Dot-MPlus12.ttf", 15)?;
 
-        let pixel_font_big = Font::new(
-            ctx,
-            a_map.get("JF-Dot-MPlus12.ttf").unwrap(),
-            26,
-        )?;
+        let pixel_font_big = load_font(ctx, "JF-Dot-MPlus12.ttf", 26)?;
 
         Ok(Assets {
             assets_map: a_map,
-            dialog: Dialog::new(ctx),
+            dialog: Dialog::new(ctx, conf.window.width, conf.window.height),
             player_ship: player_ship,
             enemy_block: enemy_block,
+            player_hitbox: player_hitbox,
+            enemy_hitbox: enemy_hitbox,
+            waves: waves,
             pixel_font: pixel_font,
             pixel_font_small: pixel_font_small,
             pixel_font_big: pixel_font_big,
         })
     }
     
+    /// 内部用。画像と同じ名前のtomlから当たり判定を読み込む
+    ///
+    /// tomlがassetsフォルダになければ、画像全体を当たり判定にする
+    fn load_hitbox<'a>(a_map: &'a HashMap<String, PathBuf>,
+                       name: &'a str,
+                       image: &'a Image) -> Result<Hitbox> {
+        let (width, height) = (image.width(), image.height());
+        
+        match a_map.get(name) {
+            Some(tmp_path) => {
+                Hitbox::load(&Assets::fs_path(tmp_path), width, height)
+                    .map_err(|e| Error::asset(name, e))
+            }
+            None => Ok(Hitbox::full(width, height)),
+        }
+    }
+    
+    /// 内部用。waves.tomlから岩の出方と速さの上がり方を読み込む
+    ///
+    /// assetsフォルダになければ、組み込みの初期値を使う
+    fn load_waves<'a>(a_map: &'a HashMap<String, PathBuf>) -> Result<Waves> {
+        match a_map.get("waves.toml") {
+            Some(tmp_path) => {
+                Waves::load(&Assets::fs_path(tmp_path))
+                    .map_err(|e| Error::asset("waves.toml", e))
+            }
+            None => Ok(Waves::default()),
+        }
+    }
+    
+    /// ファイル名から、assets内のpath（ggez用の形式）を引く
+    ///
+    /// 見つからなければ、探したフォルダを添えてエラーにする
+    pub fn get_path<'a>(a_map: &'a HashMap<String, PathBuf>,
+                        name: &'a str) -> Result<&'a PathBuf> {
+        a_map.get(name).ok_or_else(|| {
+            Error::asset(name, "assetsフォルダ内に見つかりません")
+        })
+    }
+    
+    /// ggez用の"/foo/bar.toml"形式のpathを、普通のpathに戻す
+    ///
+    /// ggezを通さずに、std::fsで直接読み込む時に使う
+    pub fn fs_path<'a>(ggez_path: &'a Path) -> PathBuf {
+        let rel_path = ggez_path.strip_prefix("/").unwrap_or(ggez_path);
+        
+        etc::easy_path_set("").join(rel_path)
+    }
+    
     // 他所で使いまわすために、assets mapを参照渡し
     pub fn show_map<'a>(&'a self) -> &'a HashMap<String, PathBuf> {
         &self.assets_map
     }
     
     /// Assets mapだけを生成する（読み取るだけ）
-    pub fn new_map<'a>(conf: &'a GameConf) -> GameResult<HashMap<String, PathBuf>> {
+    pub fn new_map<'a>(conf: &'a GameConf) -> Result<HashMap<String, PathBuf>> {
         let a_map = Assets::set_assets_map(conf)?;
         
         Ok(a_map)
@@ -153,9 +269,12 @@ impl Assets {
         // assetsフォルダは環境変数で取ってきてるよ
         let assets_path = Assets::set_assets_dir(&conf.assets.assets_dir);
         
-        // assetsフォルダがなかったらエラーで落とす
-        if !assets_path.exists() {
-            panic!("Error: 存在しないassetsフォルダが指定された");
+        // assetsフォルダがなかったらエラーにする
+        if !assets_path.is_dir() {
+            return Err(Error::asset(
+                &assets_path.display().to_string(),
+                "assetsフォルダが存在しません（[assets]のassets_dirを確認してください）",
+            ));
         }
         
         // 翻訳データ追加を想定して、先んじてVec<PathBuf>にしておく
@@ -166,8 +285,11 @@ impl Assets {
         if conf.translate.is_translate {
             let tmp_tl_path = etc::easy_path_set(&conf.translate.translate_data_dir);
             
-            if !tmp_tl_path.exists() {
-                panic!("Error: 存在しない翻訳データフォルダが指定された");
+            if !tmp_tl_path.is_dir() {
+                return Err(Error::asset(
+                    &tmp_tl_path.display().to_string(),
+                    "翻訳データフォルダが存在しません（[translate]のtranslate_data_dirを確認してください）",
+                ));
             }
             
             include_dirs.push(tmp_tl_path);
@@ -188,8 +310,7 @@ impl Assets {
             assets_path.push(manifest_dir);
         }
         
-        // conf.rsで追加した環境変数から、assetsディレクトリ名を指定
-        // もし環境変数になければpanic!する
+        // 設定ファイルで指定された、assetsディレクトリ名を追加
         assets_path.push(assets_dir);
 
         assets_path
@@ -209,17 +330,25 @@ impl Assets {
         
         // 今思うともう少しシンプルに書けたかも
         while dir_tmp.len() > 0 {
-            let rdir = std::fs::read_dir(dir_tmp.swap_remove(0))?;
+            let dir_path = dir_tmp.swap_remove(0);
+            let rdir = std::fs::read_dir(&dir_path).map_err(|e| Error::io(&dir_path, e))?;
             
             for li in rdir {
                 match li {
                     Ok(d) => {
-                        if d.metadata()?.is_dir() {
+                        let metadata = d.metadata().map_err(|e| Error::io(&d.path(), e))?;
+                        if metadata.is_dir() {
                             dir_tmp.push(d.path());
-                        } else if d.metadata()?.is_file() {
-                            let item_name = d.file_name()
-                                             .into_string()
-                                             .expect("file name -> String変換時のエラー");
+                        } else if metadata.is_file() {
+                            let item_name = match d.file_name().into_string() {
+                                Ok(name) => name,
+                                Err(_) => {
+                                    return Err(Error::asset(
+                                        &d.path().display().to_string(),
+                                        "ファイル名にUTF-8でない文字が含まれています",
+                                    ));
+                                }
+                            };
                             /* d.path()からbase_pathを引いて、
                                assetsフォルダ以降のpathを出す */
                             /*
@@ -230,10 +359,15 @@ impl Assets {
                               仕方がないので、強引に"/foo/bar.png"形式に修正。
                             */
                             let mut item_path = PathBuf::from("/");
-                            let tmp_path = d.path()
-                                .strip_prefix(&base_path)
-                                .expect("path切り詰め時のエラー")
-                                .to_path_buf();
+                            let tmp_path = match d.path().strip_prefix(&base_path) {
+                                Ok(p) => p.to_path_buf(),
+                                Err(_) => {
+                                    return Err(Error::asset(
+                                        &d.path().display().to_string(),
+                                        "assetsフォルダはゲームのフォルダ内に置いてください",
+                                    ));
+                                }
+                            };
                             item_path.push(&tmp_path);
                             base.insert(
                                 item_name, 
@@ -9,17 +9,26 @@
   * impl GameText:
     * new()
     * new_score() : スコア表示のためTextを再生成
+    * new_high_scores() : ハイスコア表示のためTextを再生成
+    * new_countdown() : 再開までのカウントダウン表示のためTextを再生成
+    * new_hud() : プレイ中のHUD表示のためTextを再生成。変わった行だけ
+    * new_title_difficulty() : タイトル画面の難易度表示のためTextを再生成
     * from_array()
   
 -------------------------------*/
-use std::io::Result;
-
 use ggez::graphics::{ Font, Text };
 use ggez::{ Context, GameResult };
 use toml;
 
 use assets::Assets;
+use conf;
+use difficulty::Difficulty;
+use error::{ Error, Result };
 use etc;
+use high_score::HighScores;
+
+/// ゲームオーバー画面に表示するハイスコアの件数
+pub const SHOWN_HIGH_SCORES: usize = 5;
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Source {
@@ -27,28 +36,41 @@ pub struct Source {
     title_description: String,
     title_headline: String,
     title_tips: Vec<String>,
+    title_difficulty: String,
+    /// 難易度の表示名。Difficulty::all()の順に並べる
+    difficulty_names: Vec<String>,
     game_over_title: String,
     game_over_score: String,
+    game_over_new_record: String,
+    game_over_high_score: String,
     game_over_tips: Vec<String>,
+    pause_title: String,
+    pause_tips: Vec<String>,
+    focus_lost: String,
+    hud_time: String,
+    hud_speed: String,
+    hud_best: String,
 }
 
 impl Source {
     fn new(assets: &Assets) -> Result<Self> {
-        // ggez用にいじってるPathBufを正常な&strにする: 7れんさ
-        let text_tmp_path = assets
-            .show_map()
-            .get("game_text.toml")
-            .expect("HashMap.get()時のエラー")
-            .strip_prefix("/")
-            .expect("strip_prefix時のエラー")
-            .to_str()
-            .expect("PathBuf.to_str()時のエラー");
-            
-        let text_path = etc::easy_path_set(text_tmp_path);
+        // ggez用にいじってるPathBufを、普通のpathに戻す
+        let text_tmp_path = Assets::get_path(assets.show_map(), "game_text.toml")?;
         
-        let tmp_vec = etc::File::read_to_vec(&text_path)?;
+        let text_path = Assets::fs_path(text_tmp_path);
         
-        let src_text: Source = toml::de::from_slice(&tmp_vec).expect("toml deserialize時のエラー");
+        let tmp_vec = etc::File::read_to_vec(&text_path)
+            .map_err(|e| Error::io(&text_path, e))?;
+        
+        // 翻訳データで項目が抜けていたら、ここでどの項目かが分かる
+        let src_text: Source = toml::de::from_slice(&tmp_vec)
+            .map_err(|e| Error::text(&text_path, e))?;
+        
+        if src_text.difficulty_names.len() != Difficulty::all().len() {
+            return Err(Error::text(&text_path, format!(
+                "difficulty_namesは難易度の数だけ（{}個）書いてください（{}個）",
+                Difficulty::all().len(), src_text.difficulty_names.len())));
+        }
         
         Ok(src_text)
     }
@@ -60,16 +82,36 @@ pub struct GameText {
     pub title_description: Text,
     pub title_headline: Text,
     pub title_tips: Vec<Text>,
+    /// タイトル画面の難易度表示。new_title_difficulty()で作り直す
+    pub title_difficulty: Text,
+    /// 難易度表示の見出しと、難易度ごとの表示名
+    pub title_difficulty_label: String,
+    pub difficulty_names: Vec<String>,
     pub game_over_title: Text,
     pub game_over_score: Text,
     pub game_over_score_num: Text,
+    pub game_over_new_record: Text,
+    pub game_over_high_score: Text,
+    pub high_score_list: Vec<Text>,
     pub game_over_tips: Vec<Text>,
+    pub pause_title: Text,
+    pub pause_tips: Vec<Text>,
+    pub countdown_num: Text,
+    pub focus_lost: Text,
+    /// HUDの見出し。値はnew_hud()で後ろに付ける
+    pub hud_time_label: String,
+    pub hud_speed_label: String,
+    pub hud_best_label: String,
+    /// HUDの各行。表示しない項目は入らない
+    pub hud: Vec<Text>,
+    /// 内部用。今のhudの元になった文字列。変わった行だけ作り直すため
+    hud_lines: Vec<String>,
 }
 
 impl GameText {
     /// GameTextの生成
     pub fn new(ctx: &mut Context,
-               assets: &Assets) -> GameResult<Self> {
+               assets: &Assets) -> Result<Self> {
         let src = Source::new(assets)?;
         
         let title_logo = Text::new(
@@ -96,6 +138,13 @@ impl GameText {
             &assets.pixel_font_small,
         )?;
         
+        // 後で書き換えるので、ひとまず見出しだけで作っておく
+        let title_difficulty = Text::new(
+            ctx,
+            &src.title_difficulty,
+            &assets.pixel_font_small,
+        )?;
+        
         let game_over_title = Text::new(
             ctx,
             &src.game_over_title,
@@ -112,30 +161,78 @@ impl GameText {
         // ほんとは空テキストを出力できる機能がggezにあるべきなのよ。
         let game_over_score_num = game_over_score.clone();
         
+        let game_over_new_record = Text::new(
+            ctx,
+            &src.game_over_new_record,
+            &assets.pixel_font
+        )?;
+        
+        let game_over_high_score = Text::new(
+            ctx,
+            &src.game_over_high_score,
+            &assets.pixel_font_small
+        )?;
+        
         let game_over_tips = GameText::from_array(
             ctx,
             &src.game_over_tips,
             &assets.pixel_font,
         )?;
         
+        let pause_title = Text::new(
+            ctx,
+            &src.pause_title,
+            &assets.pixel_font_big
+        )?;
+        
+        let pause_tips = GameText::from_array(
+            ctx,
+            &src.pause_tips,
+            &assets.pixel_font,
+        )?;
+        
+        // これも後で書き換えるので、適当にclone()
+        let countdown_num = pause_title.clone();
+        
+        let focus_lost = Text::new(
+            ctx,
+            &src.focus_lost,
+            &assets.pixel_font
+        )?;
+        
         Ok(GameText {
             title_logo: title_logo,
             title_description: title_description,
             title_headline: title_headline,
             title_tips: title_tips,
+            title_difficulty: title_difficulty,
+            title_difficulty_label: src.title_difficulty,
+            difficulty_names: src.difficulty_names,
             game_over_title: game_over_title,
             game_over_score: game_over_score,
             game_over_score_num: game_over_score_num,
+            game_over_new_record: game_over_new_record,
+            game_over_high_score: game_over_high_score,
+            high_score_list: Vec::new(),
             game_over_tips: game_over_tips,
+            pause_title: pause_title,
+            pause_tips: pause_tips,
+            countdown_num: countdown_num,
+            focus_lost: focus_lost,
+            hud_time_label: src.hud_time,
+            hud_speed_label: src.hud_speed,
+            hud_best_label: src.hud_best,
+            hud: Vec::new(),
+            hud_lines: Vec::new(),
         })
     }
     
-    // その時のフレーム数からスコアを計算する
+    // 耐久秒数からスコア表示を作る
     pub fn new_score(&mut self,
                      ctx: &mut Context,
                      font: &Font,
-                     score: usize) -> GameResult<()> {
-        let tmp_t = format!("**{}**", score);
+                     elapsed: f64) -> GameResult<()> {
+        let tmp_t = format!("**{}**", etc::format_time(elapsed));
         
         let out_t = Text::new(
             ctx,
@@ -148,6 +245,109 @@ impl GameText {
         Ok(())
     }
     
+    /// ハイスコア表から、上位数件分の表示用Textを作り直す
+    pub fn new_high_scores(&mut self,
+                           ctx: &mut Context,
+                           font: &Font,
+                           scores: &HighScores) -> GameResult<()> {
+        let mut tmp_vec = Vec::with_capacity(SHOWN_HIGH_SCORES);
+        
+        for (i, li) in scores.entries.iter().take(SHOWN_HIGH_SCORES).enumerate() {
+            // 日付部分だけを表示して、横幅を抑える
+            let date = li.date.split(' ').next().unwrap_or("");
+            tmp_vec.push(format!("{:>2}. {:>8}  {}",
+                                 i + 1, etc::format_time(li.duration), date));
+        }
+        
+        self.high_score_list = GameText::from_array(ctx, &tmp_vec, font)?;
+        
+        Ok(())
+    }
+    
+    /// 再開までの残り秒数から、カウントダウン表示を作り直す
+    pub fn new_countdown(&mut self,
+                         ctx: &mut Context,
+                         font: &Font,
+                         secs: usize) -> GameResult<()> {
+        let tmp_t = format!("{}", secs);
+        
+        self.countdown_num = Text::new(
+            ctx,
+            &tmp_t,
+            font,
+        )?;
+        
+        Ok(())
+    }
+    
+    /// 経過時間・岩の速さ・自己ベストから、HUD表示を作り直す
+    ///
+    /// 毎フレーム呼ばれるので、前回と文字列が変わった行だけTextを作る
+    pub fn new_hud(&mut self,
+                   ctx: &mut Context,
+                   font: &Font,
+                   hud: &conf::Hud,
+                   elapsed: f64,
+                   speed_level: f32,
+                   best: Option<f64>) -> GameResult<()> {
+        let mut tmp_vec = Vec::with_capacity(3);
+        
+        if hud.show_time {
+            tmp_vec.push(format!("{} {}", self.hud_time_label, etc::format_time(elapsed)));
+        }
+        
+        if hud.show_speed {
+            tmp_vec.push(format!("{} x{:.1}", self.hud_speed_label, speed_level));
+        }
+        
+        if hud.show_best {
+            // まだ記録がなければ、桁だけ見せておく
+            let best_t = match best {
+                Some(n) => etc::format_time(n),
+                None => "--:--.--".to_string(),
+            };
+            tmp_vec.push(format!("{} {}", self.hud_best_label, best_t));
+        }
+        
+        let mut out_vec = Vec::with_capacity(tmp_vec.len());
+        
+        for (i, li) in tmp_vec.iter().enumerate() {
+            let out_t = match self.hud_lines.get(i) {
+                Some(prev) if prev == li => self.hud[i].clone(),
+                _ => Text::new(ctx, li, font)?,
+            };
+            
+            out_vec.push(out_t);
+        }
+        
+        self.hud = out_vec;
+        self.hud_lines = tmp_vec;
+        
+        Ok(())
+    }
+    
+    /// 選んでいる難易度から、タイトル画面の難易度表示を作り直す
+    ///
+    /// 左右に選べることが分かるよう、表示名を矢印で挟む
+    pub fn new_title_difficulty(&mut self,
+                                ctx: &mut Context,
+                                font: &Font,
+                                difficulty: Difficulty) -> GameResult<()> {
+        let name = &self.difficulty_names[difficulty.index()];
+        let left = if difficulty.prev() != difficulty { "<" } else { " " };
+        let right = if difficulty.next() != difficulty { ">" } else { " " };
+        
+        let tmp_t = format!("{}: {} {} {}", self.title_difficulty_label, left, name, right);
+        
+        self.title_difficulty = Text::new(
+            ctx,
+            &tmp_t,
+            font,
+        )?;
+        
+        Ok(())
+    }
+    
     fn from_array(ctx: &mut Context,
                   in_vec: &Vec<String>,
                   font: &Font) -> GameResult<Vec<Text>> {
@@ -3,24 +3,41 @@ extern crate ggez;
 extern crate dodge_rock;
 
 use std::env;
-use std::path::PathBuf;
+use std::path::{ Path, PathBuf };
 
 // 可読性のため、use宣言を二つに分ける
 use ggez::{ ContextBuilder };
+use ggez::conf::FullscreenType;
 use ggez::event::run;
 
 use dodge_rock::{ args, etc };
 use dodge_rock::core_state::CoreState;
-use dodge_rock::conf::GameConf;
+use dodge_rock::conf::{ GameConf, CONF_FILE, GAME_AUTHOR, GAME_ID };
+use dodge_rock::replay::Replay;
+use dodge_rock::run_mode::RunMode;
 
-fn ggez_init(conf: GameConf) {
-    // TODO 今は直書きだけど、後々変更を施したい
-    let mut cb = ContextBuilder::new("dodge_rock", "dettalant")
+/// ggezを初期化して、ゲームを動かす
+///
+/// 起動時・実行中のエラーはそのまま返して、main()でまとめて表示する
+fn ggez_init(conf: GameConf,
+             run_mode: RunMode,
+             replay: Option<Replay>) -> dodge_rock::Result<()> {
+    // フルスクリーン時はデスクトップの解像度をそのまま使い、
+    // ゲーム画面はview.rs側で拡大・黒帯付けをする
+    let fullscreen_type = if conf.window.fullscreen {
+        FullscreenType::Desktop
+    } else {
+        FullscreenType::Off
+    };
+    
+    let mut cb = ContextBuilder::new(GAME_ID, GAME_AUTHOR)
         .window_setup(ggez::conf::WindowSetup::default()
-            .title("Dodge Rock Game")
+            .title(&conf.window.title)
         )
         .window_mode(ggez::conf::WindowMode::default()
-            .dimensions(360, 480)
+            .dimensions(conf.window.width, conf.window.height)
+            .fullscreen_type(fullscreen_type)
+            .vsync(conf.window.vsync)
         );
     
     if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
@@ -30,34 +47,101 @@ fn ggez_init(conf: GameConf) {
         cb = cb.add_resource_path("./");
     }
     
-    let ctx = &mut cb.build().expect("ggez ContextBuilderのエラー");
+    let ctx = &mut cb.build()?;
     
     // 了承なく.configと.localにフォルダ作るのやめてよggezくん
     let _ = etc::unused_dir_remove(ctx);
     
-    match CoreState::new(ctx, conf) {
-        Err(e) => {
-            println!("ゲーム起動に失敗");
-            println!("Error: {}", e);
-        }
-        Ok(ref mut game) => {
-            let result = run(ctx, game);
-            if let Err(e) = result {
-                println!("Error: ゲーム実行中に何かが起きたようだ - {}", e);
-            } else {
-                println!("ゲームを終了します");
-            }
-        }
+    let game = &mut CoreState::new(ctx, conf, run_mode)?;
+    
+    // リプレイが指定されていたら、再生モードで起動
+    if let Some(r) = replay {
+        game.start_playback(ctx, r)?;
     }
+    
+    run(ctx, game)?;
+    
+    println!("ゲームを終了します");
+    
+    Ok(())
+}
+
+/// 重ねた結果の設定を、どのファイルを読んだかと一緒に表示する
+fn print_config(conf: &GameConf) -> dodge_rock::Result<()> {
+    println!("# 読み込んだ設定ファイル（下にあるものほど優先）");
+    println!("#   (組み込みの初期値)");
+    for path in &conf.sources {
+        println!("#   {}", path.display());
+    }
+    println!("#   (起動引数)");
+    if let Some(user_path) = GameConf::user_conf_path() {
+        println!("# ユーザー設定ファイルの場所: {}", user_path.display());
+    }
+    println!();
+    print!("{}", conf.to_toml_string()?);
+    Ok(())
 }
 
 fn main() {
     // 起動引数に基づく起動モードを指定
-    args::Args::new();
+    let args = args::Args::new();
 
-    // ゲームの設定読み込みと環境変数指定
-    let conf = GameConf::new("game_config.toml").unwrap();
+    // ゲームの設定読み込み。"--config"で別のファイルも指定できる
+    let loaded = match args.config {
+        Some(ref path) => GameConf::from_file(path),
+        None => GameConf::new(CONF_FILE),
+    };
+    
+    let mut conf = match loaded {
+        Ok(c) => c,
+        Err(e) => {
+            println!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    
+    // シード値やFPSなど、起動引数で指定されたものは設定ファイルより優先する
+    args.apply_to(&mut conf);
+    
+    // 起動引数で変わった分も含めて、もう一度中身を調べる
+    if let Err(e) = conf.validate() {
+        println!("Error: {}", e);
+        std::process::exit(1);
+    }
+    
+    // "--check-config"なら、問題がなかったことを伝えて終わる
+    if args.check_config {
+        println!("設定に問題はありません");
+        for path in &conf.sources {
+            println!("  {}", path.display());
+        }
+        return;
+    }
+    
+    // "--print-config"なら、重ねた結果を表示して終わる
+    if args.print_config {
+        if let Err(e) = print_config(&conf) {
+            println!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    
+    // リプレイファイルが指定されていたら、先に読み込んでおく
+    let replay = match args.replay {
+        Some(ref path) => match Replay::load(Path::new(path)) {
+            Ok(r) => Some(r),
+            Err(e) => {
+                println!("Error: リプレイファイルを読み込めません - {}: {}", path, e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
     
     // ggezの初期化開始
-    ggez_init(conf);
+    if let Err(e) = ggez_init(conf, args.run_mode, replay) {
+        println!("Error: {}", e);
+        std::process::exit(1);
+    }
 }
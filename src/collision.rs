@@ -0,0 +1,369 @@
+/*-------------------------------
+            collision.rs
+
+  当たり判定の大まかな絞り込み（ブロードフェーズ）と、
+  移動中のすり抜けを防ぐ判定（連続的な当たり判定）を扱う
+
+  画面を一定サイズのマス目に区切り、各マスにかかっている敵の番号を覚えておく。
+  自機がかかっているマスの敵だけを、hitbox.rsで細かく調べれば済む。
+
+  マス目の中身は毎フレーム作り直すが、
+  Vecは使い回すので、敵の数が増えきった後は新たな確保をしない。
+
+  * type Aabb: 矩形(x, y, w, h)
+  * struct SpatialGrid: 一様なマス目による空間分割
+
+  * impl SpatialGrid:
+    * new()       : 対象範囲とマスの大きさから作る
+    * rebuild()   : 矩形の一覧から、マス目の中身を作り直す
+    * query()     : 矩形にかかるマスの番号を順に調べる
+    * cell_range(): 矩形がかかるマスの範囲を出す
+
+  * union()        : 二つの矩形を包む矩形
+  * swept_overlap(): 一フレームの移動中に、二つの矩形が重なったか
+  * slab()         : swept_overlap()の一軸分
+-------------------------------*/
+
+/// 矩形(x, y, w, h)。xとyは左上
+pub type Aabb = (f32, f32, f32, f32);
+
+/// マス一つの大きさ（ピクセル）。岩一つ分より少し大きめ
+pub const DEFAULT_CELL_SIZE: f32 = 64.0;
+
+#[derive(Clone, Debug)]
+/// 一様なマス目による空間分割
+///
+/// 中身はマスごとに番号を詰めた形で持つ。
+/// `cell_start[i]..cell_start[i + 1]`が、マスiに入っている番号の`items`上の範囲
+pub struct SpatialGrid {
+    /// マス一つの大きさ
+    cell_size: f32,
+    /// 対象範囲の左上
+    origin_x: f32,
+    origin_y: f32,
+    /// 横・縦のマス数
+    cols: usize,
+    rows: usize,
+    /// マスごとの、itemsの開始位置
+    cell_start: Vec<usize>,
+    /// マスごとに並べた番号
+    items: Vec<usize>,
+}
+
+impl SpatialGrid {
+    /// 対象範囲とマスの大きさから作る
+    ///
+    /// 範囲外の矩形は端のマスに入れるので、判定漏れにはならない
+    pub fn new(cell_size: f32, x: f32, y: f32, w: f32, h: f32) -> Self {
+        let cols = ((w / cell_size).ceil() as usize).max(1);
+        let rows = ((h / cell_size).ceil() as usize).max(1);
+
+        SpatialGrid {
+            cell_size: cell_size,
+            origin_x: x,
+            origin_y: y,
+            cols: cols,
+            rows: rows,
+            cell_start: vec![0; cols * rows + 1],
+            items: Vec::new(),
+        }
+    }
+
+    /// 矩形(x, y, w, h)の一覧から、マス目の中身を作り直す
+    ///
+    /// 数えて、詰める位置を決めて、詰めるの三手順。
+    /// そのためにイテレータを二回回すので、Cloneできるものを渡す
+    pub fn rebuild<I>(&mut self, rects: I)
+        where I: Iterator<Item = (f32, f32, f32, f32)> + Clone {
+        // マスごとの個数を数える。cell_start[i + 1]に溜めておく
+        for n in self.cell_start.iter_mut() {
+            *n = 0;
+        }
+
+        let mut total = 0;
+        for (x, y, w, h) in rects.clone() {
+            let (c0, c1, r0, r1) = self.cell_range(x, y, w, h);
+            for r in r0..(r1 + 1) {
+                for c in c0..(c1 + 1) {
+                    self.cell_start[r * self.cols + c + 1] += 1;
+                    total += 1;
+                }
+            }
+        }
+
+        // 累積和で、マスごとの開始位置にする
+        for i in 1..self.cell_start.len() {
+            self.cell_start[i] += self.cell_start[i - 1];
+        }
+
+        // 詰める。cursorとしてcell_start[i]を進めていき、最後に戻す
+        self.items.clear();
+        self.items.resize(total, 0);
+
+        for (idx, (x, y, w, h)) in rects.enumerate() {
+            let (c0, c1, r0, r1) = self.cell_range(x, y, w, h);
+            for r in r0..(r1 + 1) {
+                for c in c0..(c1 + 1) {
+                    let cell = r * self.cols + c;
+                    self.items[self.cell_start[cell]] = idx;
+                    self.cell_start[cell] += 1;
+                }
+            }
+        }
+
+        // 進めた分だけ一マスずれているので、後ろから戻す
+        for i in (1..self.cell_start.len()).rev() {
+            self.cell_start[i] = self.cell_start[i - 1];
+        }
+        self.cell_start[0] = 0;
+    }
+
+    /// 矩形(x, y, w, h)にかかるマスに入っている番号を、順に`f`へ渡す
+    ///
+    /// `f`がtrueを返したら、そこで打ち切ってtrueを返す。
+    /// 複数のマスにまたがる番号は、二回以上渡ることがある
+    pub fn query<F>(&self, x: f32, y: f32, w: f32, h: f32, mut f: F) -> bool
+        where F: FnMut(usize) -> bool {
+        let (c0, c1, r0, r1) = self.cell_range(x, y, w, h);
+
+        for r in r0..(r1 + 1) {
+            for c in c0..(c1 + 1) {
+                let cell = r * self.cols + c;
+                for &idx in &self.items[self.cell_start[cell]..self.cell_start[cell + 1]] {
+                    if f(idx) {
+                        return true;
+                    }
+                }
+            }
+        }
+
+        false
+    }
+
+    /// 内部用。矩形がかかるマスの範囲（列の始め・終わり、行の始め・終わり）
+    ///
+    /// 範囲外にはみ出した分は、端のマスに丸める
+    fn cell_range(&self, x: f32, y: f32, w: f32, h: f32) -> (usize, usize, usize, usize) {
+        let to_cell = |v: f32, origin: f32, max: usize| -> usize {
+            let n = ((v - origin) / self.cell_size).floor();
+            if n <= 0.0 {
+                0
+            } else {
+                (n as usize).min(max - 1)
+            }
+        };
+
+        (
+            to_cell(x, self.origin_x, self.cols),
+            to_cell(x + w, self.origin_x, self.cols),
+            to_cell(y, self.origin_y, self.rows),
+            to_cell(y + h, self.origin_y, self.rows),
+        )
+    }
+}
+
+/// 二つの矩形を包む、最小の矩形
+pub fn union(a: &Aabb, b: &Aabb) -> Aabb {
+    let x = a.0.min(b.0);
+    let y = a.1.min(b.1);
+    let right = (a.0 + a.2).max(b.0 + b.2);
+    let bottom = (a.1 + a.3).max(b.1 + b.3);
+
+    (x, y, right - x, bottom - y)
+}
+
+/// 矩形aがa0からa1へ、矩形bがb0からb1へ一フレームで真っ直ぐ動いた時、
+/// その途中で重なったかを調べる
+///
+/// bから見たaの相対移動として考えて、軸ごとに重なっている時間帯を出し、
+/// 両軸の時間帯が0.0〜1.0の間で重なっていれば衝突とする。
+/// 大きさは動く前のものを使う。辺が接しているだけなら重なりとはしない
+pub fn swept_overlap(a0: &Aabb, a1: &Aabb, b0: &Aabb, b1: &Aabb) -> bool {
+    // bを止めて、aだけが動いたことにする
+    let dx = (a1.0 - a0.0) - (b1.0 - b0.0);
+    let dy = (a1.1 - a0.1) - (b1.1 - b0.1);
+
+    let x_range = slab(a0.0, a0.2, dx, b0.0, b0.2);
+    let y_range = slab(a0.1, a0.3, dy, b0.1, b0.3);
+
+    match (x_range, y_range) {
+        (Some((x_in, x_out)), Some((y_in, y_out))) => {
+            let t_in = x_in.max(y_in).max(0.0);
+            let t_out = x_out.min(y_out).min(1.0);
+            t_in < t_out
+        }
+        _ => false,
+    }
+}
+
+/// 内部用。一軸分について、位置aから速度dで動く長さa_lenの線分が、
+/// 止まっている線分(b, b_len)と重なっている時間帯を返す
+///
+/// 一度も重ならないならNone。動いていなくて重なりっぱなしなら、全時間帯
+fn slab(a: f32, a_len: f32, d: f32, b: f32, b_len: f32) -> Option<(f32, f32)> {
+    // aの左端がこの範囲にあれば重なっている
+    let (lo, hi) = (b - a_len, b + b_len);
+
+    if d == 0.0 {
+        return if lo < a && a < hi {
+            Some((::std::f32::NEG_INFINITY, ::std::f32::INFINITY))
+        } else {
+            None
+        };
+    }
+
+    let t0 = (lo - a) / d;
+    let t1 = (hi - a) / d;
+
+    Some((t0.min(t1), t0.max(t1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use etc;
+
+    /// マス目で絞り込んでから重なりを調べた、矩形`q`と重なる番号の一覧
+    fn grid_hits(grid: &SpatialGrid, rects: &[Aabb], q: &Aabb) -> BTreeSet<usize> {
+        let mut found = BTreeSet::new();
+        grid.query(q.0, q.1, q.2, q.3, |i| {
+            if overlaps(&rects[i], q) {
+                found.insert(i);
+            }
+            false
+        });
+        found
+    }
+
+    /// 総当たりで調べた、矩形`q`と重なる番号の一覧
+    fn brute_hits(rects: &[Aabb], q: &Aabb) -> BTreeSet<usize> {
+        (0..rects.len()).filter(|&i| overlaps(&rects[i], q)).collect()
+    }
+
+    fn overlaps(a: &Aabb, b: &Aabb) -> bool {
+        a.0 < b.0 + b.2 && b.0 < a.0 + a.2 && a.1 < b.1 + b.3 && b.1 < a.1 + a.3
+    }
+
+    #[test]
+    fn grid_finds_rect_straddling_cell_borders() {
+        // 四つのマスの角にまたがる矩形
+        let rects = vec![(60.0, 60.0, 10.0, 10.0)];
+        let mut grid = SpatialGrid::new(64.0, 0.0, 0.0, 256.0, 256.0);
+        grid.rebuild(rects.iter().cloned());
+
+        for q in &[(58.0, 58.0, 4.0, 4.0), (66.0, 58.0, 4.0, 4.0),
+                   (58.0, 66.0, 4.0, 4.0), (66.0, 66.0, 4.0, 4.0)] {
+            assert_eq!(grid_hits(&grid, &rects, q), brute_hits(&rects, q));
+            assert!(grid_hits(&grid, &rects, q).contains(&0));
+        }
+    }
+
+    #[test]
+    fn grid_finds_rects_outside_its_range() {
+        // 範囲の左上より外と、右下より外
+        let rects = vec![(-100.0, -50.0, 30.0, 30.0), (400.0, 300.0, 20.0, 20.0)];
+        let mut grid = SpatialGrid::new(64.0, 0.0, 0.0, 256.0, 256.0);
+        grid.rebuild(rects.iter().cloned());
+
+        let near_first = (-90.0, -40.0, 5.0, 5.0);
+        let near_second = (410.0, 310.0, 5.0, 5.0);
+        assert_eq!(grid_hits(&grid, &rects, &near_first), [0].iter().cloned().collect());
+        assert_eq!(grid_hits(&grid, &rects, &near_second), [1].iter().cloned().collect());
+    }
+
+    #[test]
+    fn grid_rebuild_drops_previous_contents() {
+        let mut grid = SpatialGrid::new(64.0, 0.0, 0.0, 256.0, 256.0);
+        let many: Vec<Aabb> = (0..20).map(|i| (i as f32 * 12.0, 100.0, 10.0, 10.0)).collect();
+        grid.rebuild(many.iter().cloned());
+
+        // 作り直した後は、前の番号が出てこない
+        let few = vec![(200.0, 200.0, 10.0, 10.0)];
+        grid.rebuild(few.iter().cloned());
+        let mut seen = Vec::new();
+        grid.query(0.0, 0.0, 256.0, 256.0, |i| {
+            seen.push(i);
+            false
+        });
+        assert_eq!(seen, vec![0]);
+
+        // 空で作り直せば、何も出てこない
+        grid.rebuild(Vec::<Aabb>::new().into_iter());
+        assert!(!grid.query(-1000.0, -1000.0, 3000.0, 3000.0, |_| true));
+    }
+
+    #[test]
+    fn grid_matches_brute_force() {
+        let mut rng = etc::seeded_rng(12);
+        let mut grid = SpatialGrid::new(DEFAULT_CELL_SIZE, 0.0, -64.0, 360.0, 608.0);
+        let mut rand = |max: u32| etc::random_x(&mut rng, max);
+
+        for _ in 0..20 {
+            // 範囲の外に100ピクセルはみ出すものまで混ぜる
+            let rects: Vec<Aabb> = (0..60)
+                .map(|_| (rand(560) - 100.0, rand(808) - 164.0, 4.0 + rand(60), 4.0 + rand(60)))
+                .collect();
+            grid.rebuild(rects.iter().cloned());
+
+            for _ in 0..30 {
+                let q = (rand(560) - 100.0, rand(808) - 164.0, 1.0 + rand(80), 1.0 + rand(80));
+                assert_eq!(grid_hits(&grid, &rects, &q), brute_hits(&rects, &q));
+            }
+        }
+    }
+
+    #[test]
+    fn swept_overlap_hits_thin_wall_passed_in_one_frame() {
+        // 一フレームで200ピクセル動いて、厚さ1の壁を丸ごと飛び越える
+        let a0 = (0.0, -100.0, 10.0, 10.0);
+        let a1 = (0.0, 100.0, 10.0, 10.0);
+        let wall = (-50.0, 0.0, 200.0, 1.0);
+
+        assert!(swept_overlap(&a0, &a1, &wall, &wall));
+        // 壁の方が動いても同じ
+        assert!(swept_overlap(&wall, &wall, &a0, &a1));
+    }
+
+    #[test]
+    fn swept_overlap_hits_when_both_move() {
+        // 向かい合ってすれ違う
+        let a0 = (0.0, 0.0, 4.0, 4.0);
+        let a1 = (100.0, 0.0, 4.0, 4.0);
+        let b0 = (100.0, 0.0, 4.0, 4.0);
+        let b1 = (0.0, 0.0, 4.0, 4.0);
+
+        assert!(swept_overlap(&a0, &a1, &b0, &b1));
+    }
+
+    #[test]
+    fn swept_overlap_misses_near_pass() {
+        // 壁の右端から1ピクセル外を通り過ぎる
+        let a0 = (151.0, -100.0, 10.0, 10.0);
+        let a1 = (151.0, 100.0, 10.0, 10.0);
+        let wall = (-50.0, 0.0, 200.0, 1.0);
+
+        assert!(!swept_overlap(&a0, &a1, &wall, &wall));
+    }
+
+    #[test]
+    fn swept_overlap_ignores_touching_edges() {
+        // 壁の右端に辺を接したまま通り過ぎる
+        let a0 = (150.0, -100.0, 10.0, 10.0);
+        let a1 = (150.0, 100.0, 10.0, 10.0);
+        let wall = (-50.0, 0.0, 200.0, 1.0);
+
+        assert!(!swept_overlap(&a0, &a1, &wall, &wall));
+    }
+
+    #[test]
+    fn swept_overlap_misses_when_stopping_short() {
+        // 壁の手前で止まる
+        let a0 = (0.0, -100.0, 10.0, 10.0);
+        let a1 = (0.0, -20.0, 10.0, 10.0);
+        let wall = (-50.0, 0.0, 200.0, 1.0);
+
+        assert!(!swept_overlap(&a0, &a1, &wall, &wall));
+    }
+}
@@ -3,65 +3,455 @@
 
   config.tomlなどの設定ファイルから、
   他で扱うデータを運び出す
+
+  設定は次の順に重ねて、後のものほど優先する。
+  ファイルに書かれていない項目は、前の段の値がそのまま残る
+    1. 組み込みの初期値（各structのDefault）
+    2. ゲームと同じフォルダのgame_config.toml（"--config"で差し替え可）
+    3. ユーザー設定フォルダのgame_config.toml
+    4. 起動引数（args.rsのArgs::apply_to()）
   
   * GameConf impl
-    * new()           : tomlから読み込んだ内容のうち、重要なものを環境変数に登録
-    * toml_serde()    : tomlからデータを読み込む
+    * new()           : 設定ファイルを重ねて読み込む。ファイルがなければ初期値
+    * from_file()     : new()と同じだが、指定したファイルがなければエラー
+    * with_layers()   : 重ねるファイルの一覧を作って、load()を呼ぶ
+    * load()          : 指定したファイルを順に重ねて読み込む
+    * validate()      : 中身に間違いがないかを調べる。詳しくはconf_check.rs
+    * user_conf_path(): ユーザー設定フォルダのgame_config.toml
+    * to_toml_string(): 今の設定をtoml形式の文字列にする
+
+  * merge_toml(): tomlの表を、中身まで再帰的に重ねる
 
   * toml deserialize用のstruct
     * GameOption
+    * Window
+    * Hud
+    * Controls
     * Assets
     * Translate
 
 -------------------------------*/
 //use std;
-use std::io::Result;
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+use std::path::{ Path, PathBuf };
+
+use app_dirs::{ self, AppDataType, AppInfo };
 
+use conf_check;
+use difficulty::Difficulty;
+use error::{ Error, Result };
 use etc;
+use game_state::DEFAULT_MAX_ENEMIES;
 use toml;
 
-#[derive(Debug, Deserialize)]
+/// ggezに渡すゲームID。ユーザー設定フォルダの名前にもなる
+pub const GAME_ID: &'static str = "dodge_rock";
+/// ggezに渡す作者名
+pub const GAME_AUTHOR: &'static str = "dettalant";
+/// 設定ファイルの名前
+pub const CONF_FILE: &'static str = "game_config.toml";
+
+/// ゲーム画面の最小横サイズ
+pub const MIN_WINDOW_W: u32 = 240;
+/// ゲーム画面の最小縦サイズ
+pub const MIN_WINDOW_H: u32 = 320;
+
+/// HUDの表示位置として書けるもの
+pub const HUD_POSITIONS: [&'static str; 4] = [
+    "top_left",
+    "top_right",
+    "bottom_left",
+    "bottom_right",
+];
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct GameConf {
    pub game_option: GameOption,
+   pub window: Window,
+   pub hud: Hud,
+   /// 未指定なら、今までどおりの割り当てを使う
+   pub controls: Controls,
    pub assets: Assets,
    pub translate: Translate,
+   /// 実際に読み込んだ設定ファイル。重ねた順に並ぶ
+   #[serde(skip)]
+   pub sources: Vec<PathBuf>,
+}
+
+impl Default for GameConf {
+    fn default() -> Self {
+        GameConf {
+            game_option: GameOption::default(),
+            window: Window::default(),
+            hud: Hud::default(),
+            controls: Controls::default(),
+            assets: Assets::default(),
+            translate: Translate::default(),
+            sources: Vec::new(),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct GameOption {
    pub constant_fps: u32,
+   /// 敵出現用の乱数シード値。未指定ならゲームごとにランダム
+   pub seed: Option<u64>,
+   /// 起動時の難易度。easy, normal, hard, lunaticのどれか
+   pub difficulty: String,
+   /// 同時に出せる岩の数の上限
+   pub max_enemies: u32,
 }
 
-#[derive(Debug, Deserialize)]
+impl Default for GameOption {
+    fn default() -> Self {
+        GameOption {
+            constant_fps: 60,
+            seed: None,
+            difficulty: Difficulty::default().name().to_string(),
+            max_enemies: DEFAULT_MAX_ENEMIES as u32,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+/// ウィンドウ設定
+///
+/// width・heightはゲーム画面（論理解像度）の大きさでもある
+pub struct Window {
+   /// ゲーム画面の横サイズ
+   pub width: u32,
+   /// ゲーム画面の縦サイズ
+   pub height: u32,
+   /// フルスクリーンで起動するか
+   pub fullscreen: bool,
+   /// 垂直同期を使うか
+   pub vsync: bool,
+   /// ウィンドウタイトル
+   pub title: String,
+   /// 拡大表示を整数倍に限るか。ドット絵がにじまないように
+   pub integer_scale: bool,
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Window {
+            width: 360,
+            height: 480,
+            fullscreen: false,
+            vsync: true,
+            title: "Dodge Rock Game".to_string(),
+            integer_scale: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+/// プレイ中に表示するHUD（経過時間・岩の速さ・自己ベスト）の設定
+pub struct Hud {
+   /// HUDを表示するか
+   pub visible: bool,
+   /// 表示位置。HUD_POSITIONSのどれか
+   pub position: String,
+   /// 経過時間を表示するか
+   pub show_time: bool,
+   /// 岩の速さを表示するか
+   pub show_speed: bool,
+   /// 自己ベストを表示するか
+   pub show_best: bool,
+}
+
+impl Default for Hud {
+    fn default() -> Self {
+        Hud {
+            visible: true,
+            position: "top_left".to_string(),
+            show_time: true,
+            show_speed: true,
+            show_best: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+/// キー・ボタン割り当て
+///
+/// 操作名をキーに、割り当てるキー名・ボタン名の配列を値に持つ
+/// 中身の解釈はcontrols.rsを参照のこと
+pub struct Controls {
+   pub keyboard: BTreeMap<String, Vec<String>>,
+   pub gamepad: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for Controls {
+    /// 設定ファイルに[controls]がない時の割り当て
+    fn default() -> Self {
+        let keyboard = [
+            ("move_up", vec!["Up", "W", "K"]),
+            ("move_down", vec!["Down", "S", "J"]),
+            ("move_left", vec!["Left", "A", "H"]),
+            ("move_right", vec!["Right", "D", "L"]),
+            ("speed_down", vec!["Left Shift", "Right Shift"]),
+            ("restart", vec!["R"]),
+            ("title", vec!["T"]),
+            ("quit", vec!["Q"]),
+            ("pause", vec!["Escape", "P"]),
+            ("debug", vec!["M"]),
+        ];
+        
+        let gamepad = [
+            ("move_up", vec!["DPadUp"]),
+            ("move_down", vec!["DPadDown"]),
+            ("move_left", vec!["DPadLeft"]),
+            ("move_right", vec!["DPadRight"]),
+            ("speed_down", vec!["LeftShoulder", "RightShoulder"]),
+            ("pause", vec!["Start"]),
+        ];
+        
+        Controls {
+            keyboard: to_binding_map(&keyboard),
+            gamepad: to_binding_map(&gamepad),
+        }
+    }
+}
+
+/// 内部用。割り当ての初期値を、設定ファイルと同じ形に直す
+fn to_binding_map(src: &[(&str, Vec<&str>)]) -> BTreeMap<String, Vec<String>> {
+    src.iter()
+        .map(|&(action, ref keys)| {
+            (action.to_string(), keys.iter().map(|k| k.to_string()).collect())
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Assets {
    pub assets_dir: String,
 }
 
-#[derive(Debug, Deserialize)]
+impl Default for Assets {
+    fn default() -> Self {
+        Assets {
+            assets_dir: "assets".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Translate {
    pub is_translate: bool,
    pub translate_data_dir: String,
 }
 
+impl Default for Translate {
+    fn default() -> Self {
+        Translate {
+            is_translate: false,
+            translate_data_dir: "tl/english".to_string(),
+        }
+    }
+}
+
 impl GameConf {
-    /// tomlからデータを読み込み、重要なものを環境変数に登録
+    /// 設定ファイルを重ねて読み込む
     ///
-    /// 環境変数の呼び出しコードは、`src/conf.rs`冒頭コメントを参照のこと
+    /// `path_str`（普段はgame_config.toml）と、ユーザー設定フォルダの
+    /// game_config.tomlを、この順に組み込みの初期値へ重ねる。
+    /// どちらのファイルもなければ、初期値のまま動く
     ///
-    /// `GameConf::new("game_option.toml");` というふうに使う
+    /// `GameConf::new("game_config.toml");` というふうに使う
     pub fn new<'a>(path_str: &'a str) -> Result<Self> {
-        let conf_path = etc::easy_path_set(path_str);
-        let game_conf = GameConf::toml_serde(&conf_path)?;
+        GameConf::with_layers(path_str, false)
+    }
+    
+    /// new()と同じだが、`path_str`のファイルがなければエラーにする
+    ///
+    /// "--config"で指定された時用
+    pub fn from_file<'a>(path_str: &'a str) -> Result<Self> {
+        GameConf::with_layers(path_str, true)
+    }
+    
+    /// 内部用。重ねるファイルの一覧を作って、load()を呼ぶ
+    ///
+    /// ユーザー設定フォルダのファイルは、なければ飛ばす
+    fn with_layers<'a>(path_str: &'a str, required: bool) -> Result<Self> {
+        let mut layers = Vec::new();
+        
+        let base_path = etc::easy_path_set(path_str);
+        if required || base_path.is_file() {
+            layers.push(base_path);
+        }
+        
+        if let Some(user_path) = GameConf::user_conf_path() {
+            if user_path.is_file() {
+                layers.push(user_path);
+            }
+        }
+        
+        GameConf::load(&layers)
+    }
+    
+    /// 組み込みの初期値に、指定したファイルを順に重ねて読み込む
+    ///
+    /// ここではファイルがなければエラーにする。
+    /// 書き方の間違いと、重ねた後の中身の間違いを、
+    /// 全部まとめてError::Invalidで返す
+    pub fn load<'a>(paths: &'a [PathBuf]) -> Result<Self> {
+        // 初期値を一旦tomlの表にして、そこへファイルの中身を重ねていく
+        let mut merged = toml::Value::try_from(GameConf::default())
+            .map_err(|e| Error::config(Path::new(CONF_FILE), e))?;
+        let mut problems = Vec::new();
+        
+        for path in paths {
+            let text = etc::File::read_to_string(path).map_err(|e| Error::io(path, e))?;
+            let (value, mut found) = conf_check::check_file(path, &text);
+            problems.append(&mut found);
+            
+            if let Some(v) = value {
+                merge_toml(&mut merged, v);
+            }
+        }
+        
+        // 問題のあった項目は取り除かれているので、ここで失敗することはまずない
+        let blame = paths.last().map(|p| p.as_path()).unwrap_or(Path::new(CONF_FILE));
+        
+        let mut game_conf: GameConf = merged.try_into()
+            .map_err(|e| Error::config(blame, e))?;
+        game_conf.sources = paths.to_vec();
+        
+        // 中身の問題も続けて調べて、書き方の問題と一緒に返す
+        problems.append(&mut conf_check::check_semantics(&game_conf));
+        
+        if !problems.is_empty() {
+            return Err(Error::Invalid(problems));
+        }
 
         Ok(game_conf)
     }
     
-    /// 内部用。tomlファイルを読み込んで、解析する関数を呼び出す
-    fn toml_serde<'a>(path: &'a Path) -> Result<Self> {
-        // 効率化のためにVec<u8>で受け取るようにするか
-        let tmp_vec = etc::File::read_to_vec(path)?;
-        let out_data = toml::de::from_slice(&tmp_vec).expect("Toml deserialize 時のエラー");
-        Ok(out_data)
+    /// 設定の中身に間違いがないかを調べる
+    ///
+    /// 見つかった問題は全部まとめてError::Invalidで返す。
+    /// 起動引数で上書きした後にも呼ぶ
+    pub fn validate(&self) -> Result<()> {
+        let problems = conf_check::check_semantics(self);
+        
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Invalid(problems))
+        }
+    }
+    
+    /// ユーザー設定フォルダのgame_config.toml
+    ///
+    /// 場所はggezと同じ決め方（Linuxなら~/.config/dodge_rock/）。
+    /// 場所を調べるだけで、フォルダは作らない。調べられなければNone
+    pub fn user_conf_path() -> Option<PathBuf> {
+        let app_info = AppInfo { name: GAME_ID, author: GAME_AUTHOR };
+        app_dirs::get_app_root(AppDataType::UserConfig, &app_info)
+            .ok()
+            .map(|dir| dir.join(CONF_FILE))
+    }
+    
+    /// 今の設定を、設定ファイルと同じtoml形式の文字列にする
+    ///
+    /// "--print-config"で、重ねた結果を確かめる時に使う
+    pub fn to_toml_string(&self) -> Result<String> {
+        // 表より先に値を書かないといけないので、一旦Valueを通して並べ替える
+        let blame = self.sources.last().map(|p| p.as_path()).unwrap_or(Path::new(CONF_FILE));
+        toml::Value::try_from(self)
+            .map(|v| v.to_string())
+            .map_err(|e| Error::config(blame, e))
+    }
+}
+
+/// tomlの表`over`を、`base`に重ねる
+///
+/// 表同士なら中身ごとに再帰的に重ねて、それ以外（配列も含む）は丸ごと置き換える
+pub fn merge_toml(base: &mut toml::Value, over: toml::Value) {
+    match (base, over) {
+        (&mut toml::Value::Table(ref mut base_t), toml::Value::Table(over_t)) => {
+            for (k, v) in over_t {
+                match base_t.entry(k) {
+                    Entry::Occupied(mut e) => merge_toml(e.get_mut(), v),
+                    Entry::Vacant(e) => {
+                        e.insert(v);
+                    }
+                }
+            }
+        }
+        (base, over) => *base = over,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn temp_conf<'a>(name: &'a str, body: &'a str) -> PathBuf {
+        let path = env::temp_dir()
+            .join(format!("dodge_rock_test_{}_{}.toml", name, ::std::process::id()));
+        fs::write(&path, body).unwrap();
+        path
+    }
+
+    #[test]
+    fn later_layers_win_and_unset_keys_keep_defaults() {
+        let first = temp_conf("conf_first", "[window]\nwidth = 400\nheight = 500\n");
+        let second = temp_conf("conf_second", "[window]\nwidth = 420\n");
+
+        let conf = GameConf::load(&[first.clone(), second.clone()]).unwrap();
+        // 後のファイルが勝ち、書いていない項目は前の段の値が残る
+        assert_eq!(conf.window.width, 420);
+        assert_eq!(conf.window.height, 500);
+        assert_eq!(conf.game_option.constant_fps, 60);
+        assert_eq!(conf.sources, vec![first.clone(), second.clone()]);
+
+        fs::remove_file(&first).unwrap();
+        fs::remove_file(&second).unwrap();
+    }
+
+    #[test]
+    fn load_reports_missing_file_as_error() {
+        let path = env::temp_dir()
+            .join(format!("dodge_rock_test_conf_missing_{}.toml", ::std::process::id()));
+        assert!(GameConf::load(&[path]).is_err());
+    }
+
+    #[test]
+    fn to_toml_string_reads_back_as_the_same_conf() {
+        let mut conf = GameConf::default();
+        conf.window.width = 480;
+        conf.game_option.seed = Some(u32::max_value() as u64);
+
+        let path = temp_conf("conf_print", &conf.to_toml_string().unwrap());
+        let read = GameConf::load(&[path.clone()]).unwrap();
+        assert_eq!(read.window.width, 480);
+        assert_eq!(read.game_option.seed, Some(u32::max_value() as u64));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn user_conf_path_does_not_create_the_folder() {
+        // 場所を調べるだけなら、フォルダは増えも減りもしない
+        if let Some(path) = GameConf::user_conf_path() {
+            let dir = path.parent().unwrap().to_path_buf();
+            let existed = dir.exists();
+            let _ = GameConf::user_conf_path();
+            assert_eq!(dir.exists(), existed);
+            assert!(path.ends_with(CONF_FILE));
+        }
     }
 }
@@ -0,0 +1,674 @@
+/*-------------------------------
+            conf_check.rs
+
+  設定ファイルの中身を調べて、見つかった問題を全部まとめて報告する
+
+  一つ見つけたら止めるのではなく、最後まで調べてから返す。
+  問題ごとに、ファイルの何行目・何文字目かを添える。
+  toml::Valueは位置を覚えていないので、位置はファイルの文字列から探し直す
+
+  調べる内容は二段階:
+    1. 書き方（check_file()）: tomlの文法、知らない項目、値の型、キー名・ボタン名
+       ファイルごとに、重ねる前に調べる
+    2. 中身（check_semantics()）: 固定FPSが0、フォルダが存在しない、HUDの表示位置、キーの重複など
+       全部重ねて、起動引数も反映した後に調べる
+
+  * struct Diagnostic: 問題一つ分。場所とメッセージ
+  * enum Kind       : 項目の値の種類
+  * SCHEMA          : 設定ファイルに書ける項目の一覧
+
+  * impl Diagnostic:
+    * new(): 作る
+
+  * check_file()     : ファイル一つ分の書き方を調べる
+  * check_semantics(): 重ねた後の設定の中身を調べる
+  * check_table()    : 表の中の項目を一つずつ調べる
+  * check_value()    : 値の種類を調べる
+  * check_bindings() : キー・ボタン割り当ての表を調べる
+  * locate()         : 項目が書かれている行と桁を探す
+  * locate_in_sources(): 読み込んだファイルを後ろから順に探す
+  * header_path()    : "[controls.keyboard]"を["controls", "keyboard"]にする
+  * key_of_line()    : "width = 360"から"width"を取り出す
+  * similar()        : 打ち間違いらしい時に、近い名前を探す
+  * edit_distance()  : 二つの文字列の編集距離
+-------------------------------*/
+use std::fmt;
+use std::path::{ Path, PathBuf };
+
+use ggez::event::Keycode;
+use toml::{ self, Value };
+use toml::value::Table;
+
+use conf::{ GameConf, HUD_POSITIONS, MIN_WINDOW_H, MIN_WINDOW_W };
+use controls::{ self, Action, Bindings };
+use difficulty::Difficulty;
+use etc;
+
+#[derive(Clone, Debug, PartialEq)]
+/// 見つかった問題一つ分
+pub struct Diagnostic {
+    /// 問題のあるファイル。初期値や起動引数が原因ならNone
+    pub path: Option<PathBuf>,
+    /// 行と桁（どちらも1始まり）。分からなければNone
+    pub pos: Option<(usize, usize)>,
+    pub msg: String,
+}
+
+impl Diagnostic {
+    pub fn new(path: Option<PathBuf>, pos: Option<(usize, usize)>, msg: String) -> Self {
+        Diagnostic {
+            path: path,
+            pos: pos,
+            msg: msg,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    /// "game_config.toml:12:1: メッセージ" の形。コンパイラのエラー表示と同じ
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.path, self.pos) {
+            (&Some(ref path), Some((line, col))) => {
+                write!(f, "{}:{}:{}: {}", path.display(), line, col, self.msg)
+            }
+            (&Some(ref path), None) => write!(f, "{}: {}", path.display(), self.msg),
+            (&None, _) => write!(f, "(初期値または起動引数): {}", self.msg),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+/// 項目の値の種類
+pub enum Kind {
+    /// 0以上の整数（u32に収まるもの）
+    U32,
+    /// 0以上の整数（u64に収まるもの）
+    U64,
+    Bool,
+    Str,
+    /// 中に項目を持つ表
+    Table(&'static [(&'static str, Kind)]),
+    /// 操作名 = [キー名, ...] の表
+    KeyMap,
+    /// 操作名 = [ボタン名, ...] の表
+    ButtonMap,
+}
+
+impl Kind {
+    /// エラー表示用の、種類の名前
+    fn describe(&self) -> &'static str {
+        match *self {
+            Kind::U32 | Kind::U64 => "0以上の整数",
+            Kind::Bool => "trueかfalse",
+            Kind::Str => "文字列",
+            Kind::Table(_) => "表",
+            Kind::KeyMap | Kind::ButtonMap => "表（操作名 = [名前, ...]）",
+        }
+    }
+}
+
+const GAME_OPTION: &'static [(&'static str, Kind)] = &[
+    ("constant_fps", Kind::U32),
+    ("seed", Kind::U64),
+    ("difficulty", Kind::Str),
+    ("max_enemies", Kind::U32),
+];
+
+const WINDOW: &'static [(&'static str, Kind)] = &[
+    ("width", Kind::U32),
+    ("height", Kind::U32),
+    ("fullscreen", Kind::Bool),
+    ("vsync", Kind::Bool),
+    ("title", Kind::Str),
+    ("integer_scale", Kind::Bool),
+];
+
+const HUD: &'static [(&'static str, Kind)] = &[
+    ("visible", Kind::Bool),
+    ("position", Kind::Str),
+    ("show_time", Kind::Bool),
+    ("show_speed", Kind::Bool),
+    ("show_best", Kind::Bool),
+];
+
+const CONTROLS: &'static [(&'static str, Kind)] = &[
+    ("keyboard", Kind::KeyMap),
+    ("gamepad", Kind::ButtonMap),
+];
+
+const ASSETS: &'static [(&'static str, Kind)] = &[
+    ("assets_dir", Kind::Str),
+];
+
+const TRANSLATE: &'static [(&'static str, Kind)] = &[
+    ("is_translate", Kind::Bool),
+    ("translate_data_dir", Kind::Str),
+];
+
+/// 設定ファイルに書ける項目の一覧。conf.rsのstructと揃えること
+pub const SCHEMA: &'static [(&'static str, Kind)] = &[
+    ("game_option", Kind::Table(GAME_OPTION)),
+    ("window", Kind::Table(WINDOW)),
+    ("hud", Kind::Table(HUD)),
+    ("controls", Kind::Table(CONTROLS)),
+    ("assets", Kind::Table(ASSETS)),
+    ("translate", Kind::Table(TRANSLATE)),
+];
+
+/// 内部用。調べている最中のファイル
+struct Source<'a> {
+    path: &'a Path,
+    text: &'a str,
+}
+
+impl<'a> Source<'a> {
+    /// 表`at`の項目`key`についての問題を作る。位置はファイルから探す
+    fn diag(&self, at: &[String], key: Option<&str>, msg: String) -> Diagnostic {
+        Diagnostic::new(Some(self.path.to_path_buf()), locate(self.text, at, key), msg)
+    }
+}
+
+/// 設定ファイル一つ分の書き方を調べる
+///
+/// 文法が正しければ、重ねるための中身も返す。
+/// 知らない項目や型違いのあった項目は、中身から取り除いておく。
+/// 問題はファイルの上から順に並べる
+pub fn check_file<'a>(path: &'a Path, text: &'a str) -> (Option<Value>, Vec<Diagnostic>) {
+    let mut out = Vec::new();
+
+    let mut value: Value = match toml::de::from_str(text) {
+        Ok(v) => v,
+        Err(e) => {
+            // 位置は別に出すので、メッセージ末尾の" at line N"は落とす
+            let mut msg = e.to_string();
+            if let Some(i) = msg.rfind(" at line ") {
+                msg.truncate(i);
+            }
+            let pos = e.line_col().map(|(l, c)| (l + 1, c + 1));
+            out.push(Diagnostic::new(Some(path.to_path_buf()), pos,
+                                     format!("tomlの書き方が正しくありません - {}", msg)));
+            return (None, out);
+        }
+    };
+
+    let src = Source { path: path, text: text };
+
+    if let Value::Table(ref mut t) = value {
+        check_table(&src, &[], t, SCHEMA, &mut out);
+    }
+
+    out.sort_by_key(|d| d.pos);
+
+    (Some(value), out)
+}
+
+/// 重ねた後の設定の中身を調べる
+///
+/// 位置は、読み込んだファイルのうち最後にその項目を書いたものから探す
+pub fn check_semantics(conf: &GameConf) -> Vec<Diagnostic> {
+    let mut out = Vec::new();
+
+    // 位置を探すために、ファイルの中身を読み直しておく
+    let texts: Vec<(PathBuf, String)> = conf.sources.iter()
+        .filter_map(|p| etc::File::read_to_string(p).ok().map(|t| (p.clone(), t)))
+        .collect();
+
+    let mut push = |table: &[&str], key: Option<&str>, msg: String| {
+        let at: Vec<String> = table.iter().map(|s| s.to_string()).collect();
+        let (path, pos) = locate_in_sources(&texts, &at, key);
+        out.push(Diagnostic::new(path, pos, msg));
+    };
+
+    if conf.game_option.constant_fps == 0 {
+        push(&["game_option"], Some("constant_fps"),
+             "[game_option] constant_fpsは1以上にしてください".to_string());
+    }
+
+    if conf.game_option.max_enemies == 0 {
+        push(&["game_option"], Some("max_enemies"),
+             "[game_option] max_enemiesは1以上にしてください".to_string());
+    }
+
+    if Difficulty::from_name(&conf.game_option.difficulty).is_none() {
+        let names: Vec<&str> = Difficulty::all().iter().map(|d| d.name()).collect();
+        let hint = match similar(&conf.game_option.difficulty, &names) {
+            Some(name) => format!("もしかして: {}", name),
+            None => format!("書けるもの: {}", names.join(", ")),
+        };
+        push(&["game_option"], Some("difficulty"), format!(
+            "[game_option] difficultyに\"{}\"は使えません（{}）",
+            conf.game_option.difficulty, hint));
+    }
+
+    if conf.window.width < MIN_WINDOW_W {
+        push(&["window"], Some("width"), format!(
+            "[window] widthは{}以上にしてください（指定: {}）",
+            MIN_WINDOW_W, conf.window.width));
+    }
+
+    if conf.window.height < MIN_WINDOW_H {
+        push(&["window"], Some("height"), format!(
+            "[window] heightは{}以上にしてください（指定: {}）",
+            MIN_WINDOW_H, conf.window.height));
+    }
+
+    if !HUD_POSITIONS.contains(&conf.hud.position.as_str()) {
+        let hint = match similar(&conf.hud.position, &HUD_POSITIONS) {
+            Some(name) => format!("もしかして: {}", name),
+            None => format!("書けるもの: {}", HUD_POSITIONS.join(", ")),
+        };
+        push(&["hud"], Some("position"), format!(
+            "[hud] positionに\"{}\"は使えません（{}）",
+            conf.hud.position, hint));
+    }
+
+    if !etc::easy_path_set(&conf.assets.assets_dir).is_dir() {
+        push(&["assets"], Some("assets_dir"), format!(
+            "[assets] assets_dirのフォルダ\"{}\"が存在しません",
+            conf.assets.assets_dir));
+    }
+
+    if conf.translate.is_translate
+        && !etc::easy_path_set(&conf.translate.translate_data_dir).is_dir() {
+        push(&["translate"], Some("translate_data_dir"), format!(
+            "[translate] translate_data_dirのフォルダ\"{}\"が存在しません",
+            conf.translate.translate_data_dir));
+    }
+
+    // キー名・ボタン名はファイルごとに調べ済みなので、ここで出るのは重複と割り当て漏れ
+    if let Err(errors) = Bindings::new(&conf.controls) {
+        for e in errors {
+            push(&["controls", e.section], None, e.to_string());
+        }
+    }
+
+    out
+}
+
+/// 表の中の項目を、一覧`fields`と照らし合わせて一つずつ調べる
+///
+/// 問題のあった項目は表から取り除く。
+/// そうしておけば、残りを重ねて中身の方も続けて調べられる
+fn check_table(src: &Source, at: &[String], table: &mut Table,
+               fields: &'static [(&'static str, Kind)], out: &mut Vec<Diagnostic>) {
+    let mut bad_keys = Vec::new();
+
+    for (key, value) in table.iter_mut() {
+        let ok = match fields.iter().find(|&&(name, _)| name == key) {
+            Some(&(_, kind)) => check_value(src, at, key, value, kind, out),
+            None => {
+                let names: Vec<&str> = fields.iter().map(|&(name, _)| name).collect();
+                let section = if at.is_empty() {
+                    String::new()
+                } else {
+                    format!("[{}] ", at.join("."))
+                };
+                let hint = match similar(key, &names) {
+                    Some(name) => format!("もしかして: {}", name),
+                    None => format!("書ける項目: {}", names.join(", ")),
+                };
+                out.push(src.diag(at, Some(key), format!(
+                    "{}知らない項目\"{}\"があります（{}）", section, key, hint)));
+                false
+            }
+        };
+
+        if !ok {
+            bad_keys.push(key.clone());
+        }
+    }
+
+    for key in bad_keys {
+        table.remove(&key);
+    }
+}
+
+/// 項目`at.key`の値が、種類`kind`に合っているかを調べる
+///
+/// 値ごと取り除くべきならfalse。表の中身の問題は、表の中で取り除く
+fn check_value(src: &Source, at: &[String], key: &str, value: &mut Value,
+               kind: Kind, out: &mut Vec<Diagnostic>) -> bool {
+    let mut sub = at.to_vec();
+    sub.push(key.to_string());
+
+    match (kind, value) {
+        (Kind::U32, &mut Value::Integer(n)) if n >= 0 && n <= ::std::u32::MAX as i64 => true,
+        (Kind::U64, &mut Value::Integer(n)) if n >= 0 => true,
+        (Kind::Bool, &mut Value::Boolean(_)) => true,
+        (Kind::Str, &mut Value::String(_)) => true,
+        (Kind::Table(fields), &mut Value::Table(ref mut t)) => {
+            check_table(src, &sub, t, fields, out);
+            true
+        }
+        (Kind::KeyMap, &mut Value::Table(ref mut t)) => {
+            check_bindings(src, &sub, t, true, out);
+            true
+        }
+        (Kind::ButtonMap, &mut Value::Table(ref mut t)) => {
+            check_bindings(src, &sub, t, false, out);
+            true
+        }
+        (kind, value) => {
+            out.push(src.diag(at, Some(key), format!(
+                "[{}] {}は{}にしてください（指定: {}）",
+                at.join("."), key, kind.describe(), value)));
+            false
+        }
+    }
+}
+
+/// キー・ボタン割り当ての表を調べる。`is_keyboard`がfalseならゲームパッド
+///
+/// 操作名とキー名・ボタン名が正しいかだけを見る。重複はcheck_semantics()で。
+/// 問題のあった操作は、割り当てごと表から取り除く
+fn check_bindings(src: &Source, at: &[String], table: &mut Table,
+                  is_keyboard: bool, out: &mut Vec<Diagnostic>) {
+    let section = at.join(".");
+    let action_names: Vec<&str> = Action::all().iter().map(|a| a.name()).collect();
+    let mut bad_keys = Vec::new();
+
+    for (action_name, value) in table.iter() {
+        if Action::from_name(action_name).is_none() {
+            let hint = match similar(action_name, &action_names) {
+                Some(name) => format!("もしかして: {}", name),
+                None => format!("書ける操作: {}", action_names.join(", ")),
+            };
+            out.push(src.diag(at, Some(action_name), format!(
+                "[{}] \"{}\"という操作はありません（{}）", section, action_name, hint)));
+            bad_keys.push(action_name.clone());
+            continue;
+        }
+
+        let names = match *value {
+            Value::Array(ref arr) => arr,
+            _ => {
+                out.push(src.diag(at, Some(action_name), format!(
+                    "[{}] {}は名前の配列にしてください（指定: {}）",
+                    section, action_name, value)));
+                bad_keys.push(action_name.clone());
+                continue;
+            }
+        };
+
+        for name in names {
+            let known = match *name {
+                Value::String(ref s) if is_keyboard => Keycode::from_name(s).is_some(),
+                Value::String(ref s) => controls::button_from_name(s).is_some(),
+                _ => false,
+            };
+
+            if !known {
+                let what = if is_keyboard { "キー" } else { "ボタン" };
+                let shown = match *name {
+                    Value::String(ref s) => s.clone(),
+                    ref other => other.to_string(),
+                };
+                out.push(src.diag(at, Some(action_name), format!(
+                    "[{}] {}: \"{}\"という{}はありません",
+                    section, action_name, shown, what)));
+                bad_keys.push(action_name.clone());
+            }
+        }
+    }
+
+    for key in bad_keys {
+        table.remove(&key);
+    }
+}
+
+/// 表`at`の項目`key`が書かれている行と桁を、ファイルの文字列から探す
+///
+/// `key`がNoneなら、表の見出し（"[window]"など）の位置。
+/// 見つからなければNone
+pub fn locate(text: &str, at: &[String], key: Option<&str>) -> Option<(usize, usize)> {
+    let mut current: Vec<String> = Vec::new();
+    // 項目が見つからなかった時用に、表の見出しの位置も覚えておく
+    let mut header_pos = None;
+
+    for (i, line) in text.lines().enumerate() {
+        let trimmed = line.trim_start();
+        let col = line.chars().count() - trimmed.chars().count() + 1;
+
+        if trimmed.starts_with('[') {
+            current = header_path(trimmed);
+
+            if current.as_slice() == at && header_pos.is_none() {
+                header_pos = Some((i + 1, col));
+                if key.is_none() {
+                    return header_pos;
+                }
+            }
+
+            // "[window]"自体が知らない項目の時など、見出しそのものが探し物
+            if let Some(k) = key {
+                if current.len() == at.len() + 1
+                    && &current[..at.len()] == at
+                    && current[at.len()] == k {
+                    return Some((i + 1, col));
+                }
+            }
+            continue;
+        }
+
+        if let Some(k) = key {
+            if current.as_slice() == at && key_of_line(trimmed) == Some(k) {
+                return Some((i + 1, col));
+            }
+        }
+    }
+
+    header_pos
+}
+
+/// 読み込んだファイルを後ろ（優先度の高い方）から順に探して、
+/// 項目が書かれているファイルと位置を返す
+fn locate_in_sources(texts: &[(PathBuf, String)], at: &[String],
+                     key: Option<&str>) -> (Option<PathBuf>, Option<(usize, usize)>) {
+    for &(ref path, ref text) in texts.iter().rev() {
+        // 見出しだけ見つかった場合は、その項目を書いたファイルではないので続ける
+        let pos = locate(text, at, key);
+        let exact = match (key, pos) {
+            (Some(_), Some(p)) => locate(text, at, None) != Some(p),
+            (None, Some(_)) => true,
+            _ => false,
+        };
+
+        if exact {
+            return (Some(path.clone()), pos);
+        }
+    }
+
+    (None, None)
+}
+
+/// "[controls.keyboard]"や"[[a.b]]"を、["controls", "keyboard"]にする
+fn header_path(line: &str) -> Vec<String> {
+    let inner = line.trim_matches(|c| c == '[' || c == ' ');
+    let inner = match inner.find(']') {
+        Some(i) => &inner[..i],
+        None => inner,
+    };
+
+    inner.split('.')
+        .map(|s| s.trim().trim_matches('"').to_string())
+        .collect()
+}
+
+/// "width = 360"から"width"を取り出す。項目の行でなければNone
+fn key_of_line(line: &str) -> Option<&str> {
+    let (key, rest) = if line.starts_with('"') {
+        let end = line[1..].find('"')? + 1;
+        (&line[1..end], &line[end + 1..])
+    } else {
+        let end = line.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(line.len());
+        (&line[..end], &line[end..])
+    };
+
+    if !key.is_empty() && rest.trim_start().starts_with('=') {
+        Some(key)
+    } else {
+        None
+    }
+}
+
+/// `name`が打ち間違いらしい時に、`candidates`の中から近い名前を探す
+fn similar<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    candidates.iter()
+        .map(|&c| (edit_distance(&name.to_lowercase(), c), c))
+        .filter(|&(d, c)| d <= 2 && d < c.len())
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, c)| c)
+}
+
+/// 二つの文字列の編集距離（レーベンシュタイン距離）
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..b.len() + 1).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for j in 0..b.len() {
+            let cost = if ca == b[j] { 0 } else { 1 };
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use super::*;
+    use error::Error;
+
+    /// check_file()で調べて、見つかった問題を返す
+    fn problems(text: &str) -> Vec<Diagnostic> {
+        check_file(Path::new("test.toml"), text).1
+    }
+
+    /// 一時ファイルに書いてload()し、Error::Invalidの中身を返す
+    fn load_problems<'a>(name: &'a str, text: &'a str) -> (PathBuf, Vec<Diagnostic>) {
+        let path = env::temp_dir()
+            .join(format!("dodge_rock_test_{}_{}.toml", name, ::std::process::id()));
+        fs::write(&path, text).unwrap();
+        let result = GameConf::load(&[path.clone()]);
+        let _ = fs::remove_file(&path);
+
+        match result {
+            Err(Error::Invalid(found)) => (path, found),
+            other => panic!("Error::Invalidになっていない: {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn unknown_key_is_located_with_suggestion() {
+        let found = problems("[window]\nwidth = 360\n  heigth = 480\n");
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path, Some(PathBuf::from("test.toml")));
+        assert_eq!(found[0].pos, Some((3, 3)));
+        assert!(found[0].msg.contains("\"heigth\""));
+        assert!(found[0].msg.contains("もしかして: height"));
+    }
+
+    #[test]
+    fn unknown_section_is_located_at_header() {
+        let found = problems("# コメント\n[windw]\nwidth = 360\n");
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].pos, Some((2, 1)));
+        assert!(found[0].msg.contains("もしかして: window"));
+    }
+
+    #[test]
+    fn unknown_key_far_from_names_lists_them() {
+        let found = problems("[assets]\nzzzzzzzz = true\n");
+
+        assert_eq!(found[0].pos, Some((2, 1)));
+        assert!(found[0].msg.contains("書ける項目: assets_dir"));
+    }
+
+    #[test]
+    fn type_error_is_located() {
+        let found = problems("[window]\ntitle = \"x\"\n\n    fullscreen = \"yes\"\n");
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].pos, Some((4, 5)));
+        assert!(found[0].msg.contains("fullscreenはtrueかfalse"));
+    }
+
+    #[test]
+    fn out_of_range_integers_are_located() {
+        let found = problems("[game_option]\nconstant_fps = 5000000000\nseed = -1\n");
+
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].pos, Some((2, 1)));
+        assert!(found[0].msg.contains("constant_fpsは0以上の整数"));
+        assert_eq!(found[1].pos, Some((3, 1)));
+        assert!(found[1].msg.contains("seedは0以上の整数"));
+    }
+
+    #[test]
+    fn syntax_error_is_located() {
+        let (value, found) = check_file(Path::new("test.toml"), "[window]\nwidth = = 360\n");
+
+        assert!(value.is_none());
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].pos.map(|p| p.0), Some(2));
+        assert!(found[0].msg.starts_with("tomlの書き方が正しくありません"));
+    }
+
+    #[test]
+    fn semantic_range_error_is_located_in_source_file() {
+        let (path, found) = load_problems(
+            "conf_check", "[game_option]\nconstant_fps = 0\n\n[window]\nwidth = 100\n");
+
+        let width = found.iter().find(|d| d.msg.contains("width")).unwrap();
+        let fps = found.iter().find(|d| d.msg.contains("constant_fps")).unwrap();
+
+        assert_eq!(width.path, Some(path.clone()));
+        assert_eq!(width.pos, Some((5, 1)));
+        assert!(width.msg.contains(&MIN_WINDOW_W.to_string()));
+        assert_eq!(fps.pos, Some((2, 1)));
+    }
+
+    #[test]
+    fn every_binding_conflict_is_reported() {
+        // キーボードとゲームパッドの両方で重複させると、それぞれ別の問題になる
+        let (path, found) = load_problems(
+            "conf_check_controls",
+            "[controls.keyboard]\nrestart = [\"R\", \"Q\"]\n\n\
+             [controls.gamepad]\nquit = [\"Start\"]\n");
+
+        assert_eq!(found.len(), 2);
+        assert!(found[0].msg.starts_with("[controls.keyboard] キー\"Q\""));
+        assert!(found[1].msg.starts_with("[controls.gamepad] ボタン\"Start\""));
+        // 位置は、それぞれの表の見出し
+        assert_eq!(found[0].path, Some(path.clone()));
+        assert_eq!(found[0].pos, Some((1, 1)));
+        assert_eq!(found[1].pos, Some((4, 1)));
+    }
+
+    #[test]
+    fn diagnostic_display_matches_compiler_style() {
+        let d = Diagnostic::new(Some(PathBuf::from("game_config.toml")), Some((12, 3)),
+                                "メッセージ".to_string());
+
+        assert_eq!(d.to_string(), "game_config.toml:12:3: メッセージ");
+    }
+
+    #[test]
+    fn similar_finds_close_names_only() {
+        let names = ["width", "height", "title", "vsync"];
+
+        assert_eq!(similar("hieght", &names), Some("height"));
+        assert_eq!(similar("titel", &names), Some("title"));
+        assert_eq!(similar("fullscreen", &names), None);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+}
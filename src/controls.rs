@@ -0,0 +1,374 @@
+/*-------------------------------
+            controls.rs
+
+  キーボード・ゲームパッドの割り当てを扱う
+  game_config.tomlの[controls]で、
+  「どの操作（Action）に、どのキー・ボタンを割り当てるか」を決める
+
+  * enum Action: ゲーム内の操作の種類
+  * struct BindingError: 割り当ての間違い一つ分。どちらの表の間違いかも持つ
+  * struct Bindings: キー・ボタンから操作を引くための表
+
+  * impl Action:
+    * all()       : 全操作を並べたもの
+    * name()      : 設定ファイル上での名前
+    * from_name() : 設定ファイル上の名前から操作を得る
+
+  * impl Bindings:
+    * new()       : 設定から割り当て表を作る。重複や未知の名前は、すべてまとめてエラー
+    * key_action(): キーに割り当てられた操作
+    * pad_action(): ゲームパッドボタンに割り当てられた操作
+
+  * join_errors()     : 割り当ての間違いを、一行ずつの文字列にまとめる
+  * build_table()     : 内部用。片方の表を組み立てながら、間違いを集める
+  * button_from_name(): ゲームパッドボタン名からButtonを得る
+-------------------------------*/
+use std::collections::{ BTreeMap, HashMap };
+use std::fmt;
+use std::hash::Hash;
+
+use ggez::event::{ Button, Keycode };
+
+use conf::Controls;
+
+/// 操作の種類の数
+pub const ACTION_COUNT: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+/// ゲーム内の操作の種類
+///
+/// `action as usize`で、ActionStateの配列の添字にする
+pub enum Action {
+    /// 上移動
+    MoveUp,
+    /// 下移動
+    MoveDown,
+    /// 左移動
+    MoveLeft,
+    /// 右移動
+    MoveRight,
+    /// 低速移動
+    SpeedDown,
+    /// ゲームをリスタート
+    Restart,
+    /// タイトル画面へ
+    Title,
+    /// ゲームを終了
+    Quit,
+    /// 一時停止・再開
+    Pause,
+    /// デバッグ用キー
+    Debug,
+}
+
+impl Action {
+    /// 全操作を並べたもの
+    pub fn all() -> &'static [Action] {
+        &[
+            Action::MoveUp,
+            Action::MoveDown,
+            Action::MoveLeft,
+            Action::MoveRight,
+            Action::SpeedDown,
+            Action::Restart,
+            Action::Title,
+            Action::Quit,
+            Action::Pause,
+            Action::Debug,
+        ]
+    }
+
+    /// 設定ファイル上での名前
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::SpeedDown => "speed_down",
+            Action::Restart => "restart",
+            Action::Title => "title",
+            Action::Quit => "quit",
+            Action::Pause => "pause",
+            Action::Debug => "debug",
+        }
+    }
+
+    /// 設定ファイル上の名前から操作を得る
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::all().iter().cloned().find(|a| a.name() == name)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// 割り当ての間違い一つ分
+pub struct BindingError {
+    /// 間違いのある表。"keyboard"か"gamepad"
+    pub section: &'static str,
+    /// 何が間違っているか
+    pub msg: String,
+}
+
+impl fmt::Display for BindingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[controls.{}] {}", self.section, self.msg)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// キー・ボタンから操作を引くための表
+///
+/// 一つの操作に複数のキーを割り当てられるが、
+/// 一つのキーに複数の操作は割り当てられない
+pub struct Bindings {
+    keyboard: HashMap<Keycode, Action>,
+    gamepad: HashMap<Button, Action>,
+}
+
+impl Bindings {
+    /// 設定から割り当て表を作る
+    ///
+    /// 未知の操作名・キー名や、一つのキーを複数の操作に割り当てている場合はエラー。
+    /// 間違いは見つけた分だけ、キーボード・ゲームパッドの順に、それぞれ操作名の順で返す
+    pub fn new(controls: &Controls) -> Result<Self, Vec<BindingError>> {
+        let mut errors = Vec::new();
+
+        let keyboard = build_table(&controls.keyboard, "keyboard", "キー",
+                                   Keycode::from_name, &mut errors);
+        let gamepad = build_table(&controls.gamepad, "gamepad", "ボタン",
+                                  button_from_name, &mut errors);
+
+        // キーボードだけで遊べないと困るので、デバッグ用以外は必ず割り当てる
+        for action in Action::all() {
+            if *action != Action::Debug && !keyboard.values().any(|a| a == action) {
+                errors.push(BindingError {
+                    section: "keyboard",
+                    msg: format!("{}にキーが一つも割り当てられていません", action.name()),
+                });
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(Bindings {
+            keyboard: keyboard,
+            gamepad: gamepad,
+        })
+    }
+
+    /// キーに割り当てられた操作
+    pub fn key_action(&self, keycode: Keycode) -> Option<Action> {
+        self.keyboard.get(&keycode).cloned()
+    }
+
+    /// ゲームパッドボタンに割り当てられた操作
+    pub fn pad_action(&self, btn: Button) -> Option<Action> {
+        self.gamepad.get(&btn).cloned()
+    }
+}
+
+/// 割り当ての間違いを、一行ずつの文字列にまとめる
+pub fn join_errors<'a>(errors: &'a [BindingError]) -> String {
+    errors.iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// 内部用。[controls.keyboard]か[controls.gamepad]の一方を、キーから操作を引く表にする
+///
+/// 間違いは`errors`に足していき、正しく読めた割り当てだけで表を作る
+fn build_table<K, F>(table: &BTreeMap<String, Vec<String>>,
+                     section: &'static str,
+                     unit: &str,
+                     from_name: F,
+                     errors: &mut Vec<BindingError>) -> HashMap<K, Action>
+    where K: Copy + Eq + Hash, F: Fn(&str) -> Option<K> {
+    let mut out: HashMap<K, Action> = HashMap::new();
+
+    for (action_name, names) in table {
+        let action = match Action::from_name(action_name) {
+            Some(a) => a,
+            None => {
+                errors.push(BindingError {
+                    section: section,
+                    msg: format!("\"{}\"という操作はありません", action_name),
+                });
+                continue;
+            }
+        };
+
+        for name in names {
+            let key = match from_name(name) {
+                Some(k) => k,
+                None => {
+                    errors.push(BindingError {
+                        section: section,
+                        msg: format!("{}: \"{}\"という{}はありません", action_name, name, unit),
+                    });
+                    continue;
+                }
+            };
+
+            match out.get(&key).cloned() {
+                Some(prev) if prev != action => {
+                    errors.push(BindingError {
+                        section: section,
+                        msg: format!("{}\"{}\"が{}と{}の両方に割り当てられています",
+                                     unit, name, prev.name(), action.name()),
+                    });
+                }
+                Some(_) => {}
+                None => {
+                    out.insert(key, action);
+                }
+            }
+        }
+    }
+
+    out
+}
+
+/// ゲームパッドボタン名からButtonを得る
+///
+/// ggezの仕様でxbox360コン準拠の名前になる
+pub fn button_from_name(name: &str) -> Option<Button> {
+    let btn = match name {
+        "A" => Button::A,
+        "B" => Button::B,
+        "X" => Button::X,
+        "Y" => Button::Y,
+        "Back" => Button::Back,
+        "Guide" => Button::Guide,
+        "Start" => Button::Start,
+        "LeftStick" => Button::LeftStick,
+        "RightStick" => Button::RightStick,
+        "LeftShoulder" => Button::LeftShoulder,
+        "RightShoulder" => Button::RightShoulder,
+        "DPadUp" => Button::DPadUp,
+        "DPadDown" => Button::DPadDown,
+        "DPadLeft" => Button::DPadLeft,
+        "DPadRight" => Button::DPadRight,
+        _ => return None,
+    };
+
+    Some(btn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 初期値の割り当てから、`action`のキーだけ差し替える
+    fn with_keys(action: &str, keys: &[&str]) -> Controls {
+        let mut controls = Controls::default();
+        controls.keyboard.insert(action.to_string(), keys.iter().map(|k| k.to_string()).collect());
+        controls
+    }
+
+    #[test]
+    fn default_controls_are_valid() {
+        let bindings = Bindings::new(&Controls::default()).unwrap();
+
+        assert_eq!(bindings.key_action(Keycode::Up), Some(Action::MoveUp));
+        assert_eq!(bindings.key_action(Keycode::LShift), Some(Action::SpeedDown));
+        assert_eq!(bindings.key_action(Keycode::Space), None);
+        assert_eq!(bindings.pad_action(Button::Start), Some(Action::Pause));
+        assert_eq!(bindings.pad_action(Button::A), None);
+    }
+
+    #[test]
+    fn names_parse_to_actions_keys_and_buttons() {
+        for action in Action::all() {
+            assert_eq!(Action::from_name(action.name()), Some(*action));
+        }
+        assert_eq!(Action::from_name("jump"), None);
+
+        assert_eq!(button_from_name("DPadLeft"), Some(Button::DPadLeft));
+        assert_eq!(button_from_name("RightShoulder"), Some(Button::RightShoulder));
+        assert_eq!(button_from_name("Triangle"), None);
+
+        let bindings = Bindings::new(&with_keys("restart", &["Space", "Return"])).unwrap();
+        assert_eq!(bindings.key_action(Keycode::Space), Some(Action::Restart));
+        assert_eq!(bindings.key_action(Keycode::Return), Some(Action::Restart));
+        // 差し替えた操作の元のキーは、もう割り当てられていない
+        assert_eq!(bindings.key_action(Keycode::R), None);
+    }
+
+    #[test]
+    fn unknown_names_are_errors() {
+        let errors = Bindings::new(&with_keys("restart", &["Space", "NoSuchKey"])).unwrap_err();
+        assert_eq!(errors, vec![BindingError {
+            section: "keyboard",
+            msg: "restart: \"NoSuchKey\"というキーはありません".to_string(),
+        }]);
+
+        let mut controls = Controls::default();
+        controls.gamepad.insert("jump".to_string(), vec!["A".to_string()]);
+        controls.gamepad.insert("pause".to_string(), vec!["Triangle".to_string()]);
+        let errors = Bindings::new(&controls).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().all(|e| e.section == "gamepad"));
+        assert!(errors[0].msg.contains("\"jump\""));
+        assert!(errors[1].msg.contains("\"Triangle\""));
+    }
+
+    #[test]
+    fn duplicate_keys_are_errors() {
+        // 同じ操作に同じキーを二度書くのは構わない
+        assert!(Bindings::new(&with_keys("restart", &["R", "R"])).is_ok());
+
+        // 別の操作と同じキーはだめ。操作名順で先に読んだ方が「先に割り当てた方」
+        let errors = Bindings::new(&with_keys("restart", &["R", "Q"])).unwrap_err();
+        assert_eq!(errors, vec![BindingError {
+            section: "keyboard",
+            msg: "キー\"Q\"がquitとrestartの両方に割り当てられています".to_string(),
+        }]);
+
+        let mut controls = Controls::default();
+        controls.gamepad.insert("quit".to_string(), vec!["Start".to_string()]);
+        let errors = Bindings::new(&controls).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].section, "gamepad");
+        assert!(errors[0].msg.starts_with("ボタン\"Start\""));
+    }
+
+    #[test]
+    fn every_action_but_debug_needs_a_key() {
+        // ゲームパッドやデバッグ用は、割り当てがなくても構わない
+        let mut controls = Controls::default();
+        controls.gamepad.clear();
+        controls.keyboard.remove("debug");
+        assert!(Bindings::new(&controls).is_ok());
+
+        let errors = Bindings::new(&with_keys("pause", &[])).unwrap_err();
+        assert_eq!(errors, vec![BindingError {
+            section: "keyboard",
+            msg: "pauseにキーが一つも割り当てられていません".to_string(),
+        }]);
+    }
+
+    #[test]
+    fn all_conflicts_are_reported_in_a_fixed_order() {
+        let mut controls = with_keys("title", &["T", "Q", "Nope"]);
+        controls.keyboard.remove("move_up");
+        controls.gamepad.insert("pause".to_string(), vec!["DPadUp".to_string()]);
+
+        let errors = Bindings::new(&controls).unwrap_err();
+        let lines: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+        // キーボード（操作名順）→ゲームパッド→キー未割り当ての順
+        assert_eq!(lines, vec![
+            "[controls.keyboard] キー\"Q\"がquitとtitleの両方に割り当てられています",
+            "[controls.keyboard] title: \"Nope\"というキーはありません",
+            "[controls.gamepad] ボタン\"DPadUp\"がmove_upとpauseの両方に割り当てられています",
+            "[controls.keyboard] move_upにキーが一つも割り当てられていません",
+        ]);
+        assert_eq!(join_errors(&errors), lines.join("\n"));
+
+        // 何度作っても同じ並び
+        assert_eq!(Bindings::new(&controls).unwrap_err(), errors);
+    }
+}
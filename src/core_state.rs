@@ -7,6 +7,9 @@
   
   * impl CoreState: ゲームのガワを包む皮
     * new(): よくある初期化のやつ
+    * start_playback(): リプレイ再生モードに切り替える
+    * set_difficulty(): 難易度を切り替えて、その難易度のハイスコア表を読み込む
+    * change_scene()  : シーンを切り替える。各シーンの中身はscene.rsを参照
   
   * Eventhandler for CoreState: ggezのイベントハンドラ
     * update()        : 多分描画した後の更新関連
@@ -16,65 +19,192 @@
     * controller_button_down_event(): コントローラー版押下
     * controller_button_up_event()  : コントローラー版放上
     * controller_axis_event(): アナログスティックの動きを検知
+    * mouse_button_down_event(): 一時停止中のクリックで再開する
     * focus_event(): ウィンドウがアクティブになっているかを検知
   
-  * debug_frames():
+  * load_high_scores(): 難易度ごとのハイスコア表を読み込む
   * print_debug() : 起動時に一度のみデバッグモード文章を表示する
-  * measure_time():
 -------------------------------*/ 
-use std::env;
-
 use ggez::{ graphics, timer };
-use ggez::{ self, Context, GameResult };
-use ggez::event::{ Axis, Button, EventHandler, Keycode, Mod };
+use ggez::{ Context, GameError, GameResult };
+use ggez::event::{ Axis, Button, EventHandler, Keycode, Mod, MouseButton };
 
 use assets::{ Assets, GameText };
 use conf::GameConf;
+use controls::{ self, Bindings };
+use difficulty::Difficulty;
+use error;
 use input_state::InputState;
-use game_state::GameState;
+use game_state::{ GameState, SpriteInfo };
+use high_score::HighScores;
+use replay::{ Replay, ReplayPlayer };
+use run_mode::RunMode;
+use scene::{ self, Scene };
 use view;
 
 /// ゲームに使用する変数を一つにまとめる
 pub struct CoreState {
     /// ウィンドウがアクティブになっているか否か
     pub has_focus: bool,
+    /// 今表示しているシーン
+    pub scene: Scene,
     /// ゲームアセットをひとまとめ
     pub assets: Assets,
     /// ゲーム内で使うテキストまとめ
     pub text: GameText,
     /// ユーザー操作をinputとして受ける
     pub input: InputState,
+    /// キー・ボタンと操作の割り当て
+    pub bindings: Bindings,
     /// ゲーム内で使う変数まとめ
     pub game_state: GameState,
     /// game_config.tomlから取得する情報がここに
     pub game_conf: GameConf,
+    /// 起動モード。デバッグ機能のオン・オフ
+    pub run_mode: RunMode,
+    /// 現在のゲームのリプレイ記録
+    pub replay: Replay,
+    /// リプレイ再生中ならSome
+    pub playback: Option<ReplayPlayer>,
+    /// ユーザーデータフォルダに保存するハイスコア表
+    pub high_scores: HighScores,
+    /// 直前のゲームがハイスコア表に入った順位（0始まり）
+    pub new_record_rank: Option<usize>,
+    /// 一時停止から再開するまでの残りフレーム数
+    pub countdown: usize,
 }
 
 /// ゲーム根幹システム
 impl CoreState {
     /// ゲーム根幹システムの初期化
-    pub fn new(ctx: &mut Context, conf: GameConf) -> GameResult<CoreState> {
+    ///
+    /// タイトル画面から始まる
+    ///
+    /// run_modeは起動引数から作ったもの。組み込み用途ならRunMode::Normalで良い
+    pub fn new(ctx: &mut Context,
+               conf: GameConf,
+               run_mode: RunMode) -> error::Result<CoreState> {
         let assets = Assets::new(ctx, &conf)?;
         let game_text = GameText::new(ctx, &assets)?;
         
-        let mut game_state = GameState::new(ctx, &assets);
+        // 設定読み込み時にも調べているけど、念のためここでもエラーを返す
+        let bindings = Bindings::new(&conf.controls)
+            .map_err(|e| GameError::ConfigError(controls::join_errors(&e)))?;
+        
+        // GameState自体はggezに依存しないので、寸法だけを渡す
+        // ゲーム画面の大きさは、実際のウィンドウではなく設定ファイルの値を使う
+        let mut game_state = GameState::new(
+            conf.window.width,
+            conf.window.height,
+            conf.game_option.constant_fps,
+            SpriteInfo::new(assets.player_ship.width(),
+                            assets.player_ship.height(),
+                            assets.player_hitbox.clone()),
+            assets.enemy_block.iter()
+                .zip(&assets.enemy_hitbox)
+                .map(|(image, hitbox)| {
+                    SpriteInfo::new(image.width(), image.height(), hitbox.clone())
+                })
+                .collect(),
+            conf.game_option.seed,
+        )?;
         
         // "-d"引数を付けて起動した際のデバッグモード
-        if env::var("GAME_ACTIVATE_MODE").unwrap() == "DEBUG_MODE" {
-            print_debug(ctx, &game_state, &conf);
+        if run_mode.is_debug() {
+            print_debug(ctx, &game_state, &conf, &run_mode);
         }
         
+        // 無敵はゲーム側で判定するので、GameStateに伝えておく
+        game_state.invincible = run_mode.debug().invincible;
+        
+        // 岩の出方はassetsフォルダのwaves.tomlから
+        game_state.waves = assets.waves.clone();
+        
+        // 岩のプールの大きさ
+        game_state.actor.max_enemies = conf.game_option.max_enemies as usize;
+        
+        // 難易度名は設定読み込み時に調べてあるので、ここでは初期値に倒すだけ
+        game_state.difficulty = Difficulty::from_name(&conf.game_option.difficulty)
+            .unwrap_or_default();
+        
         // ゲーム初期状態にリセットをかけておく
         game_state.game_reset();
         
-        Ok(CoreState {
+        let replay = Replay::new(&game_state);
+        
+        let high_scores = load_high_scores(ctx, game_state.difficulty);
+        
+        let mut core = CoreState {
             has_focus: false,
+            scene: Scene::Title,
             assets: assets,
             text: game_text,
             input: InputState::new(),
+            bindings: bindings,
             game_state: game_state,
             game_conf: conf,
-        })
+            run_mode: run_mode,
+            replay: replay,
+            playback: None,
+            high_scores: high_scores,
+            new_record_rank: None,
+            countdown: 0,
+        };
+        
+        let difficulty = core.game_state.difficulty;
+        core.text.new_title_difficulty(ctx, &core.assets.pixel_font_small, difficulty)?;
+        
+        Scene::Title.enter(&mut core, ctx)?;
+        
+        Ok(core)
+    }
+    
+    /// リプレイ再生モードに切り替える
+    ///
+    /// シード値と固定FPS数値、難易度はリプレイ側のものを使う
+    ///
+    /// ウィンドウの大きさなど、合わせられない設定が記録時と違えばエラー
+    pub fn start_playback(&mut self,
+                          ctx: &mut Context,
+                          replay: Replay) -> error::Result<()> {
+        replay.check(&self.game_state).map_err(error::Error::replay)?;
+        
+        self.game_conf.game_option.constant_fps = replay.constant_fps;
+        self.game_state.system.constant_fps = replay.constant_fps;
+        self.game_state.fixed_seed = Some(replay.seed);
+        self.set_difficulty(ctx, replay.difficulty)?;
+        
+        self.playback = Some(ReplayPlayer::new(replay));
+        
+        Ok(())
+    }
+    
+    /// 難易度を切り替える
+    ///
+    /// ゲーム状態を初期化して、その難易度のハイスコア表を読み込み直す
+    pub fn set_difficulty(&mut self,
+                          ctx: &mut Context,
+                          difficulty: Difficulty) -> GameResult<()> {
+        self.game_state.difficulty = difficulty;
+        self.game_state.game_reset();
+        self.high_scores = load_high_scores(ctx, difficulty);
+        
+        self.text.new_title_difficulty(ctx, &self.assets.pixel_font_small, difficulty)
+    }
+    
+    /// シーンを切り替える
+    ///
+    /// 遷移元のexit()と遷移先のenter()はここで呼ぶ
+    pub fn change_scene(&mut self,
+                        ctx: &mut Context,
+                        next: Scene) -> GameResult<()> {
+        let prev = self.scene;
+        prev.exit(self, ctx)?;
+        
+        self.scene = next;
+        next.enter(self, ctx)?;
+        
+        Ok(())
     }
 }
 
@@ -87,30 +217,19 @@ impl EventHandler for CoreState {
         while timer::check_update_time(
             ctx, 
             self.game_conf.game_option.constant_fps) {        
-            // ウィンドウがアクティブな際のみ更新 & ゲームシーン分岐
-            if self.has_focus && self.game_state.system.is_title {
-                // タイトル画面
-                self.game_state.title_mode(&mut self.input);
-                
-            } else if self.has_focus && self.game_state.system.is_game_over {
-                // ゲームオーバー時の画面
-                self.game_state.game_over_mode(ctx, &mut self.input);
-                
-            } else if self.has_focus {
-                // フレーム数を計測して、時間を割り出す
-                measure_time(
-                    &mut self.game_state,
-                    self.game_conf.game_option.constant_fps,
-                );
-                
-                // メインのゲーム画面
-                self.game_state.main_game_mode(&mut self.input)?;
-                
-                if env::var("GAME_ACTIVATE_MODE").unwrap() == "DEBUG_MODE" {
-                    debug_frames(ctx, &mut self.game_state);
-                }
-            } // end if
+            // ウィンドウがアクティブな際のみ更新
+            if !self.has_focus {
+                continue;
+            }
+            
+            // 前のフレームから溜まった入力を、このフレームの入力として確定
+            self.input.begin_tick();
             
+            // 今のシーンに更新を任せて、遷移先があれば切り替える
+            let scene = self.scene;
+            if let Some(next) = scene.update(self, ctx)? {
+                self.change_scene(ctx, next)?;
+            }
         } // end while
         
         Ok(())
@@ -121,16 +240,21 @@ impl EventHandler for CoreState {
         // 画面の初期化
         graphics::clear(ctx);
         
-        // メインゲーム画面を描画
-        view::render_game(self, ctx)?;
+        // ウィンドウの大きさに合わせて、ゲーム画面を拡大して中央に置く
+        view::set_letterbox(self, ctx)?;
+        
+        // 今のシーンに描画を任せる
+        let scene = self.scene;
+        scene.render(self, ctx)?;
         
-        if self.game_state.system.is_title {
-            view::render_title(self, ctx)?;
-        } else if self.game_state.system.is_game_over {
-            // ゲームオーバー時にダイアログボックスを出す
-            view::render_game_over(self, ctx)?;
+        // 非アクティブな間は、止まっていることがわかるように
+        if !self.has_focus {
+            view::render_focus_lost(self, ctx)?;
         }
         
+        // ゲーム画面からはみ出した部分は黒帯で隠す
+        view::render_letterbox(self, ctx)?;
+        
         // 描画内容を画面に反映
         graphics::present(ctx);
         
@@ -144,7 +268,7 @@ impl EventHandler for CoreState {
                       keymod: Mod, 
                       _repeat: bool) {
         // 押されたキーの処遇はInputStateで判定
-        self.input.key_press(keycode, keymod);
+        self.input.key_press(&self.bindings, keycode, keymod);
     }
     
     // キーボードのキーが放上された時に作動
@@ -153,14 +277,28 @@ impl EventHandler for CoreState {
                     keycode: Keycode, 
                     keymod: Mod, 
                     _repeat: bool) {
-        self.input.key_release(keycode, keymod);
+        self.input.key_release(&self.bindings, keycode, keymod);
+    }
+    
+    // マウスボタンが押下された時に作動
+    fn mouse_button_down_event(&mut self,
+                               ctx: &mut Context,
+                               _button: MouseButton,
+                               _x: i32,
+                               _y: i32) {
+        // 一時停止中のクリックで、カウントダウンを挟んで再開
+        if self.has_focus && self.scene == Scene::Paused {
+            if let Err(e) = self.change_scene(ctx, Scene::Countdown) {
+                println!("Error: 再開に失敗 - {}", e);
+            }
+        }
     }
     
     // ゲームパッドのボタンが押下された時に作動
     fn controller_button_down_event(&mut self, _ctx: &mut Context, btn: Button, instance_id: i32) {
         // このゲームはおひとりさま専用でありんす
         if instance_id == 0 {
-            self.input.pad_press(btn);
+            self.input.pad_press(&self.bindings, btn);
         }
     }
 
@@ -168,7 +306,7 @@ impl EventHandler for CoreState {
     fn controller_button_up_event(&mut self, _ctx: &mut Context, btn: Button, instance_id: i32) {
         // このゲームはおひとりさま専用(ry
         if instance_id == 0 {
-            self.input.pad_release(btn);
+            self.input.pad_release(&self.bindings, btn);
         }
     }
     
@@ -184,11 +322,35 @@ impl EventHandler for CoreState {
     }
 
     // 画面がアクティブであるかを検出する
-    fn focus_event(&mut self, _ctx: &mut Context, gained: bool) {
+    fn focus_event(&mut self, ctx: &mut Context, gained: bool) {
         if gained {
+            // 再開は明示的な入力（クリックか一時停止キー）を待つ
             self.has_focus = true;
         } else {
             self.has_focus = false;
+            
+            // 押しっぱなしのまま離れたキーが残らないように、入力を消しておく
+            self.input.reset();
+            
+            // プレイ中なら一時停止シーンへ
+            if self.scene == Scene::Playing || self.scene == Scene::Countdown {
+                if let Err(e) = self.change_scene(ctx, Scene::Paused) {
+                    println!("Error: 一時停止に失敗 - {}", e);
+                }
+            }
+        }
+    }
+}
+
+/// 難易度`difficulty`のハイスコア表を読み込む
+///
+/// ハイスコア表が壊れていても、ゲーム自体は遊べるようにする
+fn load_high_scores(ctx: &mut Context, difficulty: Difficulty) -> HighScores {
+    match HighScores::load(&scene::high_score_path(ctx, difficulty)) {
+        Ok(scores) => scores,
+        Err(e) => {
+            println!("Error: ハイスコア表の読み込みに失敗 - {}", e);
+            HighScores::default()
         }
     }
 }
@@ -196,59 +358,51 @@ impl EventHandler for CoreState {
 /// デバッグモードの際に、たまに参照したくなるデータを表示する
 fn print_debug(ctx: &mut Context, 
                game_state: &GameState,
-               conf: &GameConf) {
+               conf: &GameConf,
+               run_mode: &RunMode) {
     let translate_dir = if conf.translate.is_translate {
         &conf.translate.translate_data_dir
     } else {
         "is_translateフラグ未指定"
     };
     
+    let (drawable_w, drawable_h) = graphics::get_drawable_size(ctx);
+    
+    let seed_text = match conf.game_option.seed {
+        Some(n) => n.to_string(),
+        None => "未指定（ゲームごとにランダム）".to_string(),
+    };
+    
     let debug_text = format!("    \
     でばっぐもーど
   Window Size            : {} x {}
+  Drawable Size          : {} x {}
+  Fullscreen             : {}
+  Integer scaling        : {}
   Vsync                  : {}
   Constant Frame rate    : {}
-  GAME_ACTIVATE_MODE     : {}
+  Random seed            : {}
+  Difficulty             : {}
+  Debug features         : {}
   GAME_ASSETS_DIR        : {}
   GAME_TRANSLATE_DATA_DIR: {}
   
   struct game_state      : {:?}
 ",  
-        ctx.conf.window_mode.width,
-        ctx.conf.window_mode.height,
-        ctx.conf.window_mode.vsync,
+        conf.window.width,
+        conf.window.height,
+        drawable_w,
+        drawable_h,
+        conf.window.fullscreen,
+        conf.window.integer_scale,
+        conf.window.vsync,
         conf.game_option.constant_fps,
-        env::var("GAME_ACTIVATE_MODE").unwrap(),
+        seed_text,
+        conf.game_option.difficulty,
+        run_mode.debug().names().join(", "),
         conf.assets.assets_dir,
         translate_dir,
         game_state);
     
     println!("{}", debug_text);
 }
-
-// 計測するフレーム数から、秒数を計測する
-fn measure_time(game_state: &mut GameState, constant_fps: u32) {
-    // 常にフレーム数を計測
-    game_state.system.frames += 1;
-    
-    if (game_state.system.frames % constant_fps as usize) == 0 {
-        game_state.system.seconds += 1;
-    }
-}
-
-/// デバッグ用のフレーム表示。とりあえず標準出力に出す。
-fn debug_frames(ctx: &mut Context, game_state: &mut GameState) {   
-    if game_state.system.frames % 60 == 0 {
-        println!("FPS: {}, Seconds: {}, EnemyLen: {}", 
-            ggez::timer::get_fps(ctx),
-            game_state.system.seconds,
-            game_state.actor.e_block.len());
-        //println!("Player.x: {}, Player.y: {}, Player.w: {}, Player.h: {}",
-            //game_state.actor.player.x,
-            //game_state.actor.player.y,
-            //game_state.actor.player.width,
-            //game_state.actor.player.height);
-        //println!("Player.collision: {}", game_state.actor.player.collision);
-        println!("現在敵速度: {}", game_state.system.enemy_move_speed);
-    }
-}
@@ -0,0 +1,135 @@
+/*-------------------------------
+            difficulty.rs
+
+  難易度（やさしい・ふつう・むずかしい・ルナティック）をまとめる
+
+  難易度ごとに、waves.tomlで決めた岩の速さ・速さの上がり方・
+  岩が増える間隔と、自機の速さ・当たり判定の大きさに倍率を掛ける。
+  ふつうなら全部等倍なので、waves.tomlの値がそのまま使われる
+
+  ハイスコア表は難易度ごとに別のファイルへ保存する（high_score.rsを参照）
+
+  * struct Scale  : 難易度ごとの倍率
+  * enum Difficulty: 難易度
+
+  * impl Difficulty:
+    * all()       : 全難易度を並べたもの。易しい順
+    * index()     : all()の中での番号
+    * from_index(): 番号から難易度を引く。リプレイファイル用
+    * name()      : 設定ファイルや起動引数での書き方
+    * from_name() : 名前から難易度を引く
+    * prev()      : 一つ易しい難易度。一番易しければそのまま
+    * next()      : 一つ難しい難易度。一番難しければそのまま
+    * scale()     : 難易度ごとの倍率
+-------------------------------*/
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// 難易度ごとの倍率。ふつうなら全部1.0
+pub struct Scale {
+    /// ゲーム開始時の岩の速さ
+    pub start_speed: f32,
+    /// 岩の速さの上がり方
+    pub accel: f32,
+    /// 岩が増える間隔。小さいほど早く増える
+    pub spawn_interval: f64,
+    /// 自機の速さ
+    pub player_speed: f32,
+    /// 自機の当たり判定の大きさ。当たり判定の中心を基準に拡大・縮小する
+    pub hitbox: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+/// 難易度
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    Lunatic,
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Difficulty::Normal
+    }
+}
+
+impl Difficulty {
+    /// 全難易度を並べたもの。易しい順
+    pub fn all() -> &'static [Difficulty] {
+        &[
+            Difficulty::Easy,
+            Difficulty::Normal,
+            Difficulty::Hard,
+            Difficulty::Lunatic,
+        ]
+    }
+
+    /// all()の中での番号
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    /// 番号から難易度を引く。範囲外ならNone
+    pub fn from_index(index: usize) -> Option<Difficulty> {
+        Difficulty::all().get(index).cloned()
+    }
+
+    /// 設定ファイルや起動引数での書き方
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+            Difficulty::Lunatic => "lunatic",
+        }
+    }
+
+    /// 名前から難易度を引く。知らない名前ならNone
+    pub fn from_name<'a>(name: &'a str) -> Option<Difficulty> {
+        Difficulty::all().iter().cloned().find(|d| d.name() == name)
+    }
+
+    /// 一つ易しい難易度。一番易しければそのまま
+    pub fn prev(&self) -> Difficulty {
+        Difficulty::from_index(self.index().saturating_sub(1)).unwrap_or(*self)
+    }
+
+    /// 一つ難しい難易度。一番難しければそのまま
+    pub fn next(&self) -> Difficulty {
+        Difficulty::from_index(self.index() + 1).unwrap_or(*self)
+    }
+
+    /// 難易度ごとの倍率
+    pub fn scale(&self) -> Scale {
+        match *self {
+            Difficulty::Easy => Scale {
+                start_speed: 0.8,
+                accel: 0.7,
+                spawn_interval: 1.3,
+                player_speed: 1.0,
+                hitbox: 0.8,
+            },
+            Difficulty::Normal => Scale {
+                start_speed: 1.0,
+                accel: 1.0,
+                spawn_interval: 1.0,
+                player_speed: 1.0,
+                hitbox: 1.0,
+            },
+            Difficulty::Hard => Scale {
+                start_speed: 1.2,
+                accel: 1.3,
+                spawn_interval: 0.8,
+                player_speed: 1.1,
+                hitbox: 1.1,
+            },
+            Difficulty::Lunatic => Scale {
+                start_speed: 1.5,
+                accel: 1.6,
+                spawn_interval: 0.6,
+                player_speed: 1.2,
+                hitbox: 1.2,
+            },
+        }
+    }
+}
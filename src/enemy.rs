@@ -0,0 +1,293 @@
+/*-------------------------------
+            enemy.rs
+
+  岩の種類ごとの見た目と動き方をまとめる
+
+  種類はEnemyKindで表し、動き方は種類ごとに
+  EnemyBehaviorを実装した型に任せる。
+  GameStateは種類を気にせず、behavior()に一フレーム分の移動を頼むだけ
+
+  向きなどを決める乱数はGameStateのシード付き乱数生成器を借りるので、
+  種類が増えても、同じシード値と同じ操作なら毎回同じ展開になる
+
+  * struct StepInfo: 一フレーム分動かすのに必要な、ゲーム側の値
+  * trait EnemyBehavior: 種類ごとの動き方
+    * init(): 出現した時に、向きなどの初期値を決める
+    * step(): 一フレーム分動かす
+    * sway(): 出現位置から左右に揺れる幅。出現位置を決める時に使う
+
+  * enum EnemyKind: 岩の種類
+  * impl EnemyKind:
+    * all()        : 全種類を並べたもの
+    * index()      : all()の中での番号。スプライトの一覧もこの順に並べる
+    * name()       : 種類名。waves.tomlでの書き方
+    * from_name()  : 種類名から種類を引く
+    * image_name() : 画像ファイル名。当たり判定のtomlも同じ名前
+    * behavior()   : 動き方
+
+  * enum EnemyState: 岩の一生。出現 → 画面内 → 画面外 → 回収
+  * impl EnemyState:
+    * is_live()    : 動かしたり描いたりする対象か。回収済みでなければtrue
+
+  どの種類がいつ・どれくらい出てくるかは、waves.rsを参照のこと
+
+  * Straight, ZigZag, SineDrift, Diagonal, Accelerating, Heavy: 種類ごとの動き方
+  * bounce_x(): 画面の左右端で跳ね返す
+-------------------------------*/
+use std::f32::consts::PI;
+
+use rand::{ Rng, XorShiftRng };
+
+use game_state::Enemy;
+
+/// ジグザグ岩の横移動速度（ピクセル毎秒）
+const ZIGZAG_SPEED: f32 = 100.0;
+/// ジグザグ岩が向きを変える間隔（秒）
+const ZIGZAG_INTERVAL: f32 = 0.5;
+/// ゆらゆら岩の揺れ幅（ピクセル）
+const SINE_AMPLITUDE: f32 = 40.0;
+/// ゆらゆら岩が一往復する秒数
+const SINE_PERIOD: f32 = 2.0;
+/// 斜め岩の横移動速度。出現した時の岩の速さに対する割合
+const DIAGONAL_RATIO: f32 = 0.4;
+/// 加速岩の、出現した時の速さの倍率
+const ACCEL_START: f32 = 0.5;
+/// 加速岩の、一秒ごとに増える速さの倍率
+const ACCEL_RATE: f32 = 0.75;
+/// 加速岩の速さの倍率の上限
+const ACCEL_MAX: f32 = 2.0;
+/// 重い岩の速さの倍率
+const HEAVY_RATIO: f32 = 0.6;
+
+#[derive(Clone, Copy, Debug)]
+/// 一フレーム分動かすのに必要な、ゲーム側の値
+pub struct StepInfo {
+    /// 今の岩の速さ（ピクセル毎秒）。種類ごとの倍率はここに掛ける
+    pub speed: f32,
+    /// 一フレームの秒数
+    pub dt: f32,
+    /// ゲーム画面の横幅。左右端で跳ね返る岩が使う
+    pub window_w: f32,
+}
+
+/// 岩の種類ごとの動き方
+pub trait EnemyBehavior {
+    /// 出現した時に、向きなどの初期値を決める
+    ///
+    /// 何もしなければ真下に落ちる
+    fn init(&self, _e: &mut Enemy, _rng: &mut XorShiftRng, _info: &StepInfo) {}
+
+    /// 一フレーム分動かす。e.ageはこのフレームの分まで進めてから呼ぶ
+    fn step(&self, e: &mut Enemy, info: &StepInfo);
+
+    /// 出現位置（揺れの中心）から、左右それぞれに揺れる幅の最大（ピクセル）
+    ///
+    /// GameStateは出現位置をこの分だけ画面の内側に決めて、
+    /// 重なりと隙間もこの分だけ左右に広げて調べる
+    fn sway(&self) -> f32 {
+        0.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+/// 岩の種類
+pub enum EnemyKind {
+    /// まっすぐ落ちる、いつもの岩
+    Block,
+    /// 一定間隔で左右に向きを変えながら落ちる
+    ZigZag,
+    /// 出現位置を中心に、左右にゆらゆら揺れながら落ちる
+    SineDrift,
+    /// 斜めに落ちて、画面端で跳ね返る
+    Diagonal,
+    /// はじめは遅く、だんだん速くなる
+    Accelerating,
+    /// 大きくて遅い
+    Heavy,
+}
+
+impl EnemyKind {
+    /// 全種類を並べたもの。スプライトの一覧もこの順に並べる
+    pub fn all() -> &'static [EnemyKind] {
+        &[
+            EnemyKind::Block,
+            EnemyKind::ZigZag,
+            EnemyKind::SineDrift,
+            EnemyKind::Diagonal,
+            EnemyKind::Accelerating,
+            EnemyKind::Heavy,
+        ]
+    }
+
+    /// all()の中での番号
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    /// 種類名。waves.tomlでの書き方
+    pub fn name(&self) -> &'static str {
+        match *self {
+            EnemyKind::Block => "block",
+            EnemyKind::ZigZag => "zigzag",
+            EnemyKind::SineDrift => "sine",
+            EnemyKind::Diagonal => "diagonal",
+            EnemyKind::Accelerating => "accel",
+            EnemyKind::Heavy => "heavy",
+        }
+    }
+
+    /// 種類名から種類を引く。知らない名前ならNone
+    pub fn from_name<'a>(name: &'a str) -> Option<EnemyKind> {
+        EnemyKind::all().iter().cloned().find(|k| k.name() == name)
+    }
+
+    /// 画像ファイル名。当たり判定は拡張子をtomlにしたファイルから読む
+    pub fn image_name(&self) -> &'static str {
+        match *self {
+            EnemyKind::Block => "enemy_block_32x32.png",
+            EnemyKind::ZigZag => "enemy_zigzag_24x24.png",
+            EnemyKind::SineDrift => "enemy_sine_28x28.png",
+            EnemyKind::Diagonal => "enemy_diagonal_28x20.png",
+            EnemyKind::Accelerating => "enemy_accel_20x36.png",
+            EnemyKind::Heavy => "enemy_heavy_48x48.png",
+        }
+    }
+
+    /// 動き方
+    pub fn behavior(&self) -> &'static dyn EnemyBehavior {
+        match *self {
+            EnemyKind::Block => &Straight,
+            EnemyKind::ZigZag => &ZigZag,
+            EnemyKind::SineDrift => &SineDrift,
+            EnemyKind::Diagonal => &Diagonal,
+            EnemyKind::Accelerating => &Accelerating,
+            EnemyKind::Heavy => &Heavy,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+/// 岩の一生
+///
+/// 回収済みの岩は、GameStateの岩一覧（プール）の空き枠として次の出現に使い回す
+pub enum EnemyState {
+    /// 画面の上に出現して、まだ画面に入っていない
+    Spawned,
+    /// 画面に入っている
+    Active,
+    /// 画面の下に抜けた。ここで避けた数に数える
+    Offscreen,
+    /// 画面の下に抜けきって、空き枠になった
+    Recycled,
+}
+
+impl EnemyState {
+    /// 動かしたり描いたりする対象か。回収済みでなければtrue
+    pub fn is_live(&self) -> bool {
+        *self != EnemyState::Recycled
+    }
+}
+
+/// まっすぐ落ちる
+struct Straight;
+
+impl EnemyBehavior for Straight {
+    fn step(&self, e: &mut Enemy, info: &StepInfo) {
+        e.y += info.speed * info.dt;
+    }
+}
+
+/// 一定間隔で左右に向きを変えながら落ちる
+struct ZigZag;
+
+impl EnemyBehavior for ZigZag {
+    fn init(&self, e: &mut Enemy, rng: &mut XorShiftRng, _info: &StepInfo) {
+        e.vx = if rng.gen() { ZIGZAG_SPEED } else { -ZIGZAG_SPEED };
+    }
+
+    fn step(&self, e: &mut Enemy, info: &StepInfo) {
+        // 区切りをまたいだフレームで向きを変える
+        let prev_age = e.age - info.dt;
+        if (e.age / ZIGZAG_INTERVAL).floor() > (prev_age / ZIGZAG_INTERVAL).floor() {
+            e.vx = -e.vx;
+        }
+
+        e.x += e.vx * info.dt;
+        e.y += info.speed * 0.9 * info.dt;
+        bounce_x(e, info);
+    }
+
+    fn sway(&self) -> f32 {
+        // 向きを変えるまでに進む分だけ、出現位置から片側へずれる
+        ZIGZAG_SPEED * ZIGZAG_INTERVAL
+    }
+}
+
+/// 出現位置を中心に、左右にゆらゆら揺れながら落ちる
+struct SineDrift;
+
+impl EnemyBehavior for SineDrift {
+    fn init(&self, e: &mut Enemy, _rng: &mut XorShiftRng, info: &StepInfo) {
+        // 出現位置はsway()の分だけ内側に決めてあるので、普段はそのまま中心になる。
+        // 念のため、揺れても画面からはみ出さないよう中心を内側に寄せておく
+        let max_x = (info.window_w - e.width as f32 - SINE_AMPLITUDE).max(SINE_AMPLITUDE);
+        e.origin_x = e.x.max(SINE_AMPLITUDE).min(max_x);
+    }
+
+    fn step(&self, e: &mut Enemy, info: &StepInfo) {
+        e.x = e.origin_x + SINE_AMPLITUDE * (e.age * 2.0 * PI / SINE_PERIOD).sin();
+        e.y += info.speed * info.dt;
+    }
+
+    fn sway(&self) -> f32 {
+        SINE_AMPLITUDE
+    }
+}
+
+/// 斜めに落ちて、画面端で跳ね返る
+struct Diagonal;
+
+impl EnemyBehavior for Diagonal {
+    fn init(&self, e: &mut Enemy, rng: &mut XorShiftRng, info: &StepInfo) {
+        let vx = info.speed * DIAGONAL_RATIO;
+        e.vx = if rng.gen() { vx } else { -vx };
+    }
+
+    fn step(&self, e: &mut Enemy, info: &StepInfo) {
+        e.x += e.vx * info.dt;
+        e.y += info.speed * info.dt;
+        bounce_x(e, info);
+    }
+}
+
+/// はじめは遅く、だんだん速くなる
+struct Accelerating;
+
+impl EnemyBehavior for Accelerating {
+    fn step(&self, e: &mut Enemy, info: &StepInfo) {
+        let ratio = (ACCEL_START + e.age * ACCEL_RATE).min(ACCEL_MAX);
+        e.y += info.speed * ratio * info.dt;
+    }
+}
+
+/// 大きくて遅い。大きさは画像と当たり判定の方で決まる
+struct Heavy;
+
+impl EnemyBehavior for Heavy {
+    fn step(&self, e: &mut Enemy, info: &StepInfo) {
+        e.y += info.speed * HEAVY_RATIO * info.dt;
+    }
+}
+
+/// 内部用。画面の左右端からはみ出したら、内側に戻して向きを変える
+fn bounce_x(e: &mut Enemy, info: &StepInfo) {
+    let max_x = info.window_w - e.width as f32;
+
+    if e.x < 0.0 {
+        e.x = -e.x;
+        e.vx = e.vx.abs();
+    } else if e.x > max_x {
+        e.x = max_x - (e.x - max_x);
+        e.vx = -e.vx.abs();
+    }
+}
@@ -0,0 +1,130 @@
+/*-------------------------------
+            error.rs
+
+  起動時の読み込みで起きうるエラーを、一つの型にまとめる
+
+  panic!やexpect()で落とす代わりにこれを返して、
+  main.rsで一つの分かりやすいメッセージにして表示する。
+  どのファイルで何が起きたかが分かるよう、pathなどを添えておく
+
+  * enum Error: 設定・アセット・テキスト・リプレイ・ファイル入出力・ggezのエラー
+  * type Result: Errorを返すResult
+
+  * impl Error:
+    * config(): 設定ファイルのエラーを作る
+    * asset() : アセットのエラーを作る
+    * text()  : ゲーム内テキストのエラーを作る
+    * replay(): リプレイのエラーを作る
+    * io()    : ファイル入出力のエラーを作る
+
+-------------------------------*/
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{ Path, PathBuf };
+
+use ggez::GameError;
+
+use conf_check::Diagnostic;
+
+/// dodge_rockで起きるエラー
+#[derive(Debug)]
+pub enum Error {
+    /// 設定ファイルが読めない・書き方がおかしい
+    Config { path: PathBuf, msg: String },
+    /// 設定の中身を調べて見つかった問題。全部まとめて持つ
+    Invalid(Vec<Diagnostic>),
+    /// 画像やフォントなどのアセットが見つからない・読み込めない
+    Asset { name: String, msg: String },
+    /// ゲーム内テキストのファイルが読めない・書き方がおかしい
+    Text { path: PathBuf, msg: String },
+    /// リプレイを今の設定では再生できない
+    Replay { msg: String },
+    /// その他のファイル入出力
+    Io { path: PathBuf, err: io::Error },
+    /// ggez内部で起きたエラー
+    Ggez(GameError),
+}
+
+/// Errorを返すResult
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+impl Error {
+    /// 設定ファイルのエラーを作る
+    pub fn config<'a, S: ToString>(path: &'a Path, msg: S) -> Self {
+        Error::Config { path: path.to_path_buf(), msg: msg.to_string() }
+    }
+
+    /// アセットのエラーを作る。nameはファイル名かフォルダ名
+    pub fn asset<'a, S: ToString>(name: &'a str, msg: S) -> Self {
+        Error::Asset { name: name.to_string(), msg: msg.to_string() }
+    }
+
+    /// ゲーム内テキストのエラーを作る
+    pub fn text<'a, S: ToString>(path: &'a Path, msg: S) -> Self {
+        Error::Text { path: path.to_path_buf(), msg: msg.to_string() }
+    }
+
+    /// リプレイのエラーを作る
+    pub fn replay<S: ToString>(msg: S) -> Self {
+        Error::Replay { msg: msg.to_string() }
+    }
+
+    /// ファイル入出力のエラーを作る
+    pub fn io<'a>(path: &'a Path, err: io::Error) -> Self {
+        Error::Io { path: path.to_path_buf(), err: err }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Config { ref path, ref msg } => {
+                write!(f, "設定ファイル{}の読み込みに失敗 - {}", path.display(), msg)
+            }
+            Error::Invalid(ref problems) => {
+                write!(f, "設定に{}件の問題があります", problems.len())?;
+                for p in problems {
+                    write!(f, "\n  {}", p)?;
+                }
+                Ok(())
+            }
+            Error::Asset { ref name, ref msg } => {
+                write!(f, "アセット{}の読み込みに失敗 - {}", name, msg)
+            }
+            Error::Text { ref path, ref msg } => {
+                write!(f, "テキストファイル{}の読み込みに失敗 - {}", path.display(), msg)
+            }
+            Error::Replay { ref msg } => {
+                write!(f, "リプレイを再生できません - {}", msg)
+            }
+            Error::Io { ref path, ref err } => {
+                write!(f, "{}の読み書きに失敗 - {}", path.display(), err)
+            }
+            Error::Ggez(ref e) => {
+                write!(f, "ggezのエラー - {}", e)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Config { .. } => "config error",
+            Error::Invalid(_) => "invalid config",
+            Error::Asset { .. } => "asset error",
+            Error::Text { .. } => "text error",
+            Error::Replay { .. } => "replay error",
+            Error::Io { .. } => "io error",
+            Error::Ggez(_) => "ggez error",
+        }
+    }
+}
+
+impl From<GameError> for Error {
+    fn from(e: GameError) -> Self {
+        Error::Ggez(e)
+    }
+}
+
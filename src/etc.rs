@@ -11,18 +11,24 @@
 
   * unused_dir_remove(): ggezが自動生成するフォルダを削除
   * easy_path_set()    : cargo環境でも通常環境でも適応できるpathをセット
+  * now_date_string()  : 今の日時を"YYYY-MM-DD hh:mm"形式の文字列にする（UTC）
+  * format_time()      : 秒数を"mm:ss.cc"形式の文字列にする
+  * random_seed()      : シード未指定の際に使う、その場限りのシード値を作る
+  * seeded_rng()       : シード値から再現性のある乱数生成器を作る
   * random_x()         : 敵出現位置用の乱数を取るやつ
+  * fnv1a()            : バイト列から、どの環境でも同じになるハッシュ値を作る
 -------------------------------*/
 use std;
 use std::path::{ Path, PathBuf };
 use std::io::Result;
+use std::time::{ SystemTime, UNIX_EPOCH };
 
 // for File Read
 use std::io::{ BufReader, Read };
 
 // for ggez
 use ggez;
-use rand::{ self, Rng };
+use rand::{ self, Rng, SeedableRng, XorShiftRng };
 
 
 pub struct File;
@@ -70,6 +76,9 @@ impl File {
 
 #[allow(dead_code)]
 /// ggezが自動生成したフォルダを消去する
+///
+/// 消すのは空のフォルダだけ。ハイスコア表やリプレイが
+/// 保存されたユーザーデータフォルダは残る
 pub fn unused_dir_remove(ctx: &mut ggez::Context) -> ggez::GameResult<()> {
     let user_conf_dir_path = ctx.filesystem.get_user_config_dir();
     let user_data_dir_path = ctx.filesystem.get_user_data_dir();
@@ -107,10 +116,149 @@ pub fn easy_path_set<'a>(path_str: &'a str) -> PathBuf {
     path_base
 }
 
+/// 今の日時を"YYYY-MM-DD hh:mm"形式の文字列にする（UTC）
+pub fn now_date_string() -> String {
+    let secs = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs(),
+        // 時計が狂っていたら、とりあえず1970年扱い
+        Err(_) => 0,
+    };
+    
+    date_string(secs)
+}
+
+/// 内部用。UNIX時間の秒数を"YYYY-MM-DD hh:mm"形式の文字列にする
+fn date_string(secs: u64) -> String {
+    let days = secs / 86400;
+    let rem = secs % 86400;
+    
+    // 1970-01-01からの日数を年月日に直す
+    // 参考: Howard Hinnant "chrono-Compatible Low-Level Date Algorithms"
+    // 1970年より前は扱わないので、すべて符号なしのまま計算できる
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    
+    format!("{:04}-{:02}-{:02} {:02}:{:02}",
+            year, month, day, rem / 3600, rem % 3600 / 60)
+}
+
+/// 秒数を"mm:ss.cc"形式（分:秒.百分の一秒）の文字列にする
+///
+/// 百分の一秒未満は切り捨て。記録が実際より良く見えないように
+pub fn format_time(secs: f64) -> String {
+    let centis = (secs.max(0.0) * 100.0).floor() as u64;
+    
+    format!("{:02}:{:02}.{:02}",
+            centis / 6000, centis / 100 % 60, centis % 100)
+}
+
+/// シード値が指定されていない時用に、適当なシード値を生み出す
+pub fn random_seed() -> u64 {
+    rand::thread_rng().gen()
+}
+
+/// シード値から乱数生成器を作る
+///
+/// 同じシード値からは、どの環境でも同じ乱数列が出てくる
+pub fn seeded_rng(seed: u64) -> XorShiftRng {
+    // XorShiftRngは[u32; 4]のシードを取るので、
+    // splitmix64で二回かき混ぜてから四つに切り分ける
+    let a = splitmix64(seed);
+    let b = splitmix64(a);
+    let mut rng_seed = [
+        (a >> 32) as u32,
+        a as u32,
+        (b >> 32) as u32,
+        b as u32,
+    ];
+    
+    // 全部0だとXorShiftRngがpanic!するので、念のため回避
+    if rng_seed.iter().all(|&n| n == 0) {
+        rng_seed[0] = 0x9E37_79B9;
+    }
+    
+    XorShiftRng::from_seed(rng_seed)
+}
+
+/// 内部用。シード値をかき混ぜる
+fn splitmix64(n: u64) -> u64 {
+    let mut z = n.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
 /// ランダムな値xを生み出す
 /// 
-/// 使用例: `let x = random_x(800);`
-pub fn random_x(max_num: u32) -> f32 {
-    let out_n: u32 = rand::thread_rng().gen_range(0, max_num);
+/// 使用例: `let x = random_x(&mut rng, 800);`
+pub fn random_x<R: Rng>(rng: &mut R, max_num: u32) -> f32 {
+    let out_n: u32 = rng.gen_range(0, max_num);
     out_n as f32
 }
+
+/// バイト列`bytes`から、64bitのハッシュ値を作る（FNV-1a）
+///
+/// std::hashのハッシュ値はRustのバージョンで変わりうるので、
+/// ファイルに書き出して後で見比べる値にはこちらを使う
+pub fn fnv1a<'a>(bytes: &'a [u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn date_string_converts_unix_seconds() {
+        assert_eq!(date_string(0), "1970-01-01 00:00");
+        // うるう日と、世紀の境目
+        assert_eq!(date_string(951_782_400), "2000-02-29 00:00");
+        assert_eq!(date_string(1_709_251_199), "2024-02-29 23:59");
+        assert_eq!(date_string(4_102_492_020), "2100-01-01 13:07");
+    }
+
+    #[test]
+    fn format_time_truncates_to_centiseconds() {
+        assert_eq!(format_time(0.0), "00:00.00");
+        assert_eq!(format_time(1.999), "00:01.99");
+        assert_eq!(format_time(59.5), "00:59.50");
+        assert_eq!(format_time(61.25), "01:01.25");
+        assert_eq!(format_time(3599.99), "59:59.99");
+        // 60分を超えても分の桁を増やして表す
+        assert_eq!(format_time(3600.0 * 2.0 + 5.0), "120:05.00");
+        // 負の値は0扱い
+        assert_eq!(format_time(-3.0), "00:00.00");
+    }
+
+    #[test]
+    fn now_date_string_has_fixed_layout() {
+        let s = now_date_string();
+
+        assert_eq!(s.len(), "YYYY-MM-DD hh:mm".len());
+        for (i, c) in s.chars().enumerate() {
+            match i {
+                4 | 7 => assert_eq!(c, '-'),
+                10 => assert_eq!(c, ' '),
+                13 => assert_eq!(c, ':'),
+                _ => assert!(c.is_ascii_digit(), "{}", s),
+            }
+        }
+    }
+
+    #[test]
+    fn fnv1a_matches_reference_values() {
+        // FNV-1aの公開されている検証値
+        assert_eq!(fnv1a(b""), 0xCBF2_9CE4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xAF63_DC4C_8601_EC8C);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_F739_67E8);
+    }
+}
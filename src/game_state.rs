@@ -4,58 +4,126 @@
   ゲーム内システム進行についてを、GameStateの形でまとめる
   画面描画については別（view.rsを参照）
 
+  ggez::Contextには依存させず、ウィンドウ寸法とスプライト寸法だけで組み立てる。
+  tick()を呼べば、ウィンドウなしでも一フレームずつ進められる。
+
+  * struct SpriteInfo: スプライト画像の寸法と当たり判定。Assetsの代わりにこれを受け取る。
   * struct Player: プレイヤーキャラについて。
-  * struct Enemy : 敵キャラ
+  * struct Enemy : 敵キャラ。種類ごとの動き方はenemy.rsを参照
   * struct Template: clone()元になるテンプレート入れ
   * struct Actor :  意識を持つようにして動くもの。また今度別の場所に移したい。
   * struct System: ゲームシステムに影響を持つ変数はここに。
   
+  * impl SpriteInfo:
+    * new()
+
+  * impl Player:
+    * bounds()     : 自機の当たり判定全体を包む矩形
+    * prev_bounds(): 前のフレームでの、当たり判定全体を包む矩形
+
+  * impl Enemy:
+    * bounds()
+    * prev_bounds()
+    * sway_bounds(): これから揺れて動く範囲まで含めた、当たり判定を包む矩形
+    * sway_rect()  : これから揺れて動く範囲まで含めた、画像の矩形
+
   * impl Template:
     * new()
     
   * impl Actor: 
     * new()
     * reset()
-    * add_e_block()
+    * add_e_block(): 敵を一体追加する。回収済みの枠があれば使い回す
+    * e_block_width(): 種類ごとの敵の横幅
+    * live_enemies(): 回収済みでない敵を順に返す
+    * live_count()  : 回収済みでない敵の数
+    * has_room()    : もう一体出せるか
+    * dodged()      : 画面の下に抜けた（避けた）敵の数
 
   * impl System:
     * new()
     * reset()
+    * prev_elapsed(): 前のフレームでの耐久秒数
+    * crossed(): このフレームで、何秒ごとの区切りをまたいだか
   
   * impl GameState: ゲーム内システム進行について
     * new(): よくある初期化
-    * title_mode()
-    * game_over_mode()
     * game_reset() : リスタート用に一部変数を初期化
-    * main_game_system_loop(): メインゲームループを扱う
+    * tick()       : 入力スナップショットを受け取って、メインゲームを一フレーム進める
+    * speed_level(): 岩の速さが、waves.tomlの初期速度の何倍になったか。HUD表示用
+    * measure_time(): フレーム数を数えて、秒数を割り出す
+    * main_game_mode(): メインゲームループを扱う
     * player_move(): 自機移動についてのもろもろ
     * player_move_speed(): 現在の自機移動速度を出す
-    * player_collision_check(): 自機が画面外に出ないようにする（また今度当たり判定も取る）
+    * player_collision_check(): 自機が画面外に出ないようにする
     * enemy_move()
+    * enemy_lifecycle(): 位置に合わせて、敵を出現→画面内→画面外→回収と進める
+    * enemy_step_info(): 敵を一フレーム分動かすのに必要な値をまとめる
+    * spawn_enemy(): 敵を一体出して、種類ごとの初期値を決める
+    * plan_spawn() : 通り抜けられる隙間が残り、ほかの敵と重ならないように出現位置を決める
+    * respawn_enemy(): 回収した敵の代わりに、新しい種類で上に出し直す
     * enemy_move_speed()
     * enemy_move_speed_adjust()
     * enemy_collision_check()
-    * enemy_pop()
-    * enemy_pop_width()
+    * enemy_pop()  : 今の区切りの間隔ごとに、敵を一体増やす
+    * enemy_burst(): waves.tomlの[[burst]]の時刻に、敵をまとめて出す
+    * enemy_pop_range(): 種類ごとの、出現できる左右座標値の範囲
     * debug_key()
   
   * axis_move()   : アナログスティック操作変数を、画面描画に役立つ形に直す
   * key_move()    : 十字キー操作変数を、画面描画に役立つ形に直す
 -------------------------------*/ 
 
-use std::env;
-use std::thread;
+use std::rc::Rc;
 
-use ggez::{ Context, GameResult };
-use range_checker::{ Range2D, Range2DImpl };
+use rand::XorShiftRng;
 
-use assets;
+use collision::{ self, Aabb, SpatialGrid, DEFAULT_CELL_SIZE };
+use difficulty::Difficulty;
+use enemy::{ EnemyKind, EnemyState, StepInfo };
+use error::{ self, Error };
 use etc;
+use hitbox::{ self, Hitbox };
+use controls::Action;
 use input_state::InputState;
+use spawn_plan::{ self, Scratch, Ship, SpawnStats, MAX_LIFTS, MAX_REROLLS };
+use waves::Waves;
+
+/// 自機移動速度（ピクセル毎秒）。難易度の倍率を掛ける前の値
+pub const PLAYER_MOVE_SPEED: f32 = 360.0;
+/// 敵が出現する上下座標値。画面の少し上
+const ENEMY_SPAWN_Y: f32 = -50.0;
+/// 敵を回収する、画面下端からの距離
+const ENEMY_RECYCLE_MARGIN: f32 = 50.0;
+/// 同時に出せる敵の数の初期値。設定ファイルの[game_option] max_enemiesで変えられる
+pub const DEFAULT_MAX_ENEMIES: usize = 128;
+
+#[derive(Clone, Debug)]
+/// スプライト画像の寸法と当たり判定
+///
+/// GameStateをggez抜きで組み立てられるよう、Imageの代わりにこれを渡す
+pub struct SpriteInfo {
+    /// 画像横幅
+    pub width: u32,
+    /// 画像縦幅
+    pub height: u32,
+    /// 当たり判定の形
+    pub hitbox: Hitbox,
+}
+
+impl SpriteInfo {
+    pub fn new(width: u32, height: u32, hitbox: Hitbox) -> Self {
+        SpriteInfo {
+            width: width,
+            height: height,
+            hitbox: hitbox,
+        }
+    }
+}
 
 // また今度別ファイルに移行させたい
 // 今は簡易版として、とりあえず形だけ作る
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug)]
 /// 自機が保有する変数セット
 pub struct Player {
     /// 左右初期値
@@ -66,11 +134,27 @@ pub struct Player {
     pub width: u32,
     /// 自機画像縦幅
     pub height: u32,
-    /// 当たり判定
-    pub collision: Range2D<f32>
+    /// 前のフレームでの左右座標値。すり抜け判定用
+    pub prev_x: f32,
+    /// 前のフレームでの上下座標値
+    pub prev_y: f32,
+    /// 当たり判定。画像と同名のtomlファイルから読み込んだもの
+    pub hitbox: Rc<Hitbox>,
 }
 
-#[derive(Clone, Debug, Default)]
+impl Player {
+    /// 自機の当たり判定全体を包む矩形(x, y, w, h)
+    pub fn bounds(&self) -> Aabb {
+        self.hitbox.bounds_at(self.x, self.y)
+    }
+    
+    /// 前のフレームでの、当たり判定全体を包む矩形
+    pub fn prev_bounds(&self) -> Aabb {
+        self.hitbox.bounds_at(self.prev_x, self.prev_y)
+    }
+}
+
+#[derive(Clone, Debug)]
 /// 敵一体一体が保有する変数セット
 pub struct Enemy {
     /// 左右座標値
@@ -81,41 +165,116 @@ pub struct Enemy {
     pub width: u32,
     /// 画像縦幅
     pub height: u32,
-    // 当たり判定
-    pub collision: Range2D<f32>,
+    /// 前のフレームでの左右座標値。すり抜け判定用
+    pub prev_x: f32,
+    /// 前のフレームでの上下座標値
+    pub prev_y: f32,
+    /// 当たり判定。同じ種類の敵同士で使い回す
+    pub hitbox: Rc<Hitbox>,
+    /// 敵の種類。動き方と見た目が決まる
+    pub kind: EnemyKind,
+    /// 出現してからの秒数
+    pub age: f32,
+    /// 横移動速度（ピクセル毎秒）。左右に動く種類だけが使う
+    pub vx: f32,
+    /// 揺れの中心の左右座標値。出現位置で、ゆらゆら揺れる種類は画面の内側に寄せる
+    pub origin_x: f32,
+    /// 出現してから回収されるまでの、今の段階
+    pub state: EnemyState,
+}
+
+impl Enemy {
+    /// 敵の当たり判定全体を包む矩形(x, y, w, h)
+    pub fn bounds(&self) -> Aabb {
+        self.hitbox.bounds_at(self.x, self.y)
+    }
+    
+    /// 前のフレームでの、当たり判定全体を包む矩形
+    pub fn prev_bounds(&self) -> Aabb {
+        self.hitbox.bounds_at(self.prev_x, self.prev_y)
+    }
+    
+    /// これから揺れて動く範囲まで含めた、当たり判定全体を包む矩形
+    ///
+    /// 揺れる種類なら、揺れの中心から左右に揺れ幅の分だけ広げる
+    pub fn sway_bounds(&self) -> Aabb {
+        let sway = self.kind.behavior().sway();
+        
+        if sway > 0.0 {
+            spawn_plan::widen(&self.hitbox.bounds_at(self.origin_x, self.y), sway)
+        } else {
+            self.bounds()
+        }
+    }
+    
+    /// これから揺れて動く範囲まで含めた、画像の矩形
+    pub fn sway_rect(&self) -> Aabb {
+        let sway = self.kind.behavior().sway();
+        let x = if sway > 0.0 { self.origin_x } else { self.x };
+        
+        spawn_plan::widen(&(x, self.y, self.width as f32, self.height as f32), sway)
+    }
 }
 
 #[derive(Clone, Debug)]
 /// 敵追加のためのテンプレート
 pub struct Template {
     pub player: Player,
-    pub e_block: Enemy,
+    /// 種類ごとの敵。EnemyKind::all()の順に並ぶ
+    pub e_block: Vec<Enemy>,
 }
 
 impl Template {
     /// struct Templateを生成する
-    pub fn new(assets: &assets::Assets, 
-               system: &System) -> Self {
+    ///
+    /// `e_block_sprites`はEnemyKind::all()の順に並べること。
+    /// 種類の数と合わなければエラー
+    pub fn new(player_sprite: &SpriteInfo,
+               e_block_sprites: &[SpriteInfo],
+               system: &System) -> error::Result<Self> {
+        if e_block_sprites.len() != EnemyKind::all().len() {
+            return Err(Error::asset("enemy_block", format!(
+                "敵のスプライトは種類の数だけ必要です（種類: {}, スプライト: {}）",
+                EnemyKind::all().len(), e_block_sprites.len())));
+        }
+        
+        let (player_x, player_y) = (
+            (system.window_w - player_sprite.width) as f32 / 2_f32,
+            system.window_h as f32 * 0.7,
+        );
+        
         let player = Player {
-            x: (system.window_w - assets.player_ship.width()) as f32 / 2_f32,
-            y: system.window_h as f32 * 0.7,
-            width: assets.player_ship.width(),
-            height: assets.player_ship.height(),
-            collision: Range2D::default(),
+            x: player_x,
+            y: player_y,
+            width: player_sprite.width,
+            height: player_sprite.height,
+            prev_x: player_x,
+            prev_y: player_y,
+            hitbox: Rc::new(player_sprite.hitbox.clone()),
         };
         
-        let e_block = Enemy {
-            x: 0.0,
-            y: 0.0,
-            width: assets.enemy_block.width(),
-            height: assets.enemy_block.height(),
-            collision: Range2D::default(),
-        };
+        let e_block = EnemyKind::all().iter()
+            .zip(e_block_sprites)
+            .map(|(&kind, sprite)| Enemy {
+                x: 0.0,
+                y: 0.0,
+                width: sprite.width,
+                height: sprite.height,
+                prev_x: 0.0,
+                prev_y: 0.0,
+                hitbox: Rc::new(sprite.hitbox.clone()),
+                kind: kind,
+                age: 0.0,
+                vx: 0.0,
+                origin_x: 0.0,
+                state: EnemyState::Spawned,
+            })
+            .collect();
         
-        Template {
+        Ok(Template {
             player: player,
             e_block: e_block,
-        }
+        })
     }
 }
 
@@ -123,38 +282,94 @@ impl Template {
 /// 自機や敵など、動いてゲームに関わるアクター
 pub struct Actor {
     pub player: Player,
+    /// 敵のプール。回収済みの枠も含むので、普段はlive_enemies()を使う
     pub e_block: Vec<Enemy>,
-    template: Template 
+    /// 同時に出せる敵の数。プールがこれ以上大きくならない
+    pub max_enemies: usize,
+    template: Template,
+    /// 画面の下に抜けた（避けた）敵の数
+    dodged: usize,
 }
 
 impl Actor {
     /// struct Actorを生成する
-    pub fn new(assets: &assets::Assets,
-               system: &System) -> Self {        
+    pub fn new(player_sprite: &SpriteInfo,
+               e_block_sprites: &[SpriteInfo],
+               system: &System) -> error::Result<Self> {        
 
-        let template = Template::new(assets, system);
+        let template = Template::new(player_sprite, e_block_sprites, system)?;
         let player = template.player.clone();
         
-        Actor {
+        Ok(Actor {
             player: player,
             e_block: Vec::<Enemy>::new(),
+            max_enemies: DEFAULT_MAX_ENEMIES,
             template: template,    
-        }
+            dodged: 0,
+        })
     }
     
-    /// 敵を一体追加する
-    pub fn add_e_block(&mut self, x: f32, y: f32) {
-        let mut tmp_e = self.template.e_block.clone();
+    /// 種類`kind`の敵を一体追加して、プール上の番号を返す
+    ///
+    /// 回収済みの枠があればそこを使い回す。
+    /// 空き枠がなく、プールがmax_enemiesに達していれば追加せずにNone
+    ///
+    /// 種類ごとの初期値は決めないので、普段はGameState::spawn_enemy()を使う
+    pub fn add_e_block(&mut self, kind: EnemyKind, x: f32, y: f32) -> Option<usize> {
+        let mut tmp_e = self.template.e_block[kind.index()].clone();
         tmp_e.x = x;
         tmp_e.y = y;
+        // 出てきたばかりなので、前のフレームも同じ位置にいたことにする
+        tmp_e.prev_x = x;
+        tmp_e.prev_y = y;
+        tmp_e.origin_x = x;
         
-        self.e_block.push(tmp_e);
+        match self.e_block.iter().position(|e| !e.state.is_live()) {
+            Some(i) => {
+                self.e_block[i] = tmp_e;
+                Some(i)
+            }
+            None if self.e_block.len() < self.max_enemies => {
+                self.e_block.push(tmp_e);
+                Some(self.e_block.len() - 1)
+            }
+            None => None,
+        }
+    }
+    
+    /// 種類`kind`の敵の横幅
+    pub fn e_block_width(&self, kind: EnemyKind) -> u32 {
+        self.template.e_block[kind.index()].width
+    }
+    
+    /// 回収済みでない敵を順に返す
+    pub fn live_enemies<'a>(&'a self) -> impl Iterator<Item = &'a Enemy> + 'a {
+        self.e_block.iter().filter(|e| e.state.is_live())
+    }
+    
+    /// 回収済みでない敵の数
+    pub fn live_count(&self) -> usize {
+        self.live_enemies().count()
+    }
+    
+    /// プールに空き枠があるか、まだ大きくできて、もう一体出せるか
+    pub fn has_room(&self) -> bool {
+        self.e_block.len() < self.max_enemies
+            || self.e_block.iter().any(|e| !e.state.is_live())
+    }
+    
+    /// このゲームで、画面の下に抜けた（避けた）敵の数
+    pub fn dodged(&self) -> usize {
+        self.dodged
     }
     
     // ゲームシステムに関わる部分をリセット
+    //
+    // プールは空にするだけで、確保した分は次のゲームで使い回す
     fn reset(&mut self) {
         self.player = self.template.player.clone();
-        self.e_block = Vec::<Enemy>::new();
+        self.e_block.clear();
+        self.dodged = 0;
     }
 }
 
@@ -165,43 +380,65 @@ pub struct System {
     pub window_w: u32,
     /// ウィンドウサイズ縦幅
     pub window_h: u32,
+    /// 固定FPS数値。秒数の計算に使う
+    pub constant_fps: u32,
+    /// 一フレームの秒数（1 / constant_fps）
+    pub dt: f32,
+    /// 現在のゲームで使っているシード値
+    pub seed: u64,
     /// 耐久フレーム数
     pub frames: usize,
-    /// 耐久秒数
-    pub seconds: usize,
-    /// タイトル画面が表示されているか否か
-    pub is_title: bool,
-    /// ゲームオーバー画面が表示されているか否か
-    pub is_game_over: bool,
-    /// スコアが出力されたかどうか
-    pub is_score_wrote: bool,
-    /// 自機移動速度
+    /// 耐久秒数。スコアにもなる
+    pub elapsed: f64,
+    /// 自機が敵に衝突したか否か
+    pub is_crashed: bool,
+    /// 自機移動速度（ピクセル毎秒）
     pub player_move_speed: f32,
-    /// 敵移動速度
+    /// 敵移動速度（ピクセル毎秒）
     pub enemy_move_speed: f32,
+    /// 次に敵を一体増やす耐久秒数
+    pub next_pop: f64,
 }
 
 impl System {
-    fn new(ctx: &mut Context) -> System {
+    fn new(window_w: u32, window_h: u32, constant_fps: u32) -> System {
         System {
-            window_w: ctx.conf.window_mode.width,
-            window_h: ctx.conf.window_mode.height,
+            window_w: window_w,
+            window_h: window_h,
+            constant_fps: constant_fps,
+            dt: 1.0 / constant_fps as f32,
+            seed: 0,
             frames: 0,
-            seconds: 0,
-            is_title: true,
-            is_game_over: false,
-            is_score_wrote: false,
-            player_move_speed: 2.0,
-            enemy_move_speed: 1.0,
+            elapsed: 0.0,
+            is_crashed: false,
+            player_move_speed: PLAYER_MOVE_SPEED,
+            enemy_move_speed: 0.0,
+            next_pop: 0.0,
         }
     }
     
     // ゲームシステムに関わる部分をリセット
+    //
+    // 敵の速さと出現時刻はwaves.tomlで決まるので、GameState::game_reset()で
     fn reset(&mut self) {
+        // リプレイ再生で固定FPS数値が変わることがあるので、ここで計算し直す
+        self.dt = 1.0 / self.constant_fps as f32;
         self.frames = 0;
-        self.seconds = 0;
-        self.enemy_move_speed = 1.0;
-        self.is_score_wrote = false;
+        self.elapsed = 0.0;
+        self.is_crashed = false;
+    }
+    
+    /// 前のフレームでの耐久秒数
+    pub fn prev_elapsed(&self) -> f64 {
+        self.frames.saturating_sub(1) as f64 / self.constant_fps as f64
+    }
+    
+    /// このフレームで、`interval`秒の区切りを何回またいだか
+    ///
+    /// フレーム数から秒数を出しているので、FPSが違っても同じ時刻に反応する
+    pub fn crossed(&self, interval: f64) -> usize {
+        let prev = self.prev_elapsed();
+        ((self.elapsed / interval).floor() - (prev / interval).floor()) as usize
     }
 }
 
@@ -209,43 +446,72 @@ impl System {
 pub struct GameState {
     pub actor: Actor,
     pub system: System,
+    /// 敵出現位置を決める乱数生成器。game_reset()のたびに作り直す
+    pub rng: XorShiftRng,
+    /// 固定シード値。Noneならゲームごとに新しいシード値を使う
+    pub fixed_seed: Option<u64>,
+    /// デバッグキーで出す敵の位置を決める乱数生成器
+    ///
+    /// rngから引くとゲーム展開が変わってしまうので、別に持つ
+    debug_rng: XorShiftRng,
+    /// 当たり判定の絞り込み用マス目。毎フレーム中身を作り直して使い回す
+    grid: SpatialGrid,
+    /// デバッグ用。trueなら岩に当たってもゲームオーバーにならない
+    pub invincible: bool,
+    /// 岩の出方と速さの上がり方。waves.tomlから読み込んだもの
+    ///
+    /// 差し替えた後は、game_reset()を呼ぶまで開始時の速さに反映されない
+    pub waves: Waves,
+    /// 難易度。waves.tomlの値と自機の速さ・当たり判定に倍率を掛ける
+    ///
+    /// 変えた後は、game_reset()を呼ぶまで反映されない
+    pub difficulty: Difficulty,
+    /// 出現位置を選び直した回数の集計。game_reset()で空に戻る
+    pub spawn_stats: SpawnStats,
+    /// 出現位置を調べる時に使うVec。岩を出すたびに中身を作り直して使い回す
+    spawn_scratch: Scratch,
 }
 
 impl GameState {
-    pub fn new(ctx: &mut Context, assets: &assets::Assets) -> GameState {
-        let system = System::new(ctx);
-        let actor = Actor::new(assets, &system);
-        GameState {
+    /// ウィンドウ寸法とスプライト寸法からGameStateを生成する
+    ///
+    /// ggez::Contextは不要なので、ウィンドウなしのシミュレーションにも使える
+    ///
+    /// 敵のスプライトは、EnemyKind::all()の順に種類の数だけ並べる。
+    /// 数が合わなければエラー
+    ///
+    /// `seed`を指定すると、同じ入力からは毎回同じゲーム展開になる
+    pub fn new(window_w: u32,
+               window_h: u32,
+               constant_fps: u32,
+               player_sprite: SpriteInfo,
+               e_block_sprites: Vec<SpriteInfo>,
+               seed: Option<u64>) -> error::Result<GameState> {
+        let system = System::new(window_w, window_h, constant_fps);
+        let actor = Actor::new(&player_sprite, &e_block_sprites, &system)?;
+        
+        // 画面の上下で折り返す岩も入るよう、上下に一マスずつ余裕を持たせる
+        let grid = SpatialGrid::new(
+            DEFAULT_CELL_SIZE,
+            0.0,
+            -DEFAULT_CELL_SIZE,
+            window_w as f32,
+            window_h as f32 + DEFAULT_CELL_SIZE * 2.0,
+        );
+        
+        Ok(GameState {
             actor: actor,
             system: system,
-        }
-    }
-    
-    /// タイトル画面を管理
-    pub fn title_mode(&mut self,
-                      input: &mut InputState) {
-        if input.any_key {
-            self.system.is_title = false;
-        }
-    }
-    /// ゲームオーバー時の画面を管理
-    pub fn game_over_mode(&mut self,
-                          ctx: &mut Context,
-                          input: &mut InputState) {
-        if input.game_reset {
-            // ゲームを再度はじめる
-            self.game_reset();
-            self.system.is_game_over = false;
-        } else if input.game_title {
-            // タイトル画面へ
-            self.game_reset();
-            self.system.is_game_over = false;
-            self.system.is_title = true;
-            input.reset();
-        } else if input.game_quit {
-            // ゲーム終了
-            ctx.quit().expect("ゲーム終了時のエラー");
-        }
+            rng: etc::seeded_rng(0),
+            fixed_seed: seed,
+            debug_rng: etc::seeded_rng(etc::random_seed()),
+            grid: grid,
+            invincible: false,
+            waves: Waves::default(),
+            difficulty: Difficulty::default(),
+            spawn_stats: SpawnStats::default(),
+            spawn_scratch: Scratch::default(),
+        })
     }
     
     /// ゲーム状態を初期化する
@@ -255,19 +521,81 @@ impl GameState {
         
         // struct Actor の初期化
         self.actor.reset();
+        
+        // 乱数生成器をシード値から作り直す
+        // 固定シード値がなければ、毎回違うシード値を使う
+        let seed = match self.fixed_seed {
+            Some(n) => n,
+            None => etc::random_seed(),
+        };
+        self.system.seed = seed;
+        self.rng = etc::seeded_rng(seed);
+        self.spawn_stats = SpawnStats::default();
+        
+        // 自機の速さと当たり判定は、難易度の倍率を掛けて決める
+        // 当たり判定はテンプレートの元の形から作るので、倍率が重ならない
+        let scale = self.difficulty.scale();
+        self.system.player_move_speed = PLAYER_MOVE_SPEED * scale.player_speed;
+        if scale.hitbox != 1.0 {
+            self.actor.player.hitbox =
+                Rc::new(self.actor.template.player.hitbox.scaled(scale.hitbox));
+        }
+        
+        // 敵の速さと最初の出現時刻は、waves.tomlの最初の区切りから
+        let mut start_speed = self.waves.start_speed * scale.start_speed;
+        if let Some(max) = self.waves.max_speed {
+            start_speed = start_speed.min(max);
+        }
+        self.system.enemy_move_speed = start_speed;
+        self.system.next_pop = self.waves.wave_at(0.0).spawn_interval * scale.spawn_interval;
 
         // 敵キャラを一体出しておく
-        let tmp_n = self.enemy_pop_width();
-        self.actor.add_e_block(
-            etc::random_x(tmp_n), 
-            -50.0,
-        );
+        let kind = self.waves.wave_at(0.0).pick(&mut self.rng);
+        if let Some((tmp_x, tmp_y)) = self.plan_spawn(kind) {
+            self.spawn_enemy(kind, tmp_x, tmp_y);
+        }
         
         // タイトル画面を実装したら、タイトル画面は省く処理が必要
     }
     
+    /// 入力スナップショットを受け取って、メインゲームを一フレーム進める
+    ///
+    /// 自機が衝突した後に呼んでも何もしない
+    pub fn tick(&mut self, input: &InputState) {
+        if self.system.is_crashed {
+            return;
+        }
+        
+        // フレーム数を計測して、時間を割り出す
+        self.measure_time();
+        
+        // メインのゲーム画面
+        self.main_game_mode(input);
+    }
+    
+    /// 岩の速さが、waves.tomlの初期速度の何倍になったか。HUD表示用
+    ///
+    /// 難易度の倍率を掛ける前の速さと比べるので、難しいほど大きな値から始まる
+    pub fn speed_level(&self) -> f32 {
+        if self.waves.start_speed > 0.0 {
+            self.system.enemy_move_speed / self.waves.start_speed
+        } else {
+            1.0
+        }
+    }
+    
+    // 計測するフレーム数から、秒数を計測する
+    fn measure_time(&mut self) {
+        // 常にフレーム数を計測
+        self.system.frames += 1;
+        
+        // 足し込んでいくと誤差が溜まるので、毎回フレーム数から割り出す
+        self.system.elapsed =
+            self.system.frames as f64 / self.system.constant_fps as f64;
+    }
+    
     /// メインのゲーム画面を管理するやつ 
-    pub fn main_game_mode(&mut self, input: &mut InputState) -> GameResult<()> {
+    pub fn main_game_mode(&mut self, input: &InputState) {
         // 自機移動
         self.player_move(input);
         // 自機が画面外に出ないようにチェック
@@ -276,19 +604,18 @@ impl GameState {
         self.enemy_move();
         // 敵を増殖
         self.enemy_pop();
+        self.enemy_burst();
         // 現状ではプレイヤーの当たり判定を見る
         self.enemy_collision_check();
-        
-        if env::var("GAME_ACTIVATE_MODE").unwrap() == "DEBUG_MODE" {
-            self.debug_key(input);
-        }
-        
-        Ok(())
     }
     
     /// 自機移動をまとめる関数
     fn player_move(&mut self, input: &InputState) {
         // アナログスティック処理のため、tmp変数にx,y値を入れる
+        // 動く前の位置を、すり抜け判定用に覚えておく
+        self.actor.player.prev_x = self.actor.player.x;
+        self.actor.player.prev_y = self.actor.player.y;
+        
         let (mut tmp_x, mut tmp_y) = if input.axis_lx != 0 || input.axis_ly != 0 {
             (axis_move(input.axis_lx), axis_move(input.axis_ly))
         } else {
@@ -323,9 +650,10 @@ impl GameState {
             ) * tmp_speed_n
         }
         
-        // 移動値を足して完成
-        self.actor.player.x += tmp_x * self.player_move_speed(input);
-        self.actor.player.y += tmp_y * self.player_move_speed(input);
+        // 移動値を足して完成。速度は毎秒なので、一フレーム分に直す
+        let speed = self.player_move_speed(input) * self.system.dt;
+        self.actor.player.x += tmp_x * speed;
+        self.actor.player.y += tmp_y * speed;
     }
     
     /// 自機移動速度を調整する関数
@@ -337,7 +665,7 @@ impl GameState {
             1.0
         };
         
-        3.0 * self.system.player_move_speed * slow_buff
+        self.system.player_move_speed * slow_buff
     }
     
     /// 自機が画面外に出ないようにチェック
@@ -361,54 +689,174 @@ impl GameState {
         } else {
             self.actor.player.y = 0_f32;
         }
-        
-        // 自機当たり判定を代入
-        self.actor.player.collision = Range2D::new(
-            self.actor.player.x + 10.0,
-            self.actor.player.y + 22.0,
-            (self.actor.player.width - 20) as f32,
-            (self.actor.player.height - 35) as f32,
-        );
     }
     
-    /// 敵の移動を自動で行い、コリジョン値を指定する
+    /// 敵の移動を自動で行う
+    ///
+    /// 動き方は種類ごとにenemy.rsのEnemyBehaviorへ任せる
     fn enemy_move(&mut self) {
         self.enemy_move_speed_adjust();
         
-        // e_blockたちの表示座標を動かす
+        let info = self.enemy_step_info();
+        
+        let mut recycled = 0;
+        
+        // e_blockたちの表示座標を動かす。回収済みの枠は飛ばす
         for i in 0..self.actor.e_block.len() {
-            self.actor.e_block[i].y += 1.0 * self.enemy_move_speed();
-            // 画面外の一定地点に移動したら、上へとループさせる
-            if self.actor.e_block[i].y >= (self.system.window_h + 50) as f32 {
-                self.actor.e_block[i].x = etc::random_x(
-                    self.system.window_w - self.actor.template.e_block.width
-                );
-                self.actor.e_block[i].y = -50.0;
+            {
+                let e = &mut self.actor.e_block[i];
+                
+                if !e.state.is_live() {
+                    continue;
+                }
+                
+                // 動く前の位置を、すり抜け判定用に覚えておく
+                e.prev_x = e.x;
+                e.prev_y = e.y;
+                
+                e.age += info.dt;
+                e.kind.behavior().step(e, &info);
             }
             
-            // e_blockのコリジョンを追加（雑なやり方）
-            self.actor.e_block[i].collision = Range2D::new(
-                self.actor.e_block[i].x,
-                self.actor.e_block[i].y,
-                self.actor.e_block[i].width as f32,
-                self.actor.e_block[i].height as f32,
-            );
+            if self.enemy_lifecycle(i) {
+                recycled += 1;
+            }
         }// end for
+        
+        // 回収した数だけ、代わりを上に出す
+        // 全部動かし終えてからにすると、これから動く岩と重なって出ることがない
+        for _ in 0..recycled {
+            self.respawn_enemy();
+        }
+    }
+    
+    /// `i`番目の敵の段階を、今の位置に合わせて進める
+    ///
+    /// 画面の下に抜けたら避けた数に数えて、さらに一定距離まで抜けたら回収する。
+    /// 回収したらtrue
+    fn enemy_lifecycle(&mut self, i: usize) -> bool {
+        let window_h = self.system.window_h as f32;
+        let e = &mut self.actor.e_block[i];
+        
+        match e.state {
+            EnemyState::Spawned if e.y + e.height as f32 > 0.0 => {
+                e.state = EnemyState::Active;
+            }
+            EnemyState::Active if e.y >= window_h => {
+                e.state = EnemyState::Offscreen;
+                self.actor.dodged += 1;
+            }
+            EnemyState::Offscreen if e.y >= window_h + ENEMY_RECYCLE_MARGIN => {
+                e.state = EnemyState::Recycled;
+                return true;
+            }
+            _ => (),
+        }
+        
+        false
+    }
+    
+    /// 敵を一フレーム分動かすのに必要な値をまとめる
+    fn enemy_step_info(&self) -> StepInfo {
+        StepInfo {
+            speed: self.enemy_move_speed(),
+            dt: self.system.dt,
+            window_w: self.system.window_w as f32,
+        }
+    }
+    
+    /// 種類`kind`の敵を(x, y)に一体出して、種類ごとの初期値を決める
+    ///
+    /// プールに空きがなければ出さずにNone。出したらプール上の番号
+    pub fn spawn_enemy(&mut self, kind: EnemyKind, x: f32, y: f32) -> Option<usize> {
+        let info = self.enemy_step_info();
+        let i = self.actor.add_e_block(kind, x, y)?;
+        
+        kind.behavior().init(&mut self.actor.e_block[i], &mut self.rng, &info);
+        
+        Some(i)
+    }
+    
+    /// 種類`kind`の敵の出現位置(x, y)を、通り抜けられる隙間が残り、
+    /// ほかの岩とも重ならないように決める
+    ///
+    /// 隙間がなくなる位置やほかの岩と重なる位置なら、乱数で選び直す。
+    /// 何度選び直しても駄目なら一列分上にずらして、
+    /// それでも置ける位置が見つからなければNone
+    ///
+    /// 左右に揺れる種類は、揺れ幅の分だけ広げた範囲で調べる
+    fn plan_spawn(&mut self, kind: EnemyKind) -> Option<(f32, f32)> {
+        let (pop_left, tmp_n) = self.enemy_pop_range(kind);
+        let sway = kind.behavior().sway();
+        let (hitbox, sprite_w, sprite_h) = {
+            let tmp_e = &self.actor.template.e_block[kind.index()];
+            (tmp_e.hitbox.clone(), tmp_e.width as f32, tmp_e.height as f32)
+        };
+        let ship = Ship {
+            bounds: self.actor.player.bounds(),
+            speed: self.system.player_move_speed,
+        };
+        let (speed, window_w) = (self.enemy_move_speed(), self.system.window_w as f32);
+        
+        let scratch = &mut self.spawn_scratch;
+        scratch.rocks.clear();
+        scratch.rocks.extend(self.actor.live_enemies().map(|e| e.sway_bounds()));
+        // 重なりは、見た目で重ならないよう画像の矩形で調べる
+        scratch.sprites.clear();
+        scratch.sprites.extend(self.actor.live_enemies().map(|e| e.sway_rect()));
+        
+        // 一列分は、岩の高さに自機が通れる高さを足したもの
+        let lift_h = hitbox.bounds_at(0.0, 0.0).3 + ship.bounds.3;
+        let mut rerolls = 0;
+        
+        for lift in 0..(MAX_LIFTS + 1) {
+            let tmp_y = ENEMY_SPAWN_Y - lift as f32 * lift_h;
+            
+            for _ in 0..(MAX_REROLLS + 1) {
+                let tmp_x = pop_left + etc::random_x(&mut self.rng, tmp_n);
+                let sprite = spawn_plan::widen(&(tmp_x, tmp_y, sprite_w, sprite_h), sway);
+                let candidate = spawn_plan::widen(&hitbox.bounds_at(tmp_x, tmp_y), sway);
+                
+                if !spawn_plan::overlaps_any(&scratch.sprites, &sprite)
+                    && spawn_plan::is_fair(&scratch.rocks, &candidate, &ship, speed, window_w,
+                                           &mut scratch.spans) {
+                    self.spawn_stats.record(rerolls, lift > 0);
+                    return Some((tmp_x, tmp_y));
+                }
+                
+                rerolls += 1;
+            }
+        }
+        
+        // どこに出しても隙間が残らない。壁を作るよりは、出さずにおく
+        self.spawn_stats.block(rerolls);
+        None
+    }
+    
+    /// 回収した敵の代わりに、今の経過時間に合った種類の敵を上に出す
+    ///
+    /// 回収した枠が空いているので、プールは大きくならない。
+    /// 置ける場所がなければ出さずに、空いた枠は次に敵を増やす時に使う
+    fn respawn_enemy(&mut self) {
+        let kind = self.waves.wave_at(self.system.elapsed).pick(&mut self.rng);
+        
+        if let Some((tmp_x, tmp_y)) = self.plan_spawn(kind) {
+            self.spawn_enemy(kind, tmp_x, tmp_y);
+        }
     }
     
     /// 徐々に敵速度を上昇させていく関数
     fn enemy_move_speed_adjust(&mut self) {
-        // 一定期間に一回判定するためのboolを作る
-        // 一秒一回だと若干速度上昇が露骨なので、0.5秒に一回
-        let is_speed_up = self.system.frames % 30 == 0; 
+        // 上昇量は今の区切りのもの。はじめは早く、それ以降はゆっくりにしてある
+        // 毎秒の上昇量に経過時間を掛けるので、FPSによらず同じ速さで上がる
+        // このフレームの始まりの時刻で区切りを選ぶと、区切りの変わり目もずれない
+        let accel = self.waves.wave_at(self.system.prev_elapsed()).accel
+            * self.difficulty.scale().accel;
+        
+        self.system.enemy_move_speed += accel * self.system.dt;
         
-        // はじめは早く速度上昇させて、それ移行はゆっくりに
-        if is_speed_up && self.system.enemy_move_speed < 5.0 {
-            self.system.enemy_move_speed += 0.1;
-        } else if is_speed_up && self.system.enemy_move_speed < 7.0 {
-            self.system.enemy_move_speed += 0.05;
-        } else if is_speed_up {
-            self.system.enemy_move_speed += 0.025
+        if let Some(max) = self.waves.max_speed {
+            self.system.enemy_move_speed = self.system.enemy_move_speed.min(max);
         }
     }
     
@@ -418,54 +866,116 @@ impl GameState {
     }
     
     /// 敵を一定間隔ごとに増やす
+    ///
+    /// 間隔と種類は、その時の区切り（waves.tomlの[[wave]]）のものを使う。
+    /// 間隔には難易度の倍率を掛ける
     fn enemy_pop(&mut self) {
-        // 今はとりあえず、4秒ごとに敵を1体増やす
-        if self.system.frames % 240 == 0 {
-            let tmp_n = self.enemy_pop_width();
-            self.actor.add_e_block(
-                etc::random_x(tmp_n),
-                -50.0,
-            );
+        let interval_scale = self.difficulty.scale().spawn_interval;
+        
+        while self.system.elapsed >= self.system.next_pop {
+            let (kind, interval) = {
+                let wave = self.waves.wave_at(self.system.next_pop);
+                (wave.pick(&mut self.rng), wave.spawn_interval * interval_scale)
+            };
+            
+            // プールが満杯なら、出す時刻だけ進める
+            if self.actor.has_room() {
+                if let Some((tmp_x, tmp_y)) = self.plan_spawn(kind) {
+                    self.spawn_enemy(kind, tmp_x, tmp_y);
+                }
+            }
+            
+            self.system.next_pop += interval;
         }
     }
     
-    fn enemy_pop_width(&self) -> u32 {
-        self.system.window_w - self.actor.template.e_block.width
+    /// waves.tomlの[[burst]]の時刻を迎えたら、敵をまとめて出す
+    fn enemy_burst(&mut self) {
+        let (prev, now) = (self.system.prev_elapsed(), self.system.elapsed);
+        let mut tmp_kinds = Vec::new();
+        
+        for burst in &self.waves.bursts {
+            for _ in 0..burst.crossed(prev, now) * burst.count as usize {
+                // 種類の指定がなければ、今の区切りから選ぶ
+                let kind = match burst.kind {
+                    Some(k) => k,
+                    None => self.waves.wave_at(now).pick(&mut self.rng),
+                };
+                tmp_kinds.push(kind);
+            }
+        }
+        
+        // 一体ずつ出すので、後の岩は先に出した岩も避けて位置を決める
+        for kind in tmp_kinds {
+            if !self.actor.has_room() {
+                break;
+            }
+            
+            if let Some((tmp_x, tmp_y)) = self.plan_spawn(kind) {
+                self.spawn_enemy(kind, tmp_x, tmp_y);
+            }
+        }
+    }
+    
+    /// 種類`kind`の敵が、揺れても画面からはみ出さずに出現できる左右座標値の範囲
+    ///
+    /// (左端, 幅)の形。左端から幅の分までのどこかに出す
+    fn enemy_pop_range(&self, kind: EnemyKind) -> (f32, u32) {
+        let sway = kind.behavior().sway();
+        let width = self.system.window_w as f32
+            - self.actor.e_block_width(kind) as f32
+            - sway * 2.0;
+        
+        // 幅が0だと乱数が取れないので、狭すぎる画面でも1は残す
+        (sway, (width as u32).max(1))
     }
     
     /// 敵の当たり判定処理
+    ///
+    /// マス目で自機の近くにいる敵だけに絞り込んでから、細かく調べる
+    ///
+    /// 速い岩が一フレームで自機を飛び越えてしまわないよう、
+    /// 前のフレームから今のフレームまでの移動範囲全体で判定する
     fn enemy_collision_check(&mut self) {
-        // thread移行させるため安易にclone()
-        let e_block_vec = self.actor.e_block.clone();
-        let p_collision = self.actor.player.collision.clone();
+        let e_block = &self.actor.e_block;
+        let player = &self.actor.player;
         
-        let is_crash = thread::spawn(move || {
-            let mut out_bool = false;
-            
-            for i in 0..e_block_vec.len() {
-                if e_block_vec[i].collision.is_overlap(&p_collision) {
-                    out_bool = true;
-                }
-            }
-            
-            out_bool
-        }).join().expect("is_crash handle開封時エラー");
+        // マス目には、前の位置から今の位置までを包む矩形を入れる
+        self.grid.rebuild(e_block.iter().map(|e| {
+            collision::union(&e.prev_bounds(), &e.bounds())
+        }));
+        
+        let (qx, qy, qw, qh) = collision::union(&player.prev_bounds(), &player.bounds());
+        
+        // 絞り込んだ敵について、各スプライトの当たり判定の形で調べる
+        // 回収済みの枠は、番号を揃えるためにマス目には入れてあるが、ここで飛ばす
+        let is_crash = self.grid.query(qx, qy, qw, qh, |i| {
+            let e = &e_block[i];
+            e.state.is_live() && hitbox::swept_overlap(&player.hitbox,
+                                  (player.prev_x, player.prev_y),
+                                  (player.x, player.y),
+                                  &e.hitbox,
+                                  (e.prev_x, e.prev_y),
+                                  (e.x, e.y))
+        });
         
         // この部分に衝突時の内容を書き加える
-        if is_crash {
+        if is_crash && !self.invincible {
             // println!("{}, クラッシュ！", self.system.frames);
-            self.system.is_game_over = true;
+            self.system.is_crashed = true;
         }
     }
     
     /// デバッグ用のキー。用意しておいて、適当に書き換えて使う。
-    fn debug_key(&mut self, input: &mut InputState) {
-        if input.key_m {
-            input.key_m = false;
-            self.actor.add_e_block(
-                etc::random_x(self.system.window_w),
-                0.0,
-            );
+    ///
+    /// 乱数はdebug_rngから引くので、rngの乱数列はずれない。
+    /// ただ岩が増えた分ゲーム展開は変わるので、リプレイ再生中には呼ばないこと
+    pub fn debug_key(&mut self, input: &InputState) {
+        if input.pressed(Action::Debug) {
+            let kind = EnemyKind::Block;
+            let (pop_left, tmp_n) = self.enemy_pop_range(kind);
+            let tmp_x = pop_left + etc::random_x(&mut self.debug_rng, tmp_n);
+            self.spawn_enemy(kind, tmp_x, 0.0);
         }
     }
 }
@@ -499,3 +1009,295 @@ fn axis_move(axis_value: i16) -> f32 {
     
     out_n
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 画像ファイル名の寸法に合わせた、種類ごとの敵のスプライト
+    fn enemy_sprites() -> Vec<SpriteInfo> {
+        let enemy_sizes = [(32, 32), (24, 24), (28, 28), (28, 20), (20, 36), (48, 48)];
+        assert_eq!(enemy_sizes.len(), EnemyKind::all().len());
+
+        enemy_sizes.iter()
+            .map(|&(w, h)| SpriteInfo::new(w, h, Hitbox::full(w, h)))
+            .collect()
+    }
+
+    /// 画像ファイル名の寸法に合わせたスプライトで、ウィンドウなしのGameStateを作る
+    fn headless_state(seed: u64) -> GameState {
+        let mut game_state = GameState::new(
+            360,
+            480,
+            60,
+            SpriteInfo::new(29, 48, Hitbox::full(29, 48)),
+            enemy_sprites(),
+            Some(seed),
+        ).unwrap();
+        game_state.game_reset();
+
+        game_state
+    }
+
+    /// 決めた操作で`frames`フレーム進めて、敵の位置と段階・フレーム数・衝突したかを返す
+    fn run_scripted(seed: u64,
+                    frames: usize,
+                    invincible: bool) -> (Vec<(f32, f32, EnemyState)>, usize, bool) {
+        let mut game_state = headless_state(seed);
+        game_state.invincible = invincible;
+        let mut input = InputState::new();
+
+        for i in 0..frames {
+            input.move_left = (i / 40) % 3 == 0;
+            input.move_right = (i / 40) % 3 == 1;
+            input.speed_down = i % 70 < 15;
+            input.axis_lx = ((i * 37) % 2000) as i16 - 1000;
+            game_state.tick(&input);
+        }
+
+        let enemies = game_state.actor.e_block.iter().map(|e| (e.x, e.y, e.state)).collect();
+
+        (enemies, game_state.system.frames, game_state.system.is_crashed)
+    }
+
+    #[test]
+    fn headless_ticks_count_frames_and_elapsed_time() {
+        let mut game_state = headless_state(1);
+        let input = InputState::new();
+
+        for _ in 0..120 {
+            game_state.tick(&input);
+        }
+
+        assert_eq!(game_state.system.frames, 120);
+        assert_eq!(game_state.system.elapsed, 2.0);
+        assert!(!game_state.system.is_crashed);
+        // 最初の一体は画面上から落ちてきている
+        assert!(game_state.actor.e_block[0].y > -50.0);
+    }
+
+    #[test]
+    fn headless_crash_stops_ticks() {
+        let mut game_state = headless_state(1);
+        let input = InputState::new();
+
+        // 自機の真上に敵を置いて、一フレーム進める
+        let (x, y) = (game_state.actor.player.x, game_state.actor.player.y);
+        game_state.actor.add_e_block(EnemyKind::Block, x, y);
+        game_state.tick(&input);
+
+        assert!(game_state.system.is_crashed);
+        let frames = game_state.system.frames;
+
+        // 衝突した後はtick()を呼んでも進まない
+        game_state.tick(&input);
+        assert_eq!(game_state.system.frames, frames);
+    }
+
+    #[test]
+    fn headless_ticks_are_deterministic() {
+        let first = run_scripted(1, 60 * 60, false);
+        let second = run_scripted(1, 60 * 60, false);
+
+        assert!(first.0.len() > 1);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn headless_long_runs_are_deterministic() {
+        // 無敵にして、岩が抜けては回収されるところまで進める
+        let first = run_scripted(7, 60 * 120, true);
+        let second = run_scripted(7, 60 * 120, true);
+
+        assert_eq!(first.1, 60 * 120);
+        assert!(first.0.len() > 10);
+        assert!(first.0.len() <= DEFAULT_MAX_ENEMIES);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn recycled_slots_are_reused_before_the_pool_grows() {
+        let mut game_state = headless_state(3);
+        game_state.invincible = true;
+        game_state.actor.max_enemies = 4;
+        let input = InputState::new();
+
+        for _ in 0..(60 * 120) {
+            game_state.tick(&input);
+            assert!(game_state.actor.e_block.len() <= 4);
+        }
+
+        // 抜けた岩は避けた数に数えて、枠は使い回している
+        assert!(game_state.actor.dodged() > 4);
+        assert!(game_state.actor.live_count() <= 4);
+    }
+
+    #[test]
+    fn headless_seeds_change_spawns() {
+        let a = headless_state(1);
+        let b = headless_state(2);
+
+        let first_x = |g: &GameState| g.actor.e_block.iter().map(|e| e.x).collect::<Vec<_>>();
+        assert_ne!(first_x(&a), first_x(&b));
+    }
+
+    #[test]
+    fn debug_key_does_not_touch_gameplay_rng() {
+        use ggez::event::{ Keycode, Mod };
+        use rand::Rng;
+
+        use conf::Controls;
+        use controls::Bindings;
+
+        let mut a = headless_state(5);
+        let mut b = headless_state(5);
+
+        // デバッグキー（初期値はM）を押したフレーム
+        let bindings = Bindings::new(&Controls::default()).unwrap();
+        let mut input = InputState::new();
+        input.key_press(&bindings, Keycode::M, Mod::empty());
+        input.begin_tick();
+        a.debug_key(&input);
+
+        assert_eq!(a.actor.e_block.len(), b.actor.e_block.len() + 1);
+        // 岩の出現位置に使う乱数列はずれていない
+        assert_eq!(a.rng.gen::<u64>(), b.rng.gen::<u64>());
+    }
+
+    #[test]
+    fn sprite_count_must_match_enemy_kinds() {
+        let result = GameState::new(
+            360,
+            480,
+            60,
+            SpriteInfo::new(29, 48, Hitbox::full(29, 48)),
+            vec![SpriteInfo::new(32, 32, Hitbox::full(32, 32))],
+            None,
+        );
+
+        match result {
+            Err(Error::Asset { ref name, .. }) => assert_eq!(name, "enemy_block"),
+            Err(e) => panic!("Error::Assetになっていない: {}", e),
+            Ok(_) => panic!("スプライトが足りないのに作れてしまった"),
+        }
+    }
+
+    #[test]
+    fn swaying_rocks_stay_on_screen_from_any_spawn_x() {
+        let mut game_state = headless_state(3);
+
+        for &kind in EnemyKind::all() {
+            let sway = kind.behavior().sway();
+            let (pop_left, tmp_n) = game_state.enemy_pop_range(kind);
+            assert_eq!(pop_left, sway);
+
+            // 出現範囲の両端から、3秒分動かしてみる
+            for &x in &[pop_left, pop_left + tmp_n as f32] {
+                game_state.actor.e_block.clear();
+                game_state.spawn_enemy(kind, x, 0.0);
+                let info = game_state.enemy_step_info();
+
+                for _ in 0..180 {
+                    let e = &mut game_state.actor.e_block[0];
+                    e.age += info.dt;
+                    kind.behavior().step(e, &info);
+
+                    // 揺れる種類は、画面端で跳ね返らずに揺れ幅の中に収まる
+                    if sway > 0.0 {
+                        assert!(e.x >= 0.0, "{:?}: x = {}", kind, e.x);
+                        assert!(e.x + e.width as f32 <= 360.0, "{:?}: x = {}", kind, e.x);
+                        assert!((e.x - x).abs() <= sway + 0.01, "{:?}: x = {}", kind, e.x);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn enemy_speed_stops_at_max_speed() {
+        let mut game_state = headless_state(4);
+        game_state.invincible = true;
+        game_state.waves = Waves::from_str(
+            "start_speed = 60.0\nmax_speed = 70.0\n\
+             [[wave]]\nstart = 0.0\nspawn_interval = 100.0\naccel = 12.0\n\
+             kinds = { block = 1 }\n").unwrap();
+        game_state.game_reset();
+
+        let input = InputState::new();
+        let mut speeds = Vec::new();
+        for _ in 0..120 {
+            game_state.tick(&input);
+            speeds.push(game_state.system.enemy_move_speed);
+        }
+
+        // 12/秒で上がって、1秒弱で上限に着いたらそこで止まる
+        assert!(speeds[30] > 60.0 && speeds[30] < 70.0, "{}", speeds[30]);
+        assert_eq!(*speeds.last().unwrap(), 70.0);
+        assert!(speeds.iter().all(|&v| v <= 70.0));
+    }
+
+    #[test]
+    fn fully_blocked_row_spawns_nothing() {
+        let mut game_state = headless_state(9);
+        game_state.actor.e_block.clear();
+
+        // 画面の幅いっぱいで、一列分ずつ上にずらした位置まで全部ふさぐ岩
+        let mut wall = game_state.actor.template.e_block[EnemyKind::Block.index()].clone();
+        wall.hitbox = Rc::new(Hitbox::full(360, 2000));
+        wall.x = 0.0;
+        wall.y = ENEMY_SPAWN_Y - 1000.0;
+        game_state.actor.e_block.push(wall);
+
+        let before = game_state.spawn_stats;
+        assert_eq!(game_state.plan_spawn(EnemyKind::Block), None);
+
+        let after = game_state.spawn_stats;
+        assert_eq!(after.spawns, before.spawns);
+        assert_eq!(after.blocked, before.blocked + 1);
+        assert_eq!(after.rerolls - before.rerolls, (MAX_LIFTS + 1) * (MAX_REROLLS + 1));
+
+        // 出現の時刻が来ても、壁の岩のほかには増えない
+        game_state.system.next_pop = game_state.system.elapsed;
+        game_state.enemy_pop();
+        assert_eq!(game_state.actor.e_block.len(), 1);
+        assert_eq!(game_state.spawn_stats.blocked, before.blocked + 2);
+    }
+
+    #[test]
+    fn planned_spawns_leave_a_fair_gap() {
+        let mut game_state = headless_state(11);
+        game_state.invincible = true;
+        let input = InputState::new();
+
+        // 岩が増えてから、出現位置を選ぶたびに隙間が残るかを確かめる
+        for _ in 0..(60 * 90) {
+            game_state.tick(&input);
+        }
+        for &kind in EnemyKind::all() {
+            if let Some((x, y)) = game_state.plan_spawn(kind) {
+                let hitbox = game_state.actor.template.e_block[kind.index()].hitbox.clone();
+                let candidate = spawn_plan::widen(&hitbox.bounds_at(x, y), kind.behavior().sway());
+                let rocks: Vec<Aabb> = game_state.actor.live_enemies()
+                    .map(|e| e.sway_bounds())
+                    .collect();
+                let sprites: Vec<Aabb> = game_state.actor.live_enemies()
+                    .map(|e| e.sway_rect())
+                    .collect();
+                let ship = Ship {
+                    bounds: game_state.actor.player.bounds(),
+                    speed: game_state.system.player_move_speed,
+                };
+
+                let sprite = game_state.actor.template.e_block[kind.index()].clone();
+                let rect = spawn_plan::widen(&(x, y, sprite.width as f32, sprite.height as f32),
+                                             kind.behavior().sway());
+
+                assert!(!spawn_plan::overlaps_any(&sprites, &rect));
+                assert!(spawn_plan::is_fair(&rocks, &candidate, &ship,
+                                            game_state.enemy_move_speed(), 360.0,
+                                            &mut Vec::new()));
+            }
+        }
+        assert!(game_state.spawn_stats.spawns > 10);
+    }
+}
@@ -0,0 +1,286 @@
+/*-------------------------------
+            high_score.rs
+
+  ハイスコア表を扱う
+  ユーザーデータフォルダにtomlとして保存して、次回起動時にも残す
+
+  表は難易度ごとに別のファイルにする。
+  ふつうは以前からのhigh_scores.tomlのままなので、今までの記録もそのまま残る
+
+  * struct ScoreEntry: ハイスコア表の一行分
+  * struct HighScores: ハイスコア表本体
+
+  * impl ScoreEntry:
+    * new()      : 今の日時でエントリを作る
+
+  * impl HighScores:
+    * load()     : ファイルから読み込む。ファイルがなければ空の表
+    * save()     : ファイルに書き出す
+    * add()      : スコアを追加して、ランクインした順位を返す
+    * best()     : 一位のスコア
+
+  * file_name(): 難易度ごとのハイスコア表のファイル名
+
+  * mod seed_string: u64をtomlに収めるため、文字列として読み書きする
+-------------------------------*/
+use std::cmp::Ordering;
+use std::fs;
+use std::io::{ self, Result, Write };
+use std::path::Path;
+
+use toml;
+
+use difficulty::Difficulty;
+use etc;
+
+/// ハイスコア表を保存するファイル名。ふつう以外は難易度名を後ろに付ける
+pub const HIGH_SCORE_FILE: &'static str = "high_scores.toml";
+/// ハイスコア表に残す件数
+pub const MAX_ENTRIES: usize = 10;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// ハイスコア表の一行分
+pub struct ScoreEntry {
+    /// スコアになる耐久秒数
+    pub duration: f64,
+    /// そのゲームで使ったシード値
+    #[serde(with = "seed_string")]
+    pub seed: u64,
+    /// 記録した日時（UTC）
+    pub date: String,
+}
+
+impl ScoreEntry {
+    /// 今の日時でエントリを作る
+    pub fn new(duration: f64, seed: u64) -> Self {
+        ScoreEntry {
+            duration: duration,
+            seed: seed,
+            date: etc::now_date_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+/// ハイスコア表。スコアの高い順に並ぶ
+pub struct HighScores {
+    #[serde(default)]
+    pub entries: Vec<ScoreEntry>,
+}
+
+impl HighScores {
+    /// ハイスコア表を読み込む
+    ///
+    /// まだ一度も保存していなければ、空の表を返す
+    /// 以前のフレーム数スコア（`score`）が残っていても無視して、耐久秒数で並べる
+    pub fn load<'a>(path: &'a Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(HighScores::default());
+        }
+
+        let tmp_vec = etc::File::read_to_vec(path)?;
+        let mut scores: HighScores = toml::de::from_slice(&tmp_vec)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        // 手で書き換えられていても大丈夫なように、並べ直しておく
+        scores.sort();
+        Ok(scores)
+    }
+
+    /// ハイスコア表を書き出す
+    pub fn save<'a>(&self, path: &'a Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let out_s = toml::to_string(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let mut f = fs::File::create(path)?;
+        f.write_all(out_s.as_bytes())?;
+        Ok(())
+    }
+
+    /// スコアを追加する
+    ///
+    /// ランクインしたら、その順位（0始まり）を返す
+    pub fn add(&mut self, entry: ScoreEntry) -> Option<usize> {
+        // 同点なら先に記録したほうを上にする
+        let rank = self.entries
+            .iter()
+            .position(|e| e.duration < entry.duration)
+            .unwrap_or(self.entries.len());
+
+        if rank >= MAX_ENTRIES {
+            return None;
+        }
+
+        self.entries.insert(rank, entry);
+        self.entries.truncate(MAX_ENTRIES);
+
+        Some(rank)
+    }
+
+    /// 一位のスコア
+    pub fn best(&self) -> Option<&ScoreEntry> {
+        self.entries.first()
+    }
+
+    /// 内部用。スコアの高い順に並べ直す
+    fn sort(&mut self) {
+        self.entries.sort_by(|a, b| {
+            b.duration.partial_cmp(&a.duration).unwrap_or(Ordering::Equal)
+        });
+        self.entries.truncate(MAX_ENTRIES);
+    }
+}
+
+/// 難易度`difficulty`のハイスコア表を保存するファイル名
+///
+/// 例: むずかしいなら`high_scores_hard.toml`
+pub fn file_name(difficulty: Difficulty) -> String {
+    match difficulty {
+        Difficulty::Normal => HIGH_SCORE_FILE.to_string(),
+        _ => HIGH_SCORE_FILE.replace(".toml", &format!("_{}.toml", difficulty.name())),
+    }
+}
+
+/// tomlの整数はi64までなので、u64のシード値は文字列として保存する
+mod seed_string {
+    use serde::{ Deserialize, Deserializer, Serializer };
+    use serde::de::Error;
+
+    pub fn serialize<S>(seed: &u64, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer {
+        serializer.serialize_str(&seed.to_string())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<u64, D::Error>
+        where D: Deserializer<'de> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn entry(duration: f64, seed: u64) -> ScoreEntry {
+        ScoreEntry {
+            duration: duration,
+            seed: seed,
+            date: "2018-01-01 00:00".to_string(),
+        }
+    }
+
+    fn durations_of(table: &HighScores) -> Vec<f64> {
+        table.entries.iter().map(|e| e.duration).collect()
+    }
+
+    #[test]
+    fn add_keeps_entries_sorted_by_duration() {
+        let mut table = HighScores::default();
+
+        assert_eq!(table.add(entry(30.0, 1)), Some(0));
+        assert_eq!(table.add(entry(90.0, 2)), Some(0));
+        assert_eq!(table.add(entry(60.0, 3)), Some(1));
+        assert_eq!(table.add(entry(10.0, 4)), Some(3));
+
+        assert_eq!(durations_of(&table), vec![90.0, 60.0, 30.0, 10.0]);
+        assert_eq!(table.best().map(|e| e.seed), Some(2));
+    }
+
+    #[test]
+    fn add_truncates_at_max_entries() {
+        let mut table = HighScores::default();
+        for i in 0..MAX_ENTRIES {
+            table.add(entry(100.0 - i as f64, i as u64));
+        }
+        assert_eq!(table.entries.len(), MAX_ENTRIES);
+
+        // 最下位より低ければランク外
+        assert_eq!(table.add(entry(0.5, 99)), None);
+        assert_eq!(table.entries.len(), MAX_ENTRIES);
+
+        // 途中に入れば、最下位が押し出される
+        let last = table.entries[MAX_ENTRIES - 1].duration;
+        assert_eq!(table.add(entry(99.5, 100)), Some(1));
+        assert_eq!(table.entries.len(), MAX_ENTRIES);
+        assert!(table.entries.iter().all(|e| e.duration != last));
+    }
+
+    #[test]
+    fn tied_scores_rank_below_earlier_entries() {
+        let mut table = HighScores::default();
+        table.add(entry(50.0, 1));
+        table.add(entry(30.0, 2));
+
+        // 同点なら先に記録したほうが上
+        assert_eq!(table.add(entry(50.0, 3)), Some(1));
+        assert_eq!(table.add(entry(30.0, 4)), Some(3));
+
+        let seeds: Vec<u64> = table.entries.iter().map(|e| e.seed).collect();
+        assert_eq!(seeds, vec![1, 3, 2, 4]);
+
+        // 表が埋まっていて最下位と同点なら、ランク外
+        let mut full = HighScores::default();
+        for i in 0..MAX_ENTRIES {
+            full.add(entry(10.0, i as u64));
+        }
+        assert_eq!(full.add(entry(10.0, 99)), None);
+    }
+
+    #[test]
+    fn save_and_load_keep_u64_seeds() {
+        let path = env::temp_dir()
+            .join(format!("dodge_rock_test_scores_{}", std::process::id()))
+            .join(HIGH_SCORE_FILE);
+
+        let mut table = HighScores::default();
+        table.add(entry(20.25, u64::max_value()));
+        table.add(entry(10.5, 0));
+        table.save(&path).unwrap();
+
+        // i64に収まらないシード値は文字列として書かれている
+        let text = etc::File::read_to_string(&path).unwrap();
+        assert!(text.contains(&format!("\"{}\"", u64::max_value())));
+
+        let loaded = HighScores::load(&path).unwrap();
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert_eq!(durations_of(&loaded), vec![20.25, 10.5]);
+        assert_eq!(loaded.entries[0].seed, u64::max_value());
+        assert_eq!(loaded.entries[1].seed, 0);
+    }
+
+    #[test]
+    fn load_sorts_hand_edited_files_and_missing_file_is_empty() {
+        let dir = env::temp_dir().join(format!("dodge_rock_test_scores_edit_{}", std::process::id()));
+        let path = dir.join(HIGH_SCORE_FILE);
+        assert!(HighScores::load(&path).unwrap().entries.is_empty());
+
+        fs::create_dir_all(&dir).unwrap();
+        // 以前のフレーム数スコアが残っていても、耐久秒数の順に並べる
+        fs::write(&path, "[[entries]]\nscore = 900\nduration = 1.5\nseed = \"5\"\ndate = \"\"\n\
+                          [[entries]]\nscore = 30\nduration = 7.25\nseed = \"6\"\ndate = \"\"\n").unwrap();
+
+        let loaded = HighScores::load(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(durations_of(&loaded), vec![7.25, 1.5]);
+    }
+
+    #[test]
+    fn each_difficulty_has_its_own_file() {
+        // ふつうは今までのファイル名のまま
+        assert_eq!(file_name(Difficulty::Normal), HIGH_SCORE_FILE);
+        assert_eq!(file_name(Difficulty::Hard), "high_scores_hard.toml");
+
+        let mut names: Vec<String> = Difficulty::all().iter().map(|&d| file_name(d)).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), Difficulty::all().len());
+    }
+}
@@ -0,0 +1,649 @@
+/*-------------------------------
+            hitbox.rs
+
+  スプライトごとの当たり判定の形を扱う
+
+  当たり判定は、画像と同じ名前のtomlファイルに書いておく。
+  例: `player_ship_29x48.png` なら `player_ship_29x48.toml`
+  ファイルがなければ、画像全体を一つの矩形として扱う。
+
+  座標は画像の左上を原点としたピクセル単位。書き方:
+
+    [[hitbox]]
+    shape = "rect"
+    x = 10
+    y = 22
+    w = 9
+    h = 13
+
+    [[hitbox]]
+    shape = "circle"
+    x = 16          # 中心
+    y = 16
+    r = 14
+
+    [[hitbox]]
+    shape = "polygon"   # 凸多角形のみ
+    points = [[0, 0], [32, 0], [16, 32]]
+
+  * enum Shape  : 当たり判定の形一つ分
+  * struct Hitbox: スプライト一つ分の当たり判定。複数の形を持てる
+
+  * impl Shape:
+    * bounds()   : その形を包む矩形
+    * check()    : 値がおかしくないかを調べる
+
+  * impl Hitbox:
+    * new()      : 形の一覧から作る。おかしな値ならエラー
+    * full()     : 画像全体を一つの矩形にした当たり判定
+    * load()     : tomlファイルから読み込む。ファイルがなければfull()
+    * bounds_at(): (x, y)に置いた時に、全体を包む矩形
+    * overlaps_at(): (x, y)に置いた二つの当たり判定が重なっているか
+    * single_rect(): 矩形一つだけなら、その矩形
+    * scaled()   : 全体を包む矩形の中心を基準に、拡大・縮小したもの
+
+  * swept_overlap(): 一フレームの移動中に、二つの当たり判定が重なったか
+  * shape_overlap(): 形同士の重なり判定
+  * その他、形の組み合わせごとの内部用関数
+-------------------------------*/
+use std::io::{ self, Result };
+use std::path::Path;
+
+use toml;
+
+use collision::{ self, Aabb };
+use etc;
+
+/// 移動を細切れにして調べる際の、最大分割数
+///
+/// 一フレームでこれより細かく区切る必要があるほど速い時は、この数で打ち切る
+const MAX_SUB_STEPS: usize = 64;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "shape", rename_all = "lowercase")]
+/// 当たり判定の形一つ分。座標は画像左上が原点
+pub enum Shape {
+    /// 矩形。(x, y)は左上
+    Rect { x: f32, y: f32, w: f32, h: f32 },
+    /// 円。(x, y)は中心
+    Circle { x: f32, y: f32, r: f32 },
+    /// 凸多角形。頂点を順番に並べる
+    Polygon { points: Vec<[f32; 2]> },
+}
+
+impl Shape {
+    /// その形を包む矩形
+    pub fn bounds(&self) -> Aabb {
+        match *self {
+            Shape::Rect { x, y, w, h } => (x, y, w, h),
+            Shape::Circle { x, y, r } => (x - r, y - r, r * 2.0, r * 2.0),
+            Shape::Polygon { ref points } => {
+                let mut min = [::std::f32::INFINITY; 2];
+                let mut max = [::std::f32::NEG_INFINITY; 2];
+                for p in points {
+                    for i in 0..2 {
+                        min[i] = min[i].min(p[i]);
+                        max[i] = max[i].max(p[i]);
+                    }
+                }
+                (min[0], min[1], max[0] - min[0], max[1] - min[1])
+            }
+        }
+    }
+
+    /// 値がおかしくないかを調べる
+    fn check(&self) -> ::std::result::Result<(), String> {
+        match *self {
+            Shape::Rect { w, h, .. } if !(w > 0.0 && h > 0.0) => {
+                Err(format!("rectの幅と高さは0より大きくしてください (w = {}, h = {})", w, h))
+            }
+            Shape::Circle { r, .. } if !(r > 0.0) => {
+                Err(format!("circleの半径は0より大きくしてください (r = {})", r))
+            }
+            Shape::Polygon { ref points } if points.len() < 3 => {
+                Err(format!("polygonの頂点は3つ以上必要です ({}個)", points.len()))
+            }
+            Shape::Polygon { ref points } if !is_convex(points) => {
+                Err("polygonは凸多角形にしてください".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// スプライト一つ分の当たり判定
+///
+/// どれか一つの形でも重なれば、当たったことにする
+pub struct Hitbox {
+    pub shapes: Vec<Shape>,
+    /// 全部の形を包む矩形。大まかな判定用
+    bounds: Aabb,
+}
+
+/// 内部用。tomlファイルの中身
+#[derive(Deserialize)]
+struct HitboxFile {
+    hitbox: Vec<Shape>,
+}
+
+impl Hitbox {
+    /// 形の一覧から作る
+    ///
+    /// 形が一つもない、大きさが0以下などの場合はエラー
+    pub fn new(shapes: Vec<Shape>) -> ::std::result::Result<Self, String> {
+        if shapes.is_empty() {
+            return Err("当たり判定の形が一つもありません".to_string());
+        }
+
+        for (i, s) in shapes.iter().enumerate() {
+            s.check().map_err(|e| format!("{}番目の形: {}", i + 1, e))?;
+        }
+
+        let bounds = shapes.iter()
+            .skip(1)
+            .fold(shapes[0].bounds(), |acc, s| collision::union(&acc, &s.bounds()));
+
+        Ok(Hitbox {
+            shapes: shapes,
+            bounds: bounds,
+        })
+    }
+
+    /// 画像全体を一つの矩形にした当たり判定
+    pub fn full(width: u32, height: u32) -> Self {
+        let shape = Shape::Rect { x: 0.0, y: 0.0, w: width as f32, h: height as f32 };
+
+        Hitbox {
+            bounds: shape.bounds(),
+            shapes: vec![shape],
+        }
+    }
+
+    /// tomlファイルから読み込む
+    ///
+    /// ファイルがなければ、画像全体(width x height)を当たり判定にする
+    pub fn load<'a>(path: &'a Path, width: u32, height: u32) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Hitbox::full(width, height));
+        }
+
+        let invalid = |e: String| {
+            io::Error::new(io::ErrorKind::InvalidData,
+                           format!("{}: {}", path.display(), e))
+        };
+
+        let tmp_vec = etc::File::read_to_vec(path)?;
+        let file: HitboxFile = toml::de::from_slice(&tmp_vec)
+            .map_err(|e| invalid(e.to_string()))?;
+
+        Hitbox::new(file.hitbox).map_err(invalid)
+    }
+
+    /// (x, y)に置いた時に、全体を包む矩形
+    pub fn bounds_at(&self, x: f32, y: f32) -> Aabb {
+        (self.bounds.0 + x, self.bounds.1 + y, self.bounds.2, self.bounds.3)
+    }
+
+    /// 自分を(ax, ay)に、相手を(bx, by)に置いた時に重なっているか
+    pub fn overlaps_at(&self, ax: f32, ay: f32, other: &Hitbox, bx: f32, by: f32) -> bool {
+        if !rect_rect(&self.bounds_at(ax, ay), &other.bounds_at(bx, by)) {
+            return false;
+        }
+
+        self.shapes.iter().any(|a| {
+            other.shapes.iter().any(|b| shape_overlap(a, ax, ay, b, bx, by))
+        })
+    }
+
+    /// 矩形一つだけでできていれば、その矩形
+    pub fn single_rect(&self) -> Option<Aabb> {
+        if self.shapes.len() != 1 {
+            return None;
+        }
+
+        match self.shapes[0] {
+            Shape::Rect { x, y, w, h } => Some((x, y, w, h)),
+            _ => None,
+        }
+    }
+
+    /// 全体を包む矩形の中心を基準に、`factor`倍に拡大・縮小したもの
+    ///
+    /// 画像の大きさは変えずに、当たり判定だけを大きく・小さくする時に使う
+    pub fn scaled(&self, factor: f32) -> Self {
+        let (cx, cy) = (self.bounds.0 + self.bounds.2 / 2.0,
+                        self.bounds.1 + self.bounds.3 / 2.0);
+        let sx = |x: f32| cx + (x - cx) * factor;
+        let sy = |y: f32| cy + (y - cy) * factor;
+
+        let shapes: Vec<Shape> = self.shapes.iter()
+            .map(|s| match *s {
+                Shape::Rect { x, y, w, h } => {
+                    Shape::Rect { x: sx(x), y: sy(y), w: w * factor, h: h * factor }
+                }
+                Shape::Circle { x, y, r } => {
+                    Shape::Circle { x: sx(x), y: sy(y), r: r * factor }
+                }
+                Shape::Polygon { ref points } => {
+                    Shape::Polygon { points: points.iter().map(|p| [sx(p[0]), sy(p[1])]).collect() }
+                }
+            })
+            .collect();
+
+        let bounds = shapes.iter()
+            .skip(1)
+            .fold(shapes[0].bounds(), |acc, s| collision::union(&acc, &s.bounds()));
+
+        Hitbox {
+            shapes: shapes,
+            bounds: bounds,
+        }
+    }
+}
+
+/// 当たり判定aが位置a0からa1へ、bがb0からb1へ一フレームで真っ直ぐ動いた時、
+/// その途中で重なったかを調べる
+///
+/// 両方とも矩形一つなら、collision::swept_overlap()でそのまま解く。
+/// それ以外は全体を包む矩形で大まかに調べてから、
+/// 一番小さい形でも飛び越えられないくらい細かく移動を区切って、各時点で重なりを調べる
+pub fn swept_overlap(a: &Hitbox, a0: (f32, f32), a1: (f32, f32),
+                     b: &Hitbox, b0: (f32, f32), b1: (f32, f32)) -> bool {
+    let offset = |r: &Aabb, p: (f32, f32)| (r.0 + p.0, r.1 + p.1, r.2, r.3);
+
+    if let (Some(ra), Some(rb)) = (a.single_rect(), b.single_rect()) {
+        return collision::swept_overlap(&offset(&ra, a0), &offset(&ra, a1),
+                                        &offset(&rb, b0), &offset(&rb, b1));
+    }
+
+    let (ba0, ba1) = (a.bounds_at(a0.0, a0.1), a.bounds_at(a1.0, a1.1));
+    let (bb0, bb1) = (b.bounds_at(b0.0, b0.1), b.bounds_at(b1.0, b1.1));
+
+    if !collision::swept_overlap(&ba0, &ba1, &bb0, &bb1) {
+        return false;
+    }
+
+    // bから見たaの移動量を、一番小さい形の半分ずつに区切る。
+    // 全体を包む矩形で測ると、大きな形と組み合わさった細い形を飛び越えてしまう
+    let dx = (a1.0 - a0.0) - (b1.0 - b0.0);
+    let dy = (a1.1 - a0.1) - (b1.1 - b0.1);
+    let min_extent = a.shapes.iter()
+        .chain(b.shapes.iter())
+        .map(|s| {
+            let r = s.bounds();
+            r.2.min(r.3)
+        })
+        .fold(::std::f32::INFINITY, f32::min)
+        .max(1.0);
+    let steps = (((dx.abs().max(dy.abs())) / (min_extent * 0.5)).ceil() as usize)
+        .max(1)
+        .min(MAX_SUB_STEPS);
+
+    (0..(steps + 1)).any(|i| {
+        let t = i as f32 / steps as f32;
+        let lerp = |p0: (f32, f32), p1: (f32, f32)| {
+            (p0.0 + (p1.0 - p0.0) * t, p0.1 + (p1.1 - p0.1) * t)
+        };
+        let (pa, pb) = (lerp(a0, a1), lerp(b0, b1));
+
+        a.overlaps_at(pa.0, pa.1, b, pb.0, pb.1)
+    })
+}
+
+/// 形aを(ax, ay)に、形bを(bx, by)に置いた時に重なっているか
+///
+/// 辺が接しているだけなら重なりとはしない
+pub fn shape_overlap(a: &Shape, ax: f32, ay: f32, b: &Shape, bx: f32, by: f32) -> bool {
+    match (a, b) {
+        (&Shape::Rect { x: x1, y: y1, w: w1, h: h1 },
+         &Shape::Rect { x: x2, y: y2, w: w2, h: h2 }) => {
+            rect_rect(&(x1 + ax, y1 + ay, w1, h1), &(x2 + bx, y2 + by, w2, h2))
+        }
+        (&Shape::Circle { x: x1, y: y1, r: r1 },
+         &Shape::Circle { x: x2, y: y2, r: r2 }) => {
+            let (dx, dy) = ((x2 + bx) - (x1 + ax), (y2 + by) - (y1 + ay));
+            dx * dx + dy * dy < (r1 + r2) * (r1 + r2)
+        }
+        (&Shape::Rect { x, y, w, h }, &Shape::Circle { x: cx, y: cy, r }) => {
+            rect_circle(&(x + ax, y + ay, w, h), cx + bx, cy + by, r)
+        }
+        (&Shape::Circle { .. }, &Shape::Rect { .. }) => {
+            shape_overlap(b, bx, by, a, ax, ay)
+        }
+        (&Shape::Polygon { ref points }, _) => {
+            polygon_shape(points, ax, ay, b, bx, by)
+        }
+        (_, &Shape::Polygon { ref points }) => {
+            polygon_shape(points, bx, by, a, ax, ay)
+        }
+    }
+}
+
+/// 内部用。矩形同士
+fn rect_rect(a: &Aabb, b: &Aabb) -> bool {
+    a.0 < b.0 + b.2 && b.0 < a.0 + a.2 &&
+        a.1 < b.1 + b.3 && b.1 < a.1 + a.3
+}
+
+/// 内部用。矩形と円。矩形上で円の中心に一番近い点との距離を見る
+fn rect_circle(rect: &Aabb, cx: f32, cy: f32, r: f32) -> bool {
+    let nx = cx.max(rect.0).min(rect.0 + rect.2);
+    let ny = cy.max(rect.1).min(rect.1 + rect.3);
+    let (dx, dy) = (cx - nx, cy - ny);
+
+    dx * dx + dy * dy < r * r
+}
+
+/// 内部用。多角形(points + (px, py))と、何かの形
+///
+/// 分離軸定理で調べる。矩形は四角形の多角形として扱う
+fn polygon_shape(points: &[[f32; 2]], px: f32, py: f32,
+                 other: &Shape, ox: f32, oy: f32) -> bool {
+    match *other {
+        Shape::Rect { x, y, w, h } => {
+            let quad = [[x, y], [x + w, y], [x + w, y + h], [x, y + h]];
+            polygon_polygon(points, px, py, &quad, ox, oy)
+        }
+        Shape::Polygon { points: ref other_points } => {
+            polygon_polygon(points, px, py, other_points, ox, oy)
+        }
+        Shape::Circle { x, y, r } => {
+            polygon_circle(points, px, py, x + ox, y + oy, r)
+        }
+    }
+}
+
+/// 内部用。凸多角形同士。どちらかの辺の法線で分けられれば重なっていない
+fn polygon_polygon(a: &[[f32; 2]], ax: f32, ay: f32,
+                   b: &[[f32; 2]], bx: f32, by: f32) -> bool {
+    let separated = |poly: &[[f32; 2]]| {
+        (0..poly.len()).any(|i| {
+            let (p, q) = (poly[i], poly[(i + 1) % poly.len()]);
+            let axis = (p[1] - q[1], q[0] - p[0]);
+
+            let (a_min, a_max) = project(a, ax, ay, axis);
+            let (b_min, b_max) = project(b, bx, by, axis);
+            a_max <= b_min || b_max <= a_min
+        })
+    };
+
+    !separated(a) && !separated(b)
+}
+
+/// 内部用。凸多角形と円
+///
+/// 多角形の辺の法線に加えて、円の中心に一番近い頂点への向きも分離軸にする
+fn polygon_circle(poly: &[[f32; 2]], px: f32, py: f32,
+                  cx: f32, cy: f32, r: f32) -> bool {
+    let circle_sep = |axis: (f32, f32)| {
+        let len = (axis.0 * axis.0 + axis.1 * axis.1).sqrt();
+        if len == 0.0 {
+            return false;
+        }
+        let (p_min, p_max) = project(poly, px, py, axis);
+        let c = (cx * axis.0 + cy * axis.1) / len;
+        let (p_min, p_max) = (p_min / len, p_max / len);
+        p_max <= c - r || c + r <= p_min
+    };
+
+    let edge_sep = (0..poly.len()).any(|i| {
+        let (p, q) = (poly[i], poly[(i + 1) % poly.len()]);
+        circle_sep((p[1] - q[1], q[0] - p[0]))
+    });
+
+    if edge_sep {
+        return false;
+    }
+
+    let nearest = poly.iter()
+        .map(|p| (p[0] + px - cx, p[1] + py - cy))
+        .fold((0.0, 0.0, ::std::f32::INFINITY), |acc, d| {
+            let dist = d.0 * d.0 + d.1 * d.1;
+            if dist < acc.2 { (d.0, d.1, dist) } else { acc }
+        });
+
+    !circle_sep((nearest.0, nearest.1))
+}
+
+/// 内部用。多角形を軸に投影した範囲
+fn project(poly: &[[f32; 2]], ox: f32, oy: f32, axis: (f32, f32)) -> (f32, f32) {
+    poly.iter()
+        .map(|p| (p[0] + ox) * axis.0 + (p[1] + oy) * axis.1)
+        .fold((::std::f32::INFINITY, ::std::f32::NEG_INFINITY),
+              |(min, max), v| (min.min(v), max.max(v)))
+}
+
+/// 内部用。頂点の並びが凸多角形になっているか
+///
+/// 隣り合う辺の外積の向きがすべて揃っていれば凸
+fn is_convex(points: &[[f32; 2]]) -> bool {
+    let n = points.len();
+    let mut sign = 0.0;
+
+    for i in 0..n {
+        let (p, q, r) = (points[i], points[(i + 1) % n], points[(i + 2) % n]);
+        let cross = (q[0] - p[0]) * (r[1] - q[1]) - (q[1] - p[1]) * (r[0] - q[0]);
+
+        if cross != 0.0 {
+            if sign != 0.0 && cross.signum() != sign {
+                return false;
+            }
+            sign = cross.signum();
+        }
+    }
+
+    // 全部一直線に並んでいたら多角形ではない
+    sign != 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn rect(x: f32, y: f32, w: f32, h: f32) -> Shape {
+        Shape::Rect { x: x, y: y, w: w, h: h }
+    }
+
+    fn ball(x: f32, y: f32, r: f32) -> Shape {
+        Shape::Circle { x: x, y: y, r: r }
+    }
+
+    fn polygon(points: &[[f32; 2]]) -> Shape {
+        Shape::Polygon { points: points.to_vec() }
+    }
+
+    /// 形の重なりを、順番を入れ替えても同じ結果になるか確かめながら調べる
+    fn overlap(a: &Shape, b: &Shape) -> bool {
+        let result = shape_overlap(a, 0.0, 0.0, b, 0.0, 0.0);
+        assert_eq!(result, shape_overlap(b, 0.0, 0.0, a, 0.0, 0.0));
+        result
+    }
+
+    /// 一時フォルダに当たり判定のtomlを書いて、Hitbox::load()で読む
+    fn load_text(name: &str, text: &str) -> Result<Hitbox> {
+        let path: PathBuf = env::temp_dir().join(format!("dodge_rock_test_{}.toml", name));
+        fs::write(&path, text).unwrap();
+        let result = Hitbox::load(&path, 32, 32);
+        let _ = fs::remove_file(&path);
+        result
+    }
+
+    #[test]
+    fn rects_touching_edges_do_not_overlap() {
+        assert!(!overlap(&rect(0.0, 0.0, 10.0, 10.0), &rect(10.0, 0.0, 10.0, 10.0)));
+        assert!(overlap(&rect(0.0, 0.0, 10.0, 10.0), &rect(9.5, 0.0, 10.0, 10.0)));
+    }
+
+    #[test]
+    fn circles_touching_do_not_overlap() {
+        assert!(!overlap(&ball(0.0, 0.0, 5.0), &ball(10.0, 0.0, 5.0)));
+        assert!(overlap(&ball(0.0, 0.0, 5.0), &ball(9.5, 0.0, 5.0)));
+    }
+
+    #[test]
+    fn rect_and_circle_touching_do_not_overlap() {
+        assert!(!overlap(&rect(0.0, 0.0, 10.0, 10.0), &ball(15.0, 5.0, 5.0)));
+        assert!(overlap(&rect(0.0, 0.0, 10.0, 10.0), &ball(14.5, 5.0, 5.0)));
+        // 角の外側。全体を包む矩形同士は重なる
+        assert!(!overlap(&rect(0.0, 0.0, 10.0, 10.0), &ball(14.0, 14.0, 5.0)));
+    }
+
+    #[test]
+    fn polygons_sharing_an_edge_do_not_overlap() {
+        let lower = polygon(&[[0.0, 0.0], [10.0, 0.0], [0.0, 10.0]]);
+        let upper = polygon(&[[10.0, 0.0], [10.0, 10.0], [0.0, 10.0]]);
+        let shifted = polygon(&[[9.0, 0.0], [9.0, 10.0], [-1.0, 10.0]]);
+
+        assert!(!overlap(&lower, &upper));
+        assert!(overlap(&lower, &shifted));
+    }
+
+    #[test]
+    fn polygon_and_rect_touching_do_not_overlap() {
+        let square = polygon(&[[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]]);
+
+        assert!(!overlap(&square, &rect(10.0, 0.0, 10.0, 10.0)));
+        assert!(overlap(&square, &rect(9.5, 0.0, 10.0, 10.0)));
+    }
+
+    #[test]
+    fn polygon_and_circle() {
+        let triangle = polygon(&[[0.0, 0.0], [10.0, 0.0], [0.0, 10.0]]);
+
+        // 頂点(10, 0)に接するだけ
+        assert!(!overlap(&triangle, &ball(15.0, 0.0, 5.0)));
+        assert!(overlap(&triangle, &ball(15.0, 0.0, 5.5)));
+        // 斜めの辺の外側。全体を包む矩形には入っている
+        assert!(!overlap(&triangle, &ball(8.0, 8.0, 1.0)));
+        assert!(overlap(&triangle, &ball(5.5, 5.5, 1.0)));
+    }
+
+    #[test]
+    fn hitbox_rejects_concave_polygon() {
+        let concave = polygon(&[[0.0, 0.0], [10.0, 0.0], [5.0, 2.0], [10.0, 10.0], [0.0, 10.0]]);
+        let collinear = polygon(&[[0.0, 0.0], [5.0, 0.0], [10.0, 0.0]]);
+        // 時計回りでも反時計回りでも、凸なら良い
+        let clockwise = polygon(&[[0.0, 0.0], [0.0, 10.0], [10.0, 10.0], [10.0, 0.0]]);
+
+        assert!(Hitbox::new(vec![concave]).is_err());
+        assert!(Hitbox::new(vec![collinear]).is_err());
+        assert!(Hitbox::new(vec![clockwise]).is_ok());
+    }
+
+    #[test]
+    fn hitbox_load_reads_valid_file() {
+        let hitbox = load_text("valid", "[[hitbox]]\nshape = \"circle\"\nx = 16\ny = 16\nr = 14\n")
+            .unwrap();
+
+        assert_eq!(hitbox.shapes, vec![ball(16.0, 16.0, 14.0)]);
+        assert_eq!(hitbox.bounds_at(0.0, 0.0), (2.0, 2.0, 28.0, 28.0));
+    }
+
+    #[test]
+    fn hitbox_load_rejects_malformed_files() {
+        let cases = [
+            ("toml_syntax", "[[hitbox]\nshape = \"rect\""),
+            ("unknown_shape", "[[hitbox]]\nshape = \"star\"\nx = 0\ny = 0\n"),
+            ("missing_field", "[[hitbox]]\nshape = \"rect\"\nx = 0\ny = 0\nw = 4\n"),
+            ("zero_radius", "[[hitbox]]\nshape = \"circle\"\nx = 0\ny = 0\nr = 0\n"),
+            ("concave", "[[hitbox]]\nshape = \"polygon\"\n\
+                         points = [[0, 0], [10, 0], [5, 2], [10, 10], [0, 10]]\n"),
+            ("empty", "hitbox = []\n"),
+        ];
+
+        for &(name, text) in &cases {
+            let err = load_text(name, text).err();
+            assert!(err.map(|e| e.kind()) == Some(io::ErrorKind::InvalidData),
+                    "{}が読み込めてしまった", name);
+        }
+    }
+
+    fn circle(r: f32) -> Hitbox {
+        Hitbox::new(vec![Shape::Circle { x: r, y: r, r: r }]).unwrap()
+    }
+
+    fn square(size: f32) -> Hitbox {
+        Hitbox::new(vec![Shape::Rect { x: 0.0, y: 0.0, w: size, h: size }]).unwrap()
+    }
+
+    #[test]
+    fn swept_overlap_hits_thin_polygon_passed_in_one_frame() {
+        // 厚さ1の三角形を、小さな円が一フレームで60ピクセル動いて飛び越える。
+        // 始点と終点ではどちらも重なっていない
+        let wall = Hitbox::new(vec![
+            Shape::Polygon { points: vec![[0.0, 0.0], [200.0, 0.0], [100.0, 1.0]] },
+        ]).unwrap();
+        let ball = circle(2.0);
+
+        assert!(!ball.overlaps_at(98.0, -30.0, &wall, 0.0, 0.0));
+        assert!(!ball.overlaps_at(98.0, 30.0, &wall, 0.0, 0.0));
+        assert!(swept_overlap(&ball, (98.0, -30.0), (98.0, 30.0),
+                              &wall, (0.0, 0.0), (0.0, 0.0)));
+    }
+
+    #[test]
+    fn swept_overlap_steps_by_smallest_shape() {
+        // 大きな矩形に、厚さ1の細い矩形を組み合わせた当たり判定。
+        // 全体を包む矩形（300x40）で区切ると、細い方を飛び越えてしまう
+        let wall = Hitbox::new(vec![rect(0.0, 0.0, 40.0, 40.0), rect(100.0, 0.0, 200.0, 1.0)])
+            .unwrap();
+        // 小さな円二つを、離して組み合わせたもの
+        let pair = Hitbox::new(vec![ball(1.0, 1.0, 1.0), ball(99.0, 99.0, 1.0)]).unwrap();
+
+        assert!(!pair.overlaps_at(149.0, -17.5, &wall, 0.0, 0.0));
+        assert!(!pair.overlaps_at(149.0, -2.5, &wall, 0.0, 0.0));
+        assert!(!pair.overlaps_at(149.0, 12.5, &wall, 0.0, 0.0));
+        assert!(swept_overlap(&pair, (149.0, -17.5), (149.0, 12.5),
+                              &wall, (0.0, 0.0), (0.0, 0.0)));
+    }
+
+    #[test]
+    fn swept_overlap_too_fast_for_sub_steps_is_not_a_hit_by_itself() {
+        // 角の外側を通り過ぎるだけの動きを、MAX_SUB_STEPSでは区切りきれない速さにする。
+        // 全体を包む矩形同士が重なっても、それだけで当たりにはしない
+        let ball = circle(10.0);
+        let block = square(20.0);
+
+        assert!(!swept_overlap(&ball, (1132.0, -968.0), (-968.0, 1132.0),
+                               &block, (100.0, 100.0), (100.0, 100.0)));
+        // 同じ速さでも、区切った点で重なれば当たり
+        assert!(swept_overlap(&ball, (1100.0, -900.0), (-900.0, 1100.0),
+                              &block, (100.0, 100.0), (100.0, 100.0)));
+    }
+
+    #[test]
+    fn swept_overlap_hits_between_sub_steps() {
+        // 円が矩形の角をかすめる。始点と終点ではどちらも重なっていない
+        let ball = circle(10.0);
+        let block = square(20.0);
+
+        assert!(!ball.overlaps_at(132.0, 42.0, &block, 100.0, 100.0));
+        assert!(!ball.overlaps_at(32.0, 142.0, &block, 100.0, 100.0));
+        assert!(swept_overlap(&ball, (132.0, 42.0), (32.0, 142.0),
+                              &block, (100.0, 100.0), (100.0, 100.0)));
+    }
+
+    #[test]
+    fn swept_overlap_misses_corner_near_pass() {
+        // 円が矩形の角の外側を通り過ぎる。全体を包む矩形同士は重なる
+        let ball = circle(10.0);
+        let block = square(20.0);
+
+        assert!(!swept_overlap(&ball, (132.0, 32.0), (32.0, 132.0),
+                               &block, (100.0, 100.0), (100.0, 100.0)));
+    }
+
+    #[test]
+    fn swept_overlap_misses_when_bounds_never_meet() {
+        let ball = circle(2.0);
+        let block = square(20.0);
+
+        assert!(!swept_overlap(&ball, (150.0, -1000.0), (150.0, 1000.0),
+                               &block, (100.0, 100.0), (100.0, 100.0)));
+    }
+}
@@ -1,27 +1,129 @@
 /*-------------------------------
             input_state.rs
-  
+
   ユーザー入力を処理する
   ゲーム内処理についてはgame_state.rsを、
   画面描画についてはview.rsを参照のこと。
 
+  キー・ボタンのイベントは来たときにすぐ反映せず、
+  固定フレームごとにbegin_tick()でまとめて確定させる。
+  そうすることで「そのフレームで押された・離された」が一度だけ取れる
+
+  * struct ActionState: 操作ごとの押下状態と、押された・離されたの変化
   * struct InputState: キー入力が行われてるかのboolを貯めることにする
-  
+
+  * impl ActionState:
+    * pressed() : そのフレームで押された
+    * held()    : 押されている
+    * released(): そのフレームで離された
+    * any_pressed(): そのフレームで何かのキーが押された
+    * begin_tick(): 溜まったイベントを確定させる
+    * down() / up(): キー・ボタンの押下を操作ごとに数える
+
   * impl InputState: キー入力を適切な形で検知したい
     * new(): default()を呼ぶ初期化
     * reset(): キー入力のリセット
-    * key_press()  : キーが押下されている場合の。バグがあるから修正したい。
-    * key_release(): キーが放上された際の。バグがあるから修正したい。
-    * pad_press()  : ゲームパッドボタンが押下された際の。なぜか上手く動く。
-    * pad_release(): ゲームパッドボタンが放上された際の。なぜか動く。
+    * begin_tick(): 溜まった入力を、このフレームの入力として確定させる
+    * pressed() / held() / released() / any_pressed(): ActionStateへの近道
+    * key_press()  : キーが押下されている場合の。
+    * key_release(): キーが放上された際の。
+    * pad_press()  : ゲームパッドボタンが押下された際の。
+    * pad_release(): ゲームパッドボタンが放上された際の。
     * axis_controll(): アナログスティックの管理。うまく動く。
-    * user_key_controll(): 入力信号を変数へと変換する。キーボード用。
-    * user_pad_controll(): 入力信号を変数へと変換する。ゲームパッド用。
 
 -------------------------------*/
+use std::collections::HashSet;
 
 use ggez::event::{ Axis, Button, Keycode, Mod };
 
+use controls::{ Action, Bindings, ACTION_COUNT };
+
+#[derive(Clone, Debug, Default)]
+/// 操作ごとの押下状態と、そのフレームでの変化
+///
+/// 一つの操作に複数のキーを割り当てられるので、
+/// 押されているキーの数を数えて、0と1の境目で押された・離されたとする
+pub struct ActionState {
+    /// 操作ごとに、押されているキー・ボタンの数
+    down_cnt: [u8; ACTION_COUNT],
+    /// 前のフレーム以降に押された操作。begin_tick()で確定させる
+    press_latch: [bool; ACTION_COUNT],
+    /// 前のフレーム以降に離された操作
+    release_latch: [bool; ACTION_COUNT],
+    /// このフレームで押された
+    pressed: [bool; ACTION_COUNT],
+    /// このフレームで押されている
+    held: [bool; ACTION_COUNT],
+    /// このフレームで離された
+    released: [bool; ACTION_COUNT],
+    /// 「なにかキーをおして〜〜」用。前のフレーム以降に何か押された
+    any_latch: bool,
+    /// このフレームで何か押された
+    any_pressed: bool,
+}
+
+impl ActionState {
+    /// そのフレームで押されたか
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed[action as usize]
+    }
+
+    /// そのフレームで押されているか
+    pub fn held(&self, action: Action) -> bool {
+        self.held[action as usize]
+    }
+
+    /// そのフレームで離されたか
+    pub fn released(&self, action: Action) -> bool {
+        self.released[action as usize]
+    }
+
+    /// そのフレームで、割り当ての有無にかかわらず何かのキーが押されたか
+    pub fn any_pressed(&self) -> bool {
+        self.any_pressed
+    }
+
+    /// 溜まったイベントを、このフレームの入力として確定させる
+    fn begin_tick(&mut self) {
+        for i in 0..ACTION_COUNT {
+            self.pressed[i] = self.press_latch[i];
+            self.released[i] = self.release_latch[i];
+            // フレームの間に押して離した場合も、そのフレームは押されていた扱い
+            self.held[i] = self.down_cnt[i] > 0 || self.press_latch[i];
+
+            self.press_latch[i] = false;
+            self.release_latch[i] = false;
+        }
+
+        self.any_pressed = self.any_latch;
+        self.any_latch = false;
+    }
+
+    /// 操作に割り当てられたキー・ボタンが一つ押された
+    fn down(&mut self, action: Option<Action>) {
+        self.any_latch = true;
+
+        if let Some(a) = action {
+            let i = a as usize;
+            if self.down_cnt[i] == 0 {
+                self.press_latch[i] = true;
+            }
+            self.down_cnt[i] = self.down_cnt[i].saturating_add(1);
+        }
+    }
+
+    /// 操作に割り当てられたキー・ボタンが一つ離された
+    fn up(&mut self, action: Option<Action>) {
+        if let Some(a) = action {
+            let i = a as usize;
+            if self.down_cnt[i] == 1 {
+                self.release_latch[i] = true;
+            }
+            self.down_cnt[i] = self.down_cnt[i].saturating_sub(1);
+        }
+    }
+}
+
 #[derive(Default)]
 /// ユーザー操作に対して、どういう効果をもたせるか
 /// 基本的に0で動作を止めて、それ以外で動かす。
@@ -31,6 +133,9 @@ use ggez::event::{ Axis, Button, Keycode, Mod };
 ///
 /// 0がニュートラルで、上に行けば-にいって、下に行けば+に行く。
 /// アナログスティックの動きと、方向キーの数値を合わせる。
+///
+/// 移動に関わる部分はリプレイにも残すので、
+/// begin_tick()のたびにActionStateから書き写しておく
 pub struct InputState {
     /// 左アナログスティックの横軸
     pub axis_lx: i16,
@@ -46,16 +151,12 @@ pub struct InputState {
     pub move_right: bool,
     /// 低速移動
     pub speed_down: bool,
-    /// ゲームをリスタート
-    pub game_reset: bool,
-    /// ゲームオーバー画面からタイトル画面へ向かう
-    pub game_title: bool,
-    /// ゲームオーバー画面でゲームを終了させる
-    pub game_quit: bool,
-    /// なにかキーをおして〜〜用に、すべてのキーで反応するやつ
-    pub any_key: bool,
-    /// デバッグ用キー
-    pub key_m: bool,
+    /// 操作ごとの押された・離された
+    pub actions: ActionState,
+    /// 今押されているキー。キーリピートや取りこぼしで数がずれないように
+    keys_down: HashSet<Keycode>,
+    /// 今押されているゲームパッドボタン
+    pads_down: HashSet<Button>,
 }
 
 impl InputState {
@@ -64,54 +165,84 @@ impl InputState {
         let input: InputState = Default::default();
         input
     }
-    
+
     /// キー入力をリセットする
+    ///
+    /// 押しっぱなしのキーも、一度離してからでないと反応しなくなる
     pub fn reset(&mut self) {
-        // リセット用の変数束縛
-        let input: InputState = Default::default();
-        
-        // 見よ、この強引なリセット！
-        self.axis_lx = input.axis_lx;
-        self.axis_ly = input.axis_ly;
-        self.move_up = input.move_up;
-        self.move_down = input.move_down;
-        self.move_left = input.move_left;
-        self.move_right = input.move_right;
-        self.speed_down = input.speed_down;
-        self.game_reset = input.game_reset;
-        self.game_title = input.game_title;
-        self.game_quit = input.game_quit;
-        self.any_key = input.any_key;
-        self.key_m = input.key_m;
-    }
-    
-    /// キーが押されたら、入力信号をtrueに。キーボード用。
-    pub fn key_press(&mut self, 
+        *self = InputState::new();
+    }
+
+    /// 前のフレーム以降に溜まった入力を、このフレームの入力として確定させる
+    ///
+    /// 固定フレームごとに、シーンの更新前に一度だけ呼ぶ
+    pub fn begin_tick(&mut self) {
+        self.actions.begin_tick();
+
+        self.move_up = self.actions.held(Action::MoveUp);
+        self.move_down = self.actions.held(Action::MoveDown);
+        self.move_left = self.actions.held(Action::MoveLeft);
+        self.move_right = self.actions.held(Action::MoveRight);
+        self.speed_down = self.actions.held(Action::SpeedDown);
+    }
+
+    /// そのフレームで押されたか
+    pub fn pressed(&self, action: Action) -> bool {
+        self.actions.pressed(action)
+    }
+
+    /// そのフレームで押されているか
+    pub fn held(&self, action: Action) -> bool {
+        self.actions.held(action)
+    }
+
+    /// そのフレームで離されたか
+    pub fn released(&self, action: Action) -> bool {
+        self.actions.released(action)
+    }
+
+    /// そのフレームで何かのキーが押されたか
+    pub fn any_pressed(&self) -> bool {
+        self.actions.any_pressed()
+    }
+
+    /// キーが押されたら、入力を溜めておく。キーボード用。
+    pub fn key_press(&mut self,
+                  bindings: &Bindings,
                   keycode: Keycode,
                   _keymod: Mod) {
-        // キーが押されたら移動を始める
-        self.user_key_controll(keycode, true);
+        // キーリピートで何度も来ても、一回だけ数える
+        if self.keys_down.insert(keycode) {
+            self.actions.down(bindings.key_action(keycode));
+        }
     }
-    
-    /// キーが離されたなら、入力信号をfalseに。キーボード用。
+
+    /// キーが離されたら、入力を溜めておく。キーボード用。
     pub fn key_release(&mut self,
+                    bindings: &Bindings,
                     keycode: Keycode,
                     _keymod: Mod) {
-        // キーが離されたら移動をやめる
-        self.user_key_controll(keycode, false);
+        // reset()前から押されていたキーは数えていないので無視
+        if self.keys_down.remove(&keycode) {
+            self.actions.up(bindings.key_action(keycode));
+        }
     }
-    
-    /// キーが押されたら、入力信号をtrueに。パッド用。
-    pub fn pad_press(&mut self, btn: Button) {
+
+    /// ボタンが押されたら、入力を溜めておく。パッド用。
+    pub fn pad_press(&mut self, bindings: &Bindings, btn: Button) {
         // 1pコンの入力のみ取得しているよ
-        self.user_pad_controll(btn, true);
+        if self.pads_down.insert(btn) {
+            self.actions.down(bindings.pad_action(btn));
+        }
     }
-    /// キーが離されたなら、入力信号をfalseに。パッド用。
-    pub fn pad_release(&mut self, btn: Button) {
+    /// ボタンが離されたら、入力を溜めておく。パッド用。
+    pub fn pad_release(&mut self, bindings: &Bindings, btn: Button) {
         // 1pコンでのみ動作
-        self.user_pad_controll(btn, false);
+        if self.pads_down.remove(&btn) {
+            self.actions.up(bindings.pad_action(btn));
+        }
     }
-    
+
     // アナログスティック入力を振り分ける
     pub fn axis_controll(&mut self, axis: Axis, value: i16) {
         match axis {
@@ -121,80 +252,154 @@ impl InputState {
             _ => (),
         }
     }
-    
-    /// 入力キーごとに判定を変えるのがココ。キーボード用。
-    fn user_key_controll(&mut self,
-                         keycode: Keycode,
-                         pressed: bool) {
-        /*
-          キーごとに反応を返す
-          愚直にboolを使うことにした
-          
-          キー入力がある時 == true
-        
-          キー入力がない時  == false
-        */
-        
-        // 「なにかキーが押されたら〜〜」イベント用に
-        self.any_key = pressed;
-                
-        // 方向キー、wasdキー、hjklキー(vim配列)に対応。
-        match keycode {
-            // 方向キー
-            Keycode::Up     => self.move_up = pressed,
-            Keycode::Down   => self.move_down = pressed,
-            Keycode::Left   => self.move_left = pressed,
-            Keycode::Right  => self.move_right = pressed,
-            // wasdキー
-            Keycode::W      => self.move_up = pressed,
-            Keycode::A      => self.move_left = pressed,
-            Keycode::S      => self.move_down = pressed,
-            Keycode::D      => self.move_right = pressed,
-            // vim配列(hjklキー)
-            Keycode::K      => self.move_up = pressed,
-            Keycode::H      => self.move_left = pressed,
-            Keycode::J      => self.move_down = pressed,
-            Keycode::L      => self.move_right = pressed,
-            // 低速移動
-            Keycode::LShift => self.speed_down = pressed,
-            Keycode::RShift => self.speed_down = pressed,
-            // ゲームリスタート
-            Keycode::R      => self.game_reset = pressed,
-            // タイトル画面へ
-            Keycode::T      => self.game_title = pressed,
-            // ゲーム終了
-            Keycode::Q      => self.game_quit = pressed,
-            // デバッグ用キー
-            Keycode::M      => self.key_m = pressed,
-            _ => (), // Do nothing
-        }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use conf::Controls;
+
+    fn bindings() -> Bindings {
+        Bindings::new(&Controls::default()).unwrap()
     }
-    
-    /// 入力ボタンごとに判定を変える。ゲームパッド用。
-    fn user_pad_controll(&mut self,
-                         btn: Button,
-                         pressed: bool) {
-        /* 
-          とりあえずわかりやすいボタンに対するやつ
-          ggezの仕様でxbox360コン準拠。
-          
-          ボタン入力がある時 == true
-        
-          ボタン入力がない時 == false
-        */
-        
-        self.any_key = pressed;
-        
-        match btn {
-            // 十字キーでの移動
-            Button::DPadUp => self.move_up = pressed,
-            Button::DPadDown => self.move_down = pressed,
-            Button::DPadLeft => self.move_left = pressed,
-            Button::DPadRight => self.move_right = pressed,
-            // 低速移動
-            Button::LeftShoulder => self.speed_down = pressed,
-            Button::RightShoulder => self.speed_down = pressed,
-            _ => (), // Do nothing
+
+    fn press(input: &mut InputState, b: &Bindings, keycode: Keycode) {
+        input.key_press(b, keycode, Mod::empty());
+    }
+
+    fn release(input: &mut InputState, b: &Bindings, keycode: Keycode) {
+        input.key_release(b, keycode, Mod::empty());
+    }
+
+    #[test]
+    fn events_wait_for_begin_tick() {
+        let b = bindings();
+        let mut input = InputState::new();
+
+        press(&mut input, &b, Keycode::Left);
+        // begin_tick()を呼ぶまでは、前のフレームのまま
+        assert!(!input.pressed(Action::MoveLeft));
+        assert!(!input.move_left);
+
+        input.begin_tick();
+        assert!(input.pressed(Action::MoveLeft));
+        assert!(input.held(Action::MoveLeft));
+        assert!(input.move_left);
+        assert!(input.any_pressed());
+    }
+
+    #[test]
+    fn held_key_is_pressed_once_then_held() {
+        let b = bindings();
+        let mut input = InputState::new();
+
+        press(&mut input, &b, Keycode::Up);
+        input.begin_tick();
+        assert!(input.pressed(Action::MoveUp));
+
+        for _ in 0..3 {
+            // キーリピートで何度来ても、押されたのは最初のフレームだけ
+            press(&mut input, &b, Keycode::Up);
+            input.begin_tick();
+            assert!(!input.pressed(Action::MoveUp));
+            assert!(input.held(Action::MoveUp));
+            assert!(!input.released(Action::MoveUp));
+            assert!(!input.any_pressed());
         }
+
+        release(&mut input, &b, Keycode::Up);
+        input.begin_tick();
+        assert!(input.released(Action::MoveUp));
+        assert!(!input.held(Action::MoveUp));
+        assert!(!input.move_up);
+
+        input.begin_tick();
+        assert!(!input.released(Action::MoveUp));
+    }
+
+    #[test]
+    fn press_and_release_in_one_tick_still_counts() {
+        let b = bindings();
+        let mut input = InputState::new();
+
+        press(&mut input, &b, Keycode::R);
+        release(&mut input, &b, Keycode::R);
+        input.begin_tick();
+
+        // フレームの間に押して離しても、取りこぼさない
+        assert!(input.pressed(Action::Restart));
+        assert!(input.held(Action::Restart));
+        assert!(input.released(Action::Restart));
+
+        input.begin_tick();
+        assert!(!input.pressed(Action::Restart));
+        assert!(!input.held(Action::Restart));
+        assert!(!input.released(Action::Restart));
+    }
+
+    #[test]
+    fn keys_for_one_action_are_counted_together() {
+        let b = bindings();
+        let mut input = InputState::new();
+
+        // 左移動にはLeftとAの両方が割り当たっている
+        press(&mut input, &b, Keycode::Left);
+        input.begin_tick();
+        press(&mut input, &b, Keycode::A);
+        input.begin_tick();
+        assert!(!input.pressed(Action::MoveLeft));
+
+        release(&mut input, &b, Keycode::Left);
+        input.begin_tick();
+        assert!(input.held(Action::MoveLeft));
+        assert!(!input.released(Action::MoveLeft));
+
+        release(&mut input, &b, Keycode::A);
+        input.begin_tick();
+        assert!(input.released(Action::MoveLeft));
+        assert!(!input.held(Action::MoveLeft));
+    }
+
+    #[test]
+    fn unbound_keys_only_count_as_any_pressed() {
+        let b = bindings();
+        let mut input = InputState::new();
+
+        press(&mut input, &b, Keycode::Space);
+        input.begin_tick();
+
+        assert!(input.any_pressed());
+        assert!(Action::all().iter().all(|a| !input.held(*a)));
+    }
+
+    #[test]
+    fn reset_on_focus_loss_drops_held_keys() {
+        let b = bindings();
+        let mut input = InputState::new();
+
+        press(&mut input, &b, Keycode::Right);
+        input.pad_press(&b, Button::DPadUp);
+        input.axis_controll(Axis::LeftX, 12000);
+        input.begin_tick();
+        assert!(input.move_right && input.move_up);
+
+        // フォーカスを失ったら、押しっぱなしの入力を全部捨てる
+        input.reset();
+        input.begin_tick();
+        assert!(!input.held(Action::MoveRight));
+        assert!(!input.move_right && !input.move_up);
+        assert_eq!(input.axis_lx, 0);
+
+        // 戻ってきてから離しても、離された扱いにはならない
+        release(&mut input, &b, Keycode::Right);
+        input.pad_release(&b, Button::DPadUp);
+        input.begin_tick();
+        assert!(!input.released(Action::MoveRight));
+        assert!(!input.released(Action::MoveUp));
+
+        // 押し直せば、また反応する
+        press(&mut input, &b, Keycode::Right);
+        input.begin_tick();
+        assert!(input.pressed(Action::MoveRight));
     }
 }
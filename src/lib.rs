@@ -1,19 +1,33 @@
+extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate toml;
 extern crate ggez;
 extern crate rand;
-
-/// 自前のやつ
-extern crate range_checker; 
+extern crate app_dirs;
 
 pub mod args;
 // audio機能は一旦凍結
 //pub mod audio;
 pub mod assets;
+pub mod collision;
 pub mod conf;
+pub mod conf_check;
+pub mod controls;
 pub mod core_state;
+pub mod difficulty;
+pub mod enemy;
+pub mod error;
 pub mod etc;
 pub mod game_state;
+pub mod high_score;
+pub mod hitbox;
 pub mod input_state;
+pub mod replay;
+pub mod run_mode;
+pub mod scene;
+pub mod spawn_plan;
 pub mod view;
+pub mod waves;
+
+pub use error::{ Error, Result };
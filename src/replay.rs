@@ -0,0 +1,539 @@
+/*-------------------------------
+            replay.rs
+
+  リプレイの記録と再生をまとめる
+
+  一フレームごとの入力内容と、シード値・固定FPS数値・難易度を保存しておけば、
+  GameStateに同じ入力を流し込むことでゲームを丸ごと再現できる。
+  ウィンドウの大きさと岩のプールの大きさは再生側で合わせられないので、
+  記録しておいて再生前に比べる。
+
+  ファイル形式（数値はすべてリトルエンディアン）:
+    * "DRRP"            : マジックナンバー
+    * u8                : フォーマットバージョン
+    * u64               : シード値
+    * u32               : 固定FPS数値
+    * u8                : 難易度（Difficulty::all()の中での番号）
+    * u32               : ウィンドウ横幅
+    * u32               : ウィンドウ縦幅
+    * u32               : 岩のプールの大きさ（max_enemies）
+    * u64               : waves.tomlのハッシュ値
+    * u32               : 以下に続くランの数
+    * ランの数だけ繰り返し:
+      * u16 : 同じ入力が続いたフレーム数
+      * i16 : 左アナログスティック横軸
+      * i16 : 左アナログスティック縦軸
+      * u8  : 十字キー・低速移動のビットフラグ
+
+  * struct ReplayFrame: 一フレーム分の入力内容
+  * struct Replay     : 記録したリプレイ本体
+  * struct ReplayPlayer: リプレイを先頭から一フレームずつ取り出す
+
+  * impl ReplayFrame:
+    * from_input() : InputStateから必要な部分を抜き出す
+    * to_input()   : InputStateに戻す
+
+  * impl Replay:
+    * new()        : GameStateの今の設定で、空のリプレイを作る
+    * check()      : 再生しようとしている設定が、記録した時と同じか調べる
+    * record()     : 一フレーム分の入力を記録する
+    * len()        : 記録したフレーム数
+    * save()       : ファイルに書き出す
+    * load()       : ファイルから読み込む
+
+  * impl ReplayPlayer:
+    * new()
+    * next_input() : 次のフレームの入力を取り出す
+    * rewind()     : 先頭に巻き戻す
+-------------------------------*/
+use std::fs;
+use std::io::{ self, BufReader, BufWriter, Read, Result, Write };
+use std::path::Path;
+
+use difficulty::Difficulty;
+use game_state::GameState;
+use input_state::InputState;
+
+/// リプレイファイルのマジックナンバー
+const REPLAY_MAGIC: &'static [u8; 4] = b"DRRP";
+/// リプレイファイルのフォーマットバージョン
+const REPLAY_VERSION: u8 = 1;
+/// ランより前の部分のバイト数（ランの数まで含む）
+const HEADER_BYTES: u64 = 4 + 1 + 8 + 4 + 1 + 4 + 4 + 4 + 8 + 4;
+/// ラン一つ分のバイト数
+const RUN_BYTES: u64 = 2 + 2 + 2 + 1;
+
+// ReplayFrame.flags用のビット
+const FLAG_UP: u8 = 1;
+const FLAG_DOWN: u8 = 1 << 1;
+const FLAG_LEFT: u8 = 1 << 2;
+const FLAG_RIGHT: u8 = 1 << 3;
+const FLAG_SPEED_DOWN: u8 = 1 << 4;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// 一フレーム分の入力内容。ゲーム進行に関わる部分だけを持つ
+pub struct ReplayFrame {
+    pub axis_lx: i16,
+    pub axis_ly: i16,
+    pub flags: u8,
+}
+
+impl ReplayFrame {
+    /// InputStateから、ゲーム進行に関わる入力だけを抜き出す
+    pub fn from_input(input: &InputState) -> Self {
+        let mut flags = 0;
+
+        if input.move_up { flags |= FLAG_UP; }
+        if input.move_down { flags |= FLAG_DOWN; }
+        if input.move_left { flags |= FLAG_LEFT; }
+        if input.move_right { flags |= FLAG_RIGHT; }
+        if input.speed_down { flags |= FLAG_SPEED_DOWN; }
+
+        ReplayFrame {
+            axis_lx: input.axis_lx,
+            axis_ly: input.axis_ly,
+            flags: flags,
+        }
+    }
+
+    /// InputStateの形に戻す
+    pub fn to_input(&self) -> InputState {
+        let mut input = InputState::new();
+
+        input.axis_lx = self.axis_lx;
+        input.axis_ly = self.axis_ly;
+        input.move_up = self.flags & FLAG_UP != 0;
+        input.move_down = self.flags & FLAG_DOWN != 0;
+        input.move_left = self.flags & FLAG_LEFT != 0;
+        input.move_right = self.flags & FLAG_RIGHT != 0;
+        input.speed_down = self.flags & FLAG_SPEED_DOWN != 0;
+
+        input
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// 記録したリプレイ
+///
+/// 同じ入力が続くことが多いので、(フレーム数, 入力)のランとして持つ
+pub struct Replay {
+    /// このリプレイで使ったシード値
+    pub seed: u64,
+    /// このリプレイを記録した際の固定FPS数値
+    pub constant_fps: u32,
+    /// このリプレイを記録した際の難易度
+    pub difficulty: Difficulty,
+    /// このリプレイを記録した際のウィンドウ横幅
+    pub window_w: u32,
+    /// このリプレイを記録した際のウィンドウ縦幅
+    pub window_h: u32,
+    /// このリプレイを記録した際の、同時に出せる岩の数
+    pub max_enemies: u32,
+    /// このリプレイを記録した際のwaves.tomlのハッシュ値
+    pub waves_digest: u64,
+    runs: Vec<(u16, ReplayFrame)>,
+}
+
+impl Replay {
+    pub fn new(game_state: &GameState) -> Self {
+        Replay {
+            seed: game_state.system.seed,
+            constant_fps: game_state.system.constant_fps,
+            difficulty: game_state.difficulty,
+            window_w: game_state.system.window_w,
+            window_h: game_state.system.window_h,
+            max_enemies: game_state.actor.max_enemies as u32,
+            waves_digest: game_state.waves.digest,
+            runs: Vec::new(),
+        }
+    }
+
+    /// `game_state`の設定が、このリプレイを記録した時と同じかを調べる
+    ///
+    /// シード値・固定FPS数値・難易度は再生時にリプレイ側へ合わせるので見ない。
+    /// 違っていれば、違うものを並べた文字列を返す
+    pub fn check(&self, game_state: &GameState) -> ::std::result::Result<(), String> {
+        let mut problems = Vec::new();
+
+        if (self.window_w, self.window_h) !=
+            (game_state.system.window_w, game_state.system.window_h) {
+            problems.push(format!("ウィンドウの大きさ（記録時 {}x{}、今 {}x{}）",
+                                  self.window_w, self.window_h,
+                                  game_state.system.window_w, game_state.system.window_h));
+        }
+        if self.max_enemies as usize != game_state.actor.max_enemies {
+            problems.push(format!("max_enemies（記録時 {}、今 {}）",
+                                  self.max_enemies, game_state.actor.max_enemies));
+        }
+        if self.waves_digest != game_state.waves.digest {
+            problems.push("waves.tomlの中身".to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("記録した時と設定が違います: {}", problems.join(", ")))
+        }
+    }
+
+    /// 一フレーム分の入力を記録する
+    pub fn record(&mut self, input: &InputState) {
+        let frame = ReplayFrame::from_input(input);
+
+        // 直前と同じ入力なら、ランを伸ばすだけ
+        if let Some(last) = self.runs.last_mut() {
+            if last.1 == frame && last.0 < u16::max_value() {
+                last.0 += 1;
+                return;
+            }
+        }
+
+        self.runs.push((1, frame));
+    }
+
+    /// 記録したフレーム数
+    pub fn len(&self) -> usize {
+        self.runs.iter().map(|r| r.0 as usize).sum()
+    }
+
+    /// 何も記録されていないか
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    /// リプレイをファイルに書き出す
+    pub fn save<'a>(&self, path: &'a Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut f = BufWriter::new(fs::File::create(path)?);
+
+        f.write_all(REPLAY_MAGIC)?;
+        f.write_all(&[REPLAY_VERSION])?;
+        f.write_all(&self.seed.to_le_bytes())?;
+        f.write_all(&self.constant_fps.to_le_bytes())?;
+        f.write_all(&[self.difficulty.index() as u8])?;
+        f.write_all(&self.window_w.to_le_bytes())?;
+        f.write_all(&self.window_h.to_le_bytes())?;
+        f.write_all(&self.max_enemies.to_le_bytes())?;
+        f.write_all(&self.waves_digest.to_le_bytes())?;
+        f.write_all(&(self.runs.len() as u32).to_le_bytes())?;
+
+        for &(cnt, frame) in &self.runs {
+            f.write_all(&cnt.to_le_bytes())?;
+            f.write_all(&frame.axis_lx.to_le_bytes())?;
+            f.write_all(&frame.axis_ly.to_le_bytes())?;
+            f.write_all(&[frame.flags])?;
+        }
+
+        f.flush()?;
+        Ok(())
+    }
+
+    /// リプレイをファイルから読み込む
+    pub fn load<'a>(path: &'a Path) -> Result<Self> {
+        let file = fs::File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut f = BufReader::new(file);
+
+        let mut magic = [0; 4];
+        f.read_exact(&mut magic)?;
+        if &magic != REPLAY_MAGIC {
+            return Err(invalid_data("リプレイファイルではありません"));
+        }
+
+        let mut version = [0; 1];
+        f.read_exact(&mut version)?;
+        if version[0] != REPLAY_VERSION {
+            return Err(invalid_data("対応していないリプレイファイルのバージョンです"));
+        }
+
+        let mut buf8 = [0; 8];
+        let mut buf4 = [0; 4];
+        let mut buf2 = [0; 2];
+
+        f.read_exact(&mut buf8)?;
+        let seed = u64::from_le_bytes(buf8);
+
+        f.read_exact(&mut buf4)?;
+        let constant_fps = u32::from_le_bytes(buf4);
+        if constant_fps == 0 {
+            return Err(invalid_data("リプレイファイルの固定FPS数値が0です"));
+        }
+
+        let mut difficulty = [0; 1];
+        f.read_exact(&mut difficulty)?;
+        let difficulty = Difficulty::from_index(difficulty[0] as usize)
+            .ok_or_else(|| invalid_data("リプレイファイルの難易度が正しくありません"))?;
+
+        f.read_exact(&mut buf4)?;
+        let window_w = u32::from_le_bytes(buf4);
+        f.read_exact(&mut buf4)?;
+        let window_h = u32::from_le_bytes(buf4);
+        f.read_exact(&mut buf4)?;
+        let max_enemies = u32::from_le_bytes(buf4);
+        f.read_exact(&mut buf8)?;
+        let waves_digest = u64::from_le_bytes(buf8);
+
+        f.read_exact(&mut buf4)?;
+        let run_len = u64::from(u32::from_le_bytes(buf4));
+
+        // ランの数はファイルに書かれた値なので、そのまま信じて確保しない
+        if run_len > file_len.saturating_sub(HEADER_BYTES) / RUN_BYTES {
+            return Err(invalid_data("リプレイファイルのランの数がファイルの大きさと合いません"));
+        }
+
+        let mut runs = Vec::with_capacity(run_len as usize);
+        for _ in 0..run_len {
+            f.read_exact(&mut buf2)?;
+            let cnt = u16::from_le_bytes(buf2);
+            f.read_exact(&mut buf2)?;
+            let axis_lx = i16::from_le_bytes(buf2);
+            f.read_exact(&mut buf2)?;
+            let axis_ly = i16::from_le_bytes(buf2);
+            let mut flags = [0; 1];
+            f.read_exact(&mut flags)?;
+
+            runs.push((cnt, ReplayFrame {
+                axis_lx: axis_lx,
+                axis_ly: axis_ly,
+                flags: flags[0],
+            }));
+        }
+
+        Ok(Replay {
+            seed: seed,
+            constant_fps: constant_fps,
+            difficulty: difficulty,
+            window_w: window_w,
+            window_h: window_h,
+            max_enemies: max_enemies,
+            waves_digest: waves_digest,
+            runs: runs,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+/// リプレイを先頭から一フレームずつ取り出すためのカーソル
+pub struct ReplayPlayer {
+    pub replay: Replay,
+    run_idx: usize,
+    run_pos: u16,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        ReplayPlayer {
+            replay: replay,
+            run_idx: 0,
+            run_pos: 0,
+        }
+    }
+
+    /// 次のフレームの入力を取り出す。最後まで再生し終えたらNone
+    pub fn next_input(&mut self) -> Option<InputState> {
+        while self.run_idx < self.replay.runs.len() {
+            let (cnt, frame) = self.replay.runs[self.run_idx];
+
+            if self.run_pos < cnt {
+                self.run_pos += 1;
+                return Some(frame.to_input());
+            }
+
+            self.run_idx += 1;
+            self.run_pos = 0;
+        }
+
+        None
+    }
+
+    /// 先頭に巻き戻す
+    pub fn rewind(&mut self) {
+        self.run_idx = 0;
+        self.run_pos = 0;
+    }
+}
+
+/// 内部用。壊れたリプレイファイル用のエラーを作る
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+
+    use enemy::EnemyKind;
+    use game_state::SpriteInfo;
+    use hitbox::Hitbox;
+    use waves::Waves;
+
+    /// テスト用の一時ファイルパス
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("dodge_rock_test_{}_{}.drrp", name, std::process::id()))
+    }
+
+    /// 同じ入力が続く部分と、毎フレーム変わる部分を混ぜた入力列
+    fn sample_inputs() -> Vec<InputState> {
+        (0..300).map(|i| {
+            let mut input = InputState::new();
+            input.move_left = i < 100;
+            input.move_up = i % 7 == 0;
+            input.speed_down = i >= 250;
+            input.axis_lx = if i >= 200 { (i * 100) as i16 } else { 0 };
+            input.axis_ly = -3;
+            input
+        }).collect()
+    }
+
+    fn headless_state(window_w: u32, window_h: u32) -> GameState {
+        GameState::new(
+            window_w,
+            window_h,
+            60,
+            SpriteInfo::new(29, 48, Hitbox::full(29, 48)),
+            EnemyKind::all().iter().map(|_| SpriteInfo::new(32, 32, Hitbox::full(32, 32))).collect(),
+            Some(0xDEAD_BEEF_0000_0001),
+        ).unwrap()
+    }
+
+    fn sample_replay() -> Replay {
+        let mut game_state = headless_state(360, 480);
+        game_state.game_reset();
+
+        let mut replay = Replay::new(&game_state);
+        for input in &sample_inputs() {
+            replay.record(input);
+        }
+
+        replay
+    }
+
+    /// 保存したファイルのバイト列を書き換えて、読み込みの結果を返す
+    fn load_patched<F: FnOnce(&mut Vec<u8>)>(name: &str, patch: F) -> Result<Replay> {
+        let path = temp_path(name);
+        sample_replay().save(&path).unwrap();
+
+        let mut bytes = fs::read(&path).unwrap();
+        patch(&mut bytes);
+        fs::write(&path, &bytes).unwrap();
+
+        let result = Replay::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        result
+    }
+
+    #[test]
+    fn record_merges_identical_frames_into_runs() {
+        let replay = sample_replay();
+
+        assert_eq!(replay.len(), 300);
+        // 毎フレーム変わる部分があるので300未満、同じ入力の続く部分がまとまるので大きく減る
+        assert!(replay.runs.len() < 200);
+        assert!(replay.runs.iter().all(|r| r.0 > 0));
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let path = temp_path("round_trip");
+        let replay = sample_replay();
+        replay.save(&path).unwrap();
+
+        let file_len = fs::metadata(&path).unwrap().len();
+        assert_eq!(file_len, HEADER_BYTES + RUN_BYTES * replay.runs.len() as u64);
+
+        let loaded = Replay::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.seed, replay.seed);
+        assert_eq!(loaded.constant_fps, replay.constant_fps);
+        assert_eq!(loaded.difficulty, replay.difficulty);
+        assert_eq!((loaded.window_w, loaded.window_h), (360, 480));
+        assert_eq!(loaded.max_enemies, replay.max_enemies);
+        assert_eq!(loaded.waves_digest, replay.waves_digest);
+        assert_eq!(loaded.runs, replay.runs);
+
+        // 再生すると、記録した入力がそのまま出てくる
+        let mut player = ReplayPlayer::new(loaded);
+        for input in &sample_inputs() {
+            let played = player.next_input().unwrap();
+            assert_eq!(ReplayFrame::from_input(&played), ReplayFrame::from_input(input));
+        }
+        assert!(player.next_input().is_none());
+
+        player.rewind();
+        assert!(player.next_input().is_some());
+    }
+
+    #[test]
+    fn load_rejects_wrong_magic_and_version() {
+        let err = load_patched("magic", |b| b[0] = b'X').unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let err = load_patched("version", |b| b[4] = REPLAY_VERSION + 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn load_rejects_truncated_file() {
+        // 最後のランが途中で切れている
+        let err = load_patched("truncated", |b| { let n = b.len(); b.truncate(n - 3); }).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // ヘッダの途中で切れている
+        let err = load_patched("header", |b| b.truncate(10)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn load_rejects_oversized_run_len() {
+        // ランの数だけ巨大な値に書き換える
+        let at = (HEADER_BYTES - 4) as usize;
+        let err = load_patched("run_len", |b| {
+            b[at..at + 4].copy_from_slice(&u32::max_value().to_le_bytes());
+        }).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn check_reports_window_size_mismatch() {
+        let replay = sample_replay();
+
+        assert!(replay.check(&headless_state(360, 480)).is_ok());
+
+        let msg = replay.check(&headless_state(480, 640)).unwrap_err();
+        assert!(msg.contains("360x480"), "{}", msg);
+        assert!(msg.contains("480x640"), "{}", msg);
+    }
+
+    #[test]
+    fn check_reports_max_enemies_mismatch() {
+        let replay = sample_replay();
+        let mut game_state = headless_state(360, 480);
+        game_state.actor.max_enemies = replay.max_enemies as usize / 2;
+
+        let msg = replay.check(&game_state).unwrap_err();
+        assert!(msg.contains("max_enemies"), "{}", msg);
+    }
+
+    #[test]
+    fn check_reports_waves_mismatch() {
+        let replay = sample_replay();
+        let mut game_state = headless_state(360, 480);
+        assert!(replay.check(&game_state).is_ok());
+
+        // 中身が一文字でも違えば、別のwaves.tomlとみなす
+        let text = "start_speed = 61.0\n[[wave]]\nstart = 0.0\nspawn_interval = 4.0\n\
+                    accel = 12.0\nkinds = { block = 1 }\n";
+        game_state.waves = Waves::from_str(text).unwrap();
+
+        let msg = replay.check(&game_state).unwrap_err();
+        assert!(msg.contains("waves.toml"), "{}", msg);
+    }
+}
@@ -0,0 +1,139 @@
+/*-------------------------------
+            run_mode.rs
+
+  起動モードと、デバッグ機能ごとのオン・オフをまとめる
+
+  起動引数から作って、CoreStateに持たせて使い回す。
+  環境変数は使わないので、Args::new()を通さずに
+  ライブラリとして組み込んだ場合も、RunMode::Normalで動く
+
+  * struct DebugOptions: デバッグ機能ごとのオン・オフ
+  * enum RunMode: 通常モードかデバッグモードか
+
+  * impl DebugOptions:
+    * all()       : "-d"だけで起動した時の組み合わせ
+    * from_list() : "hitboxes,fps_log"のような文字列から作る
+    * names()     : オンになっている機能の名前一覧
+    * changes_play(): ゲーム展開を変えてしまう機能がオンか
+
+  * impl RunMode:
+    * is_debug(): デバッグモードか
+    * debug()   : デバッグ機能ごとのオン・オフ。通常モードなら全部オフ
+
+-------------------------------*/
+
+/// デバッグ機能の名前一覧。"-d"に続けて指定する時の書き方
+pub const DEBUG_OPTION_NAMES: [&'static str; 5] = [
+    "hitboxes",
+    "fps_log",
+    "spawn_key",
+    "invincible",
+    "spawn_stats",
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// デバッグ機能ごとのオン・オフ
+pub struct DebugOptions {
+    /// 自機と岩の当たり判定を描画する
+    pub hitboxes: bool,
+    /// 一秒ごとにFPSや敵の数を標準出力に出す
+    pub fps_log: bool,
+    /// デバッグキーで岩を一つ増やせるようにする
+    pub spawn_key: bool,
+    /// 岩に当たってもゲームオーバーにならない
+    pub invincible: bool,
+    /// 岩の出現位置を選び直した回数を、一秒ごとと、ゲームオーバー時に標準出力に出す
+    pub spawn_stats: bool,
+}
+
+impl DebugOptions {
+    /// "-d"だけで起動した時の組み合わせ
+    ///
+    /// 無敵はゲームが終わらなくなるので、名前を指定した時だけ。
+    /// 出現位置の集計も、計測したい時だけ名前を指定して使う
+    pub fn all() -> Self {
+        DebugOptions {
+            hitboxes: true,
+            fps_log: true,
+            spawn_key: true,
+            invincible: false,
+            spawn_stats: false,
+        }
+    }
+
+    /// "hitboxes,fps_log"のような、カンマ区切りの名前一覧から作る
+    ///
+    /// 知らない名前があればエラー
+    pub fn from_list<'a>(list: &'a str) -> Result<Self, String> {
+        let mut opts = DebugOptions::default();
+
+        for name in list.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            match name {
+                "hitboxes" => opts.hitboxes = true,
+                "fps_log" => opts.fps_log = true,
+                "spawn_key" => opts.spawn_key = true,
+                "invincible" => opts.invincible = true,
+                "spawn_stats" => opts.spawn_stats = true,
+                _ => {
+                    return Err(format!("知らないデバッグ機能\"{}\"が指定された（指定できるもの: {}）",
+                                       name, DEBUG_OPTION_NAMES.join(", ")));
+                }
+            }
+        }
+
+        Ok(opts)
+    }
+
+    /// オンになっている機能の名前一覧
+    pub fn names(&self) -> Vec<&'static str> {
+        let flags = [self.hitboxes, self.fps_log, self.spawn_key, self.invincible,
+                     self.spawn_stats];
+
+        DEBUG_OPTION_NAMES.iter()
+            .zip(flags.iter())
+            .filter(|&(_, &on)| on)
+            .map(|(&name, _)| name)
+            .collect()
+    }
+
+    /// ゲーム展開を変えてしまう機能（岩を増やすキー・無敵）がオンか
+    ///
+    /// オンの間のゲームは、リプレイで再現できずスコアも比べられないので、
+    /// リプレイとハイスコア表には残さない
+    pub fn changes_play(&self) -> bool {
+        self.spawn_key || self.invincible
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// 起動モード
+pub enum RunMode {
+    /// 普通に遊ぶ
+    Normal,
+    /// デバッグモード。どの機能を使うかを持つ
+    Debug(DebugOptions),
+}
+
+impl Default for RunMode {
+    fn default() -> Self {
+        RunMode::Normal
+    }
+}
+
+impl RunMode {
+    /// デバッグモードか
+    pub fn is_debug(&self) -> bool {
+        match *self {
+            RunMode::Normal => false,
+            RunMode::Debug(_) => true,
+        }
+    }
+
+    /// デバッグ機能ごとのオン・オフ。通常モードなら全部オフ
+    pub fn debug(&self) -> DebugOptions {
+        match *self {
+            RunMode::Normal => DebugOptions::default(),
+            RunMode::Debug(opts) => opts,
+        }
+    }
+}
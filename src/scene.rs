@@ -0,0 +1,394 @@
+/*-------------------------------
+            scene.rs
+
+  ゲーム画面の切り替えを、明示的な状態遷移としてまとめる
+  CoreStateは今いるSceneを一つだけ持ち、
+  update()とdraw()をそのSceneに任せる
+
+  遷移はCoreState::change_scene()を通して行い、
+  その際に遷移元のexit()と遷移先のenter()が呼ばれる
+
+  * enum Scene: 画面の種類
+    * Title   : タイトル画面
+    * Playing : メインゲーム画面
+    * Paused  : 一時停止中
+    * Countdown: 一時停止から再開するまでのカウントダウン
+    * GameOver: ゲームオーバー画面
+
+  * impl Scene:
+    * enter()  : そのシーンに入った時の処理
+    * exit()   : そのシーンから出る時の処理
+    * update() : 一フレーム分の更新。遷移先があればSomeで返す
+    * render() : そのシーンの描画
+
+  * countdown_secs(): カウントダウンの残り秒数
+  * update_hud()  : HUD表示を今のゲーム状況に合わせる
+  * play_tick()   : メインゲームを一フレーム進める。リプレイの記録・再生もここで
+  * save_replay() : 終わったゲームのリプレイをファイルに書き出す
+  * record_score(): 終わったゲームのスコアをハイスコア表に記録する
+  * replay_path() : リプレイを保存する場所
+  * high_score_path(): 難易度ごとの、ハイスコア表を保存する場所
+  * debug_frames(): デバッグ用のフレーム表示
+-------------------------------*/
+use std::path::PathBuf;
+
+use ggez::{ self, Context, GameResult };
+
+use controls::Action;
+use core_state::CoreState;
+use difficulty::Difficulty;
+use game_state::GameState;
+use high_score::{ self, ScoreEntry };
+use replay::Replay;
+use view;
+
+/// 一時停止から再開するまでのカウントダウン秒数
+pub const RESUME_COUNTDOWN_SECS: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// ゲーム画面の種類
+pub enum Scene {
+    /// タイトル画面
+    Title,
+    /// メインゲーム画面
+    Playing,
+    /// 一時停止中
+    Paused,
+    /// 一時停止から再開するまでのカウントダウン
+    Countdown,
+    /// ゲームオーバー画面
+    GameOver,
+}
+
+impl Scene {
+    /// そのシーンに入った時の処理
+    pub fn enter(&self,
+                 core: &mut CoreState,
+                 ctx: &mut Context) -> GameResult<()> {
+        match *self {
+            Scene::Countdown => {
+                core.countdown = RESUME_COUNTDOWN_SECS
+                    * core.game_state.system.constant_fps as usize;
+                core.text.new_countdown(
+                    ctx,
+                    &core.assets.pixel_font_big,
+                    RESUME_COUNTDOWN_SECS)?;
+            }
+            Scene::GameOver => {
+                if core.run_mode.debug().spawn_stats {
+                    println!("{}", core.game_state.spawn_stats.summary());
+                }
+
+                // リプレイ再生と、展開を変えるデバッグ機能を使ったゲームは
+                // ハイスコアにもリプレイにも残さない
+                let changes_play = core.run_mode.debug().changes_play();
+                if core.playback.is_none() && !changes_play {
+                    save_replay(core, ctx);
+                    record_score(core, ctx);
+                } else {
+                    if core.playback.is_none() {
+                        println!("デバッグ機能（spawn_key, invincible）が有効なので、リプレイとハイスコアは保存しません");
+                    }
+                    core.new_record_rank = None;
+                }
+
+                // 一ゲームに一度だけスコアとハイスコア表を印字
+                core.text.new_score(
+                    ctx,
+                    &core.assets.pixel_font,
+                    core.game_state.system.elapsed)?;
+                core.text.new_high_scores(
+                    ctx,
+                    &core.assets.pixel_font_small,
+                    &core.high_scores)?;
+            }
+            Scene::Playing => {
+                // リスタート直後の一フレーム目から、新しいゲームの値を出す
+                update_hud(core, ctx)?;
+            }
+            Scene::Title | Scene::Paused => (),
+        }
+
+        Ok(())
+    }
+
+    /// そのシーンから出る時の処理
+    pub fn exit(&self,
+                core: &mut CoreState,
+                _ctx: &mut Context) -> GameResult<()> {
+        match *self {
+            Scene::GameOver => {
+                // リスタートでもタイトル行きでも、ゲーム状態は初期化しておく
+                core.game_state.game_reset();
+            }
+            Scene::Title | Scene::Playing | Scene::Paused | Scene::Countdown => (),
+        }
+
+        Ok(())
+    }
+
+    /// 一フレーム分の更新
+    ///
+    /// 別のシーンに移るなら、その遷移先をSomeで返す
+    pub fn update(&self,
+                  core: &mut CoreState,
+                  ctx: &mut Context) -> GameResult<Option<Scene>> {
+        let next = match *self {
+            Scene::Title => {
+                // 左右で難易度を選ぶ。リプレイ再生中は記録した難易度のまま
+                let difficulty = core.game_state.difficulty;
+                let chosen = if core.playback.is_some() {
+                    None
+                } else if core.input.pressed(Action::MoveLeft) {
+                    Some(difficulty.prev())
+                } else if core.input.pressed(Action::MoveRight) {
+                    Some(difficulty.next())
+                } else {
+                    None
+                };
+
+                // 押しっぱなしのキーでタイトルを飛ばさないように、押された瞬間だけ見る
+                if let Some(d) = chosen {
+                    if d != difficulty {
+                        core.set_difficulty(ctx, d)?;
+                    }
+                    None
+                } else if core.input.any_pressed() {
+                    Some(Scene::Playing)
+                } else {
+                    None
+                }
+            }
+            Scene::Playing => {
+                if core.input.pressed(Action::Pause) {
+                    return Ok(Some(Scene::Paused));
+                }
+
+                play_tick(core);
+                update_hud(core, ctx)?;
+
+                let debug = core.run_mode.debug();
+                // リプレイ再生中に岩を増やすと、記録した展開からずれる
+                if debug.spawn_key && core.playback.is_none() {
+                    core.game_state.debug_key(&core.input);
+                }
+                if debug.fps_log {
+                    debug_frames(ctx, &core.game_state);
+                }
+                if debug.spawn_stats && core.game_state.system.crossed(1.0) > 0 {
+                    println!("{}", core.game_state.spawn_stats.summary());
+                }
+
+                if core.game_state.system.is_crashed {
+                    Some(Scene::GameOver)
+                } else {
+                    None
+                }
+            }
+            Scene::Paused => {
+                // クリックでの再開はCoreState::mouse_button_down_event()で
+                if core.input.pressed(Action::Pause) {
+                    // いきなり再開すると避けられないので、カウントダウンを挟む
+                    Some(Scene::Countdown)
+                } else if core.input.pressed(Action::Restart) {
+                    // ゲームを最初からやり直す
+                    core.game_state.game_reset();
+                    Some(Scene::Playing)
+                } else if core.input.pressed(Action::Title) {
+                    // タイトル画面へ
+                    core.game_state.game_reset();
+                    Some(Scene::Title)
+                } else if core.input.pressed(Action::Quit) {
+                    // ゲーム終了
+                    ctx.quit()?;
+                    None
+                } else {
+                    None
+                }
+            }
+            Scene::Countdown => {
+                if core.input.pressed(Action::Pause) {
+                    return Ok(Some(Scene::Paused));
+                }
+
+                let prev_secs = countdown_secs(core);
+                core.countdown = core.countdown.saturating_sub(1);
+
+                if core.countdown == 0 {
+                    Some(Scene::Playing)
+                } else {
+                    // 表示する秒数が変わった時だけTextを作り直す
+                    let secs = countdown_secs(core);
+                    if secs != prev_secs {
+                        core.text.new_countdown(
+                            ctx,
+                            &core.assets.pixel_font_big,
+                            secs)?;
+                    }
+                    None
+                }
+            }
+            Scene::GameOver => {
+                // 押しっぱなしでリスタートを繰り返さないよう、押された瞬間だけ見る
+                if core.input.pressed(Action::Restart) {
+                    // ゲームを再度はじめる
+                    Some(Scene::Playing)
+                } else if core.input.pressed(Action::Title) {
+                    // タイトル画面へ
+                    Some(Scene::Title)
+                } else if core.input.pressed(Action::Quit) {
+                    // ゲーム終了
+                    ctx.quit()?;
+                    None
+                } else {
+                    None
+                }
+            }
+        };
+
+        Ok(next)
+    }
+
+    /// そのシーンの描画
+    pub fn render(&self,
+                  core: &mut CoreState,
+                  ctx: &mut Context) -> GameResult<()> {
+        // どのシーンでも、背景としてメインゲーム画面を描画
+        view::render_game(core, ctx)?;
+
+        match *self {
+            Scene::Title => view::render_title(core, ctx)?,
+            // ゲームオーバー時にダイアログボックスを出す
+            Scene::GameOver => view::render_game_over(core, ctx)?,
+            Scene::Countdown => {
+                view::render_hud(core, ctx)?;
+                view::render_countdown(core, ctx)?;
+            }
+            Scene::Paused => {
+                view::render_hud(core, ctx)?;
+                view::render_pause(core, ctx)?;
+            }
+            Scene::Playing => view::render_hud(core, ctx)?,
+        }
+
+        Ok(())
+    }
+}
+
+/// カウントダウンの残り秒数（切り上げ）
+fn countdown_secs(core: &CoreState) -> usize {
+    let fps = core.game_state.system.constant_fps as usize;
+    (core.countdown + fps - 1) / fps
+}
+
+/// HUD表示を、今の経過時間・岩の速さ・自己ベストに合わせる
+fn update_hud(core: &mut CoreState, ctx: &mut Context) -> GameResult<()> {
+    if !core.game_conf.hud.visible {
+        return Ok(());
+    }
+
+    let best = core.high_scores.best().map(|e| e.duration);
+    let game_state = &core.game_state;
+
+    core.text.new_hud(
+        ctx,
+        &core.assets.pixel_font_small,
+        &core.game_conf.hud,
+        game_state.system.elapsed,
+        game_state.speed_level(),
+        best)
+}
+
+/// メインゲームを一フレーム進める
+///
+/// 通常時は入力を記録しながら、リプレイ再生中は記録した入力で進める
+fn play_tick(core: &mut CoreState) {
+    // 新しいゲームの最初のフレームで、記録・再生を頭からやり直す
+    if core.game_state.system.frames == 0 {
+        core.replay = Replay::new(&core.game_state);
+
+        if let Some(ref mut player) = core.playback {
+            player.rewind();
+        }
+    }
+
+    match core.playback {
+        Some(ref mut player) => {
+            match player.next_input() {
+                Some(input) => core.game_state.tick(&input),
+                // 記録が尽きたら、そこでゲームオーバー扱い
+                None => core.game_state.system.is_crashed = true,
+            }
+        }
+        None => {
+            core.replay.record(&core.input);
+            core.game_state.tick(&core.input);
+        }
+    }
+}
+
+/// 終わったゲームのリプレイを、ユーザーデータフォルダに書き出す
+fn save_replay(core: &CoreState, ctx: &mut Context) {
+    let path = replay_path(ctx);
+
+    // リプレイ保存に失敗しても、ゲーム自体は続けられるようにする
+    match core.replay.save(&path) {
+        Ok(_) => println!("リプレイを保存しました: {}", path.display()),
+        Err(e) => println!("Error: リプレイの保存に失敗 - {}", e),
+    }
+}
+
+/// 終わったゲームのスコアを、ハイスコア表に記録する
+fn record_score(core: &mut CoreState, ctx: &mut Context) {
+    let entry = {
+        let system = &core.game_state.system;
+        ScoreEntry::new(
+            system.elapsed,
+            system.seed,
+        )
+    };
+
+    core.new_record_rank = core.high_scores.add(entry);
+
+    // ランクインした時だけ書き出す
+    if core.new_record_rank.is_some() {
+        let path = high_score_path(ctx, core.game_state.difficulty);
+        if let Err(e) = core.high_scores.save(&path) {
+            println!("Error: ハイスコア表の保存に失敗 - {}", e);
+        }
+    }
+}
+
+/// 最後に遊んだゲームのリプレイを保存する場所
+fn replay_path(ctx: &mut Context) -> PathBuf {
+    let mut path = ctx.filesystem.get_user_data_dir().to_path_buf();
+    path.push("replays");
+    path.push("last.replay");
+
+    path
+}
+
+/// 難易度`difficulty`のハイスコア表を保存する場所
+pub fn high_score_path(ctx: &mut Context, difficulty: Difficulty) -> PathBuf {
+    ctx.filesystem.get_user_data_dir().join(high_score::file_name(difficulty))
+}
+
+/// デバッグ用のフレーム表示。とりあえず標準出力に出す。
+fn debug_frames(ctx: &mut Context, game_state: &GameState) {
+    // 一秒に一回だけ表示
+    if game_state.system.crossed(1.0) > 0 {
+        println!("FPS: {}, Seconds: {:.2}, EnemyLen: {} (Pool: {}), Dodged: {}",
+            ggez::timer::get_fps(ctx),
+            game_state.system.elapsed,
+            game_state.actor.live_count(),
+            game_state.actor.e_block.len(),
+            game_state.actor.dodged());
+        //println!("Player.x: {}, Player.y: {}, Player.w: {}, Player.h: {}",
+            //game_state.actor.player.x,
+            //game_state.actor.player.y,
+            //game_state.actor.player.width,
+            //game_state.actor.player.height);
+        //println!("Player.collision: {}", game_state.actor.player.collision);
+        println!("現在敵速度: {}", game_state.system.enemy_move_speed);
+    }
+}
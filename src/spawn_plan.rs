@@ -0,0 +1,292 @@
+/*-------------------------------
+            spawn_plan.rs
+
+  岩の出現位置が、通り抜けられない壁を作らないか、
+  ほかの岩と重ならないかを調べる
+
+  岩を出す前に、同じ高さ（横一列）にいる岩の横幅を並べて、
+  自機の当たり判定が通れる隙間が残るか、
+  さらにその隙間へ、列が降りてくるまでに自機が間に合うかを見る。
+  間に合わなければGameState側で出現位置を選び直す（game_state.rsを参照）
+
+  列が降りてくるまでの時間は、今の岩の速さでまっすぐ落ちるとして見積もる。
+  ジグザグ・ゆらゆら揺れる種類の岩は、揺れ幅（EnemyBehavior::sway()）の分だけ
+  左右に広げた矩形で調べる。斜めに動いて跳ね返る種類の動きまでは読まないので、
+  そこはあくまで出した時点での保証
+
+  * struct Ship      : 自機の当たり判定と速さ。隙間に間に合うかを見るのに使う
+  * struct Scratch   : 調べる時に使うVecの入れ物。GameStateに持たせて使い回す
+  * struct SpawnStats: 出現位置を選び直した回数の集計。デバッグ機能spawn_stats用
+
+  * impl SpawnStats:
+    * record()     : 一体出すごとに、選び直した回数などを足し込む
+    * block()      : 置ける場所がなく、出さなかった時に足し込む
+    * reroll_rate(): 選び直しが起きた割合
+    * summary()    : 集計結果を一行の文字列にする
+
+  * widen()     : 矩形を左右に広げる。揺れる岩の動く範囲を見るのに使う
+  * overlaps_any(): 候補の矩形が、今いる岩のどれかと重なるか
+  * is_fair()  : 候補の位置に岩を出しても、間に合う隙間が残るか
+  * gap_reachable(): 隙間一つが、自機の通れる幅で、かつ間に合う位置にあるか
+-------------------------------*/
+use collision::Aabb;
+
+/// 一度に出現位置を選び直す最大回数
+pub const MAX_REROLLS: usize = 16;
+/// 選び直しても駄目だった時に、出現位置を一列分上にずらす最大回数
+pub const MAX_LIFTS: usize = 3;
+/// 隙間に求める、自機の当たり判定の横幅からの余裕（ピクセル）
+pub const GAP_MARGIN: f32 = 4.0;
+
+#[derive(Clone, Copy, Debug)]
+/// 自機の当たり判定と速さ
+pub struct Ship {
+    /// 自機の当たり判定全体を包む矩形
+    pub bounds: Aabb,
+    /// 自機移動速度（ピクセル毎秒）
+    pub speed: f32,
+}
+
+#[derive(Clone, Debug, Default)]
+/// 出現位置を調べる時に使うVecの入れ物
+///
+/// 岩を出すたびに何度も調べるので、GameStateに持たせて使い回す。
+/// 岩の数が増えきった後は新たな確保をしない
+pub struct Scratch {
+    /// 今いる岩の当たり判定を包む矩形
+    pub rocks: Vec<Aabb>,
+    /// 今いる岩の画像の矩形。重なりを調べるのに使う
+    pub sprites: Vec<Aabb>,
+    /// is_fair()で、同じ列の岩の左右の範囲を並べる場所
+    pub spans: Vec<(f32, f32)>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// 出現位置を選び直した回数の集計
+///
+/// GameState::game_reset()で空に戻る
+pub struct SpawnStats {
+    /// 出した岩の数
+    pub spawns: usize,
+    /// 一回でも選び直しが起きた岩の数
+    pub rerolled: usize,
+    /// 選び直した回数の合計
+    pub rerolls: usize,
+    /// 上にずらして出した岩の数
+    pub lifted: usize,
+    /// 隙間の残る場所かほかの岩と重ならない場所がなく、出さなかった岩の数
+    pub blocked: usize,
+}
+
+impl SpawnStats {
+    /// 一体出すごとに、選び直した回数`rerolls`などを足し込む
+    pub fn record(&mut self, rerolls: usize, lifted: bool) {
+        self.spawns += 1;
+        self.rerolls += rerolls;
+
+        if rerolls > 0 {
+            self.rerolled += 1;
+        }
+        if lifted {
+            self.lifted += 1;
+        }
+    }
+
+    /// 置ける場所がなく、岩を出さなかった時に、選び直した回数`rerolls`を足し込む
+    pub fn block(&mut self, rerolls: usize) {
+        self.rerolls += rerolls;
+        self.blocked += 1;
+    }
+
+    /// 出した岩のうち、選び直しが起きた割合（0.0から1.0）
+    pub fn reroll_rate(&self) -> f64 {
+        if self.spawns == 0 {
+            0.0
+        } else {
+            self.rerolled as f64 / self.spawns as f64
+        }
+    }
+
+    /// 集計結果を一行の文字列にする
+    pub fn summary(&self) -> String {
+        format!("Spawns: {}, Rerolled: {} ({:.1}%), Rerolls: {}, Lifted: {}, Blocked: {}",
+                self.spawns,
+                self.rerolled,
+                self.reroll_rate() * 100.0,
+                self.rerolls,
+                self.lifted,
+                self.blocked)
+    }
+}
+
+/// 矩形`r`を、左右それぞれに`dx`ピクセルずつ広げる
+pub fn widen(r: &Aabb, dx: f32) -> Aabb {
+    (r.0 - dx, r.1, r.2 + dx * 2.0, r.3)
+}
+
+/// 矩形`candidate`が、`rocks`のどれかと重なるか。辺が接しているだけなら重ならない
+pub fn overlaps_any(rocks: &[Aabb], candidate: &Aabb) -> bool {
+    rocks.iter().any(|r| {
+        r.0 < candidate.0 + candidate.2 && candidate.0 < r.0 + r.2 &&
+            r.1 < candidate.1 + candidate.3 && candidate.1 < r.1 + r.3
+    })
+}
+
+/// 矩形`candidate`の位置に岩を出しても、自機が間に合う隙間が残るか
+///
+/// `rocks`は今いる岩の当たり判定を包む矩形。
+/// 候補と縦に自機一つ分も離れていない岩は、同じ列として横幅を`spans`に並べる。
+/// `spans`の中身は最初に空にするので、使い回しのVecを渡せば良い
+pub fn is_fair(rocks: &[Aabb],
+               candidate: &Aabb,
+               ship: &Ship,
+               enemy_speed: f32,
+               window_w: f32,
+               spans: &mut Vec<(f32, f32)>) -> bool {
+    let (top, bottom) = (candidate.1 - ship.bounds.3,
+                         candidate.1 + candidate.3 + ship.bounds.3);
+
+    spans.clear();
+    spans.extend(rocks.iter()
+        .filter(|r| r.1 < bottom && top < r.1 + r.3)
+        .map(|r| (r.0, r.0 + r.2)));
+    spans.push((candidate.0, candidate.0 + candidate.2));
+    spans.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(::std::cmp::Ordering::Equal));
+
+    // 列が自機の高さまで降りてくるまでに、自機が横に動ける距離
+    let time = ((ship.bounds.1 - (candidate.1 + candidate.3)) / enemy_speed.max(1.0)).max(0.0);
+    let reach = ship.speed * time;
+
+    // 左端から順に、岩と岩の間の隙間を調べる
+    let mut left = 0.0_f32;
+    for &(start, end) in spans.iter() {
+        if gap_reachable(left, start, ship, reach) {
+            return true;
+        }
+        left = left.max(end);
+    }
+
+    gap_reachable(left, window_w, ship, reach)
+}
+
+/// 左右座標値`left`から`right`までの隙間が、自機の通れる幅で、
+/// かつ今の位置から`reach`ピクセル以内に入れるか
+fn gap_reachable(left: f32, right: f32, ship: &Ship, reach: f32) -> bool {
+    let need = ship.bounds.2 + GAP_MARGIN;
+
+    if right - left < need {
+        return false;
+    }
+
+    // 隙間の中で、自機の当たり判定の左端を置ける範囲
+    let (lo, hi) = (left + GAP_MARGIN / 2.0, right - need + GAP_MARGIN / 2.0);
+    let nearest = ship.bounds.0.max(lo).min(hi);
+
+    (nearest - ship.bounds.0).abs() <= reach
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 画面下の方、x = 100にいる20x20の自機。毎秒200ピクセル動ける
+    fn ship() -> Ship {
+        Ship {
+            bounds: (100.0, 400.0, 20.0, 20.0),
+            speed: 200.0,
+        }
+    }
+
+    /// 画面上端の一列を、左右座標値`gap_left`から`gap_right`の隙間だけ残して埋める
+    ///
+    /// 左側を今いる岩、右側を出そうとしている岩にする
+    fn wall(gap_left: f32, gap_right: f32) -> (Vec<Aabb>, Aabb) {
+        (vec![(0.0, 0.0, gap_left, 30.0)], (gap_right, 0.0, 360.0 - gap_right, 30.0))
+    }
+
+    #[test]
+    fn is_fair_accepts_gap_wider_than_ship() {
+        let (rocks, candidate) = wall(100.0, 130.0);
+        let mut spans = Vec::new();
+
+        assert!(is_fair(&rocks, &candidate, &ship(), 100.0, 360.0, &mut spans));
+    }
+
+    #[test]
+    fn is_fair_rejects_gap_narrower_than_ship() {
+        let (rocks, candidate) = wall(100.0, 118.0);
+        let mut spans = Vec::new();
+
+        assert!(!is_fair(&rocks, &candidate, &ship(), 100.0, 360.0, &mut spans));
+    }
+
+    #[test]
+    fn is_fair_rejects_unreachable_gap() {
+        // 隙間は画面の右端。岩が速すぎて、列が降りてくるまでに自機が間に合わない
+        let rocks = vec![(0.0, 0.0, 150.0, 30.0)];
+        let candidate = (150.0, 0.0, 170.0, 30.0);
+        let mut spans = Vec::new();
+
+        assert!(!is_fair(&rocks, &candidate, &ship(), 10000.0, 360.0, &mut spans));
+        // 岩が遅ければ間に合う
+        assert!(is_fair(&rocks, &candidate, &ship(), 100.0, 360.0, &mut spans));
+    }
+
+    #[test]
+    fn is_fair_ignores_rocks_in_other_rows() {
+        // 右側の隙間をふさぐ岩はずっと上にいるので、同じ列には並べない
+        let rocks = vec![(150.0, -200.0, 210.0, 30.0)];
+        let candidate = (0.0, 0.0, 200.0, 30.0);
+        let mut spans = Vec::new();
+
+        assert!(is_fair(&rocks, &candidate, &ship(), 100.0, 360.0, &mut spans));
+    }
+
+    #[test]
+    fn gap_reachable_needs_ship_width_plus_margin() {
+        let ship = ship();
+        let need = ship.bounds.2 + GAP_MARGIN;
+
+        assert!(gap_reachable(90.0, 90.0 + need, &ship, 1000.0));
+        assert!(!gap_reachable(90.0, 90.0 + need - 0.5, &ship, 1000.0));
+    }
+
+    #[test]
+    fn gap_reachable_checks_distance() {
+        // 隙間の左端に自機を置くには、200ピクセル右に動く必要がある
+        let ship = ship();
+        let left = 300.0 - GAP_MARGIN / 2.0;
+
+        assert!(gap_reachable(left, 360.0, &ship, 200.0));
+        assert!(!gap_reachable(left, 360.0, &ship, 199.0));
+    }
+
+    #[test]
+    fn overlaps_any_ignores_touching_edges() {
+        let rocks = vec![(0.0, 0.0, 10.0, 10.0)];
+
+        assert!(overlaps_any(&rocks, &(9.0, 9.0, 10.0, 10.0)));
+        assert!(!overlaps_any(&rocks, &(10.0, 0.0, 10.0, 10.0)));
+    }
+
+    #[test]
+    fn widen_grows_both_sides() {
+        assert_eq!(widen(&(100.0, 5.0, 20.0, 30.0), 50.0), (50.0, 5.0, 120.0, 30.0));
+        assert_eq!(widen(&(100.0, 5.0, 20.0, 30.0), 0.0), (100.0, 5.0, 20.0, 30.0));
+    }
+
+    #[test]
+    fn block_counts_rerolls_but_not_spawns() {
+        let mut stats = SpawnStats::default();
+        stats.record(0, false);
+        stats.record(3, true);
+        stats.block(68);
+
+        assert_eq!(stats.spawns, 2);
+        assert_eq!(stats.rerolled, 1);
+        assert_eq!(stats.rerolls, 71);
+        assert_eq!(stats.lifted, 1);
+        assert_eq!(stats.blocked, 1);
+        assert_eq!(stats.reroll_rate(), 0.5);
+    }
+}
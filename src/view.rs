@@ -9,23 +9,31 @@
   * render_game()  : ゲームの状況に合わせて、適切な部分を描画するおまとめ関数
   * render_player(): プレイヤー周りを描画する
   * render_enemy() :
-  * debug_render() :
+  * debug_render() : 当たり判定を描画する
+  * render_hitbox(): 当たり判定の形を一つずつ描く
+  * render_hud()   : 経過時間・岩の速さ・自己ベストを画面の隅に描画
   * render_title() :
   * render_title_dialog_text()
   * title_dialog_text_pos()
   * render_game_over()
   * render_game_over_dialog_text() : 名前が長すぎる
   * game_over_dialog_text_pos()
+  * render_pause() : 一時停止ダイアログを描画
+  * render_countdown(): 再開までのカウントダウンを描画
+  * render_focus_lost(): ウィンドウが非アクティブな時の表示を描画
+  * set_letterbox()    : ウィンドウに合わせてゲーム画面を拡大・中央寄せ
+  * render_letterbox() : ゲーム画面の外側を黒帯で塗る
+  * letterbox_rect()   : ウィンドウ全体をゲーム画面の座標系で表す
   * render_game_over_score()       : スコア部分を描画
+  * render_high_scores()           : ハイスコア表を描画。NEW RECORDも
   * calc_ml_text() * 複数行Vec<Text>のPoint2生成
   * draw_ml_text() : 複数行Vec<Text>を描画
 -------------------------------*/ 
-use std::env;
-
 use ggez::{ Context, GameResult };
-use ggez::graphics::{ self, Point2, Text};
+use ggez::graphics::{ self, Point2, Rect, Text};
 
 use core_state::CoreState;
+use hitbox::{ Hitbox, Shape };
 
 /// メインゲーム画面を描画
 pub fn render_game(core: &mut CoreState, ctx: &mut Context) -> GameResult<()> {
@@ -33,7 +41,7 @@ pub fn render_game(core: &mut CoreState, ctx: &mut Context) -> GameResult<()> {
     render_player(core, ctx)?;
     render_enemy(core, ctx)?;
     
-    if env::var("GAME_ACTIVATE_MODE").unwrap() == "DEBUG_MODE" {
+    if core.run_mode.debug().hitboxes {
         debug_render(core, ctx)?;
     }
     
@@ -58,16 +66,18 @@ fn render_player(core: &mut CoreState,
 fn render_enemy(core: &mut CoreState,
                 ctx: &mut Context) -> GameResult<()> {
     // いちいち書くのがだるいので、短縮ネームを変数束縛
-    let e_block = &core.game_state.actor.e_block;
+    let actor = &core.game_state.actor;
     
-    for li in e_block {
+    // 回収済みの枠は描かない
+    for li in actor.live_enemies() {
         let e_block_pos = Point2::new(
             li.x,
             li.y,
         );
         
+        // 画像は種類ごとに違う
         graphics::draw(ctx,
-                       &core.assets.enemy_block,
+                       &core.assets.enemy_block[li.kind.index()],
                        e_block_pos,
                        0.0)?;
     }
@@ -75,25 +85,33 @@ fn render_enemy(core: &mut CoreState,
     Ok(())
 }
 
-/// デバッグ引数がオンの時にだけ当たり判定を描画
+/// デバッグ機能のhitboxesがオンの時にだけ当たり判定を描画
+///
+/// 自機は塗りつぶし、岩は線で、読み込んだ形をそのまま描く
 fn debug_render(core: &mut CoreState,
                 ctx: &mut Context) -> GameResult<()> {
     let actor = &core.game_state.actor;
     
-    let p_col_rect = graphics::Rect::new(
-        actor.player.x + 10.0,
-        actor.player.y + 22.0,
-        (actor.player.width - 20) as f32,
-        (actor.player.height - 35) as f32,
-    );
-
     let p_color = graphics::Color::from_rgba(0, 0, 255, 230);
+    let e_color = graphics::Color::from_rgba(255, 0, 0, 230);
     
     graphics::set_color(ctx, p_color)?;
     
-    graphics::rectangle(ctx,
-                        graphics::DrawMode::Fill,
-                        p_col_rect)?;
+    render_hitbox(ctx,
+                  &actor.player.hitbox,
+                  actor.player.x,
+                  actor.player.y,
+                  graphics::DrawMode::Fill)?;
+    
+    graphics::set_color(ctx, e_color)?;
+    
+    for li in actor.live_enemies() {
+        render_hitbox(ctx,
+                      &li.hitbox,
+                      li.x,
+                      li.y,
+                      graphics::DrawMode::Line(1.0))?;
+    }
     
     graphics::set_color(ctx, 
                         core.assets.dialog.default_color)?;
@@ -101,6 +119,96 @@ fn debug_render(core: &mut CoreState,
     Ok(())
 }
 
+/// 内部用。(x, y)に置いた当たり判定の形を一つずつ描く
+fn render_hitbox(ctx: &mut Context,
+                 hitbox: &Hitbox,
+                 x: f32,
+                 y: f32,
+                 mode: graphics::DrawMode) -> GameResult<()> {
+    for shape in &hitbox.shapes {
+        match *shape {
+            Shape::Rect { x: sx, y: sy, w, h } => {
+                graphics::rectangle(ctx,
+                                    mode,
+                                    graphics::Rect::new(x + sx, y + sy, w, h))?;
+            }
+            Shape::Circle { x: sx, y: sy, r } => {
+                graphics::circle(ctx,
+                                 mode,
+                                 Point2::new(x + sx, y + sy),
+                                 r,
+                                 0.5)?;
+            }
+            Shape::Polygon { ref points } => {
+                let tmp_vec: Vec<Point2> = points.iter()
+                    .map(|p| Point2::new(x + p[0], y + p[1]))
+                    .collect();
+                
+                graphics::polygon(ctx, mode, &tmp_vec)?;
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// HUD（経過時間・岩の速さ・自己ベスト）を描画する
+///
+/// 表示位置はgame_config.tomlの[hud]で指定した画面の隅。
+/// 右側なら右寄せ、下側なら下から積む
+pub fn render_hud(core: &mut CoreState,
+                  ctx: &mut Context) -> GameResult<()> {
+    let hud_conf = &core.game_conf.hud;
+    let hud = &core.text.hud;
+    
+    if !hud_conf.visible || hud.is_empty() {
+        return Ok(());
+    }
+    
+    // 画面端からの余白と、行同士の間隔
+    let (margin, line_gap) = (8.0, 4.0);
+    let (window_w, window_h) = (
+        core.game_state.system.window_w as f32,
+        core.game_state.system.window_h as f32
+    );
+    
+    let is_right = hud_conf.position.ends_with("right");
+    let is_bottom = hud_conf.position.starts_with("bottom");
+    
+    let total_h = hud.iter()
+        .map(|t| t.height() as f32 + line_gap)
+        .sum::<f32>() - line_gap;
+    
+    let mut tmp_y = if is_bottom {
+        window_h - margin - total_h
+    } else {
+        margin
+    };
+    
+    graphics::set_color(ctx,
+                        core.assets.dialog.go_box_color)?;
+    
+    for li in hud {
+        let tmp_x = if is_right {
+            window_w - margin - li.width() as f32
+        } else {
+            margin
+        };
+        
+        graphics::draw(ctx,
+                       li,
+                       Point2::new(tmp_x.round(), tmp_y.round()),
+                       0.0)?;
+        
+        tmp_y += li.height() as f32 + line_gap;
+    }
+    
+    graphics::set_color(ctx,
+                        core.assets.dialog.default_color)?;
+    
+    Ok(())
+}
+
 /// タイトル画面を描画する
 pub fn render_title(core: &mut CoreState,
                     ctx: &mut Context) -> GameResult<()> {
@@ -127,7 +235,8 @@ fn render_title_dialog_text(core: &mut CoreState,
     let (title_logo_pos, 
          title_description_pos, 
          title_headline_pos, 
-         title_tips_pos) = title_dialog_text_pos(core);
+         title_tips_pos,
+         title_difficulty_pos) = title_dialog_text_pos(core);
     
     graphics::set_color(ctx,
                         core.assets.dialog.black_color)?;
@@ -147,6 +256,11 @@ fn render_title_dialog_text(core: &mut CoreState,
                  title_tips_pos,
                  0.0)?;
     
+    graphics::draw(ctx,
+                   &core.text.title_difficulty,
+                   title_difficulty_pos,
+                   0.0)?;
+    
     graphics::draw(ctx,
                    &core.text.title_description,
                    title_description_pos,
@@ -160,37 +274,40 @@ fn render_title_dialog_text(core: &mut CoreState,
 
 /// タイトル画面説明文のPoint2を設定する
 fn title_dialog_text_pos(core: &mut CoreState) 
-    -> (Point2, Point2, Point2, Vec<Point2>) {
+    -> (Point2, Point2, Point2, Vec<Point2>, Point2) {
     
-    let (window_w, window_h) = (
-        core.game_state.system.window_w as f32,
-        core.game_state.system.window_h as f32,
-    );
+    // 文字の配置はダイアログ用のレイアウト領域を基準にする
+    let layout = core.assets.dialog.layout;
     
     let title_logo_pos = Point2::new(
-        ((window_w - core.text.title_logo.width() as f32) / 2.0).round(),
-        ((window_h - core.text.title_logo.height() as f32 ) * 0.285).round(),
+        layout.x + ((layout.w - core.text.title_logo.width() as f32) / 2.0).round(),
+        layout.y + ((layout.h - core.text.title_logo.height() as f32 ) * 0.285).round(),
     );
     
     let title_description_pos = Point2::new(
-        ((window_w - core.text.title_description.width() as f32) / 2.0).round(),
-        ((window_h - core.text.title_description.height() as f32 ) * 0.72).round(),
+        layout.x + ((layout.w - core.text.title_description.width() as f32) / 2.0).round(),
+        layout.y + ((layout.h - core.text.title_description.height() as f32 ) * 0.72).round(),
     );
     
     let title_headline_pos = Point2::new(
-        ((window_w - core.text.title_headline.width() as f32) / 2.0).round(),
-        ((window_h - core.text.title_headline.height() as f32 ) * 0.435).round(),
+        layout.x + ((layout.w - core.text.title_headline.width() as f32) / 2.0).round(),
+        layout.y + ((layout.h - core.text.title_headline.height() as f32 ) * 0.435).round(),
     );
     
     let title_tips_pos = calc_ml_text_pos(
         &core.text.title_tips,
-        window_w,
-        window_h,
+        &layout,
         0.5,
         0.5,
     );
     
-    (title_logo_pos, title_description_pos, title_headline_pos, title_tips_pos)
+    let title_difficulty_pos = Point2::new(
+        layout.x + ((layout.w - core.text.title_difficulty.width() as f32) / 2.0).round(),
+        layout.y + ((layout.h - core.text.title_difficulty.height() as f32 ) * 0.64).round(),
+    );
+    
+    (title_logo_pos, title_description_pos, title_headline_pos, title_tips_pos,
+     title_difficulty_pos)
 }
 
 /// ゲームオーバー画面を描画する
@@ -234,6 +351,8 @@ fn render_game_over_dialog_text(core: &mut CoreState,
     
     render_game_over_score(core, ctx)?;
     
+    render_high_scores(core, ctx)?;
+    
     draw_ml_text(ctx,
                  &core.text.game_over_tips,
                  go_tip_pos,
@@ -247,27 +366,24 @@ fn render_game_over_dialog_text(core: &mut CoreState,
 
 /// Point2指定がやけに長いので、別関数に分けておく
 fn game_over_dialog_text_pos(core: &mut CoreState) -> (Point2, Point2, Vec<Point2>) {
-    let (window_w, window_h) = (
-        core.game_state.system.window_w as f32,
-        core.game_state.system.window_h as f32
-    );
+    // 文字の配置はダイアログ用のレイアウト領域を基準にする
+    let layout = core.assets.dialog.layout;
     
     let go_title_pos = Point2::new(
-        ((window_w - core.text.game_over_title.width() as f32) / 2.0).round(),
-        ((window_h - core.text.game_over_title.height() as f32 ) * 0.32).round(),
+        layout.x + ((layout.w - core.text.game_over_title.width() as f32) / 2.0).round(),
+        layout.y + ((layout.h - core.text.game_over_title.height() as f32 ) * 0.13).round(),
     );
     
     let go_score_pos = Point2::new(
-        ((window_w - core.text.game_over_score.width() as f32 ) / 2.0).round(),
-        ((window_h - core.text.game_over_score.height() as f32 ) * 0.42).round(),
+        layout.x + ((layout.w - core.text.game_over_score.width() as f32 ) / 2.0).round(),
+        layout.y + ((layout.h - core.text.game_over_score.height() as f32 ) * 0.24).round(),
     );
     
     let go_tip_pos = calc_ml_text_pos(
         &core.text.game_over_tips,
-        window_w,
-        window_h,
+        &layout,
         0.5,
-        0.568,
+        0.73,
     );
     
     (go_title_pos, go_score_pos, go_tip_pos)
@@ -275,41 +391,288 @@ fn game_over_dialog_text_pos(core: &mut CoreState) -> (Point2, Point2, Vec<Point
 
 fn render_game_over_score(core: &mut CoreState,
                        ctx: &mut Context) -> GameResult<()>{
-    // 一ゲーム中に一度だけスコアを印字
-    if !core.game_state.system.is_score_wrote {
-        core.text.new_score(
-            ctx,
-            &core.assets.pixel_font,
-            core.game_state.system.frames)?;
-        core.game_state.system.is_score_wrote = true;
+    // スコアのTextはScene::GameOverに入った時に作り直している
+    // 文字の配置はダイアログ用のレイアウト領域を基準にする
+    let layout = core.assets.dialog.layout;
+    
+    let go_score_num_pos = Point2::new(
+        layout.x + (layout.w - core.text.game_over_score_num.width() as f32) / 2.0,
+        layout.y + (layout.h - core.text.game_over_score_num.height() as f32 ) * 0.31,
+    );
+    
+    graphics::draw(ctx,
+                   &core.text.game_over_score_num,
+                   go_score_num_pos,
+                   0.0)?;
+    
+    Ok(())
+}
+
+/// ハイスコア表を描画する
+///
+/// 今回のスコアがランクインしていたら、その行を目立たせる
+/// 一位ならNEW RECORDも表示する
+fn render_high_scores(core: &mut CoreState,
+                      ctx: &mut Context) -> GameResult<()> {
+    // 文字の配置はダイアログ用のレイアウト領域を基準にする
+    let layout = core.assets.dialog.layout;
+    
+    if core.new_record_rank == Some(0) {
+        let new_record_pos = Point2::new(
+            layout.x + ((layout.w - core.text.game_over_new_record.width() as f32) / 2.0).round(),
+            layout.y + ((layout.h - core.text.game_over_new_record.height() as f32) * 0.37).round(),
+        );
+        
+        graphics::set_color(ctx,
+                            core.assets.dialog.highlight_color)?;
+        graphics::draw(ctx,
+                       &core.text.game_over_new_record,
+                       new_record_pos,
+                       0.0)?;
+        graphics::set_color(ctx,
+                            core.assets.dialog.black_color)?;
+    }
+    
+    if core.text.high_score_list.is_empty() {
+        return Ok(());
     }
     
+    let high_score_pos = Point2::new(
+        layout.x + ((layout.w - core.text.game_over_high_score.width() as f32) / 2.0).round(),
+        layout.y + ((layout.h - core.text.game_over_high_score.height() as f32) * 0.45).round(),
+    );
+    
+    graphics::draw(ctx,
+                   &core.text.game_over_high_score,
+                   high_score_pos,
+                   0.0)?;
+    
+    let list_pos = calc_ml_text_pos(
+        &core.text.high_score_list,
+        &layout,
+        0.5,
+        0.51,
+    );
+    
+    // ランクインした行だけ色を変えるので、draw_ml_text()は使わない
+    for (i, li) in core.text.high_score_list.iter().enumerate() {
+        let color = if core.new_record_rank == Some(i) {
+            core.assets.dialog.highlight_color
+        } else {
+            core.assets.dialog.black_color
+        };
+        
+        graphics::set_color(ctx, color)?;
+        graphics::draw(ctx, li, list_pos[i], 0.0)?;
+    }
+    
+    graphics::set_color(ctx,
+                        core.assets.dialog.black_color)?;
+    
+    Ok(())
+}
+
+/// 一時停止ダイアログを描画する
+pub fn render_pause(core: &mut CoreState,
+                    ctx: &mut Context) -> GameResult<()> {
+    // 文字の配置はダイアログ用のレイアウト領域を基準にする
+    let layout = core.assets.dialog.layout;
+    
+    // foreground colorを変える
+    graphics::set_color(ctx,
+                        core.assets.dialog.go_box_color)?;
+    
+    // ダイアログボックスを表示
+    graphics::rectangle(ctx,
+                        graphics::DrawMode::Fill,
+                        core.assets.dialog.pause_box)?;
+    
+    graphics::set_color(ctx,
+                        core.assets.dialog.black_color)?;
+    
+    let pause_title_pos = Point2::new(
+        layout.x + ((layout.w - core.text.pause_title.width() as f32) / 2.0).round(),
+        layout.y + ((layout.h - core.text.pause_title.height() as f32) * 0.32).round(),
+    );
+    
+    let pause_tip_pos = calc_ml_text_pos(
+        &core.text.pause_tips,
+        &layout,
+        0.5,
+        0.46,
+    );
+    
+    graphics::draw(ctx,
+                   &core.text.pause_title,
+                   pause_title_pos,
+                   0.0)?;
+    
+    draw_ml_text(ctx,
+                 &core.text.pause_tips,
+                 pause_tip_pos,
+                 0.0)?;
+    
+    // foreground colorを元に戻す
+    graphics::set_color(ctx,
+                        core.assets.dialog.default_color)?;
+    
+    Ok(())
+}
+
+/// 再開までのカウントダウンを、画面中央に大きく描画する
+pub fn render_countdown(core: &mut CoreState,
+                        ctx: &mut Context) -> GameResult<()> {
+    // 文字の配置はダイアログ用のレイアウト領域を基準にする
+    let layout = core.assets.dialog.layout;
+    
+    let countdown_pos = Point2::new(
+        layout.x + ((layout.w - core.text.countdown_num.width() as f32) / 2.0).round(),
+        layout.y + ((layout.h - core.text.countdown_num.height() as f32) / 2.0).round(),
+    );
+    
+    graphics::set_color(ctx,
+                        core.assets.dialog.go_box_color)?;
+    
+    graphics::draw(ctx,
+                   &core.text.countdown_num,
+                   countdown_pos,
+                   0.0)?;
+    
+    graphics::set_color(ctx,
+                        core.assets.dialog.default_color)?;
+    
+    Ok(())
+}
+
+/// ウィンドウが非アクティブな間、画面を暗くして一時停止中であることを示す
+pub fn render_focus_lost(core: &mut CoreState,
+                         ctx: &mut Context) -> GameResult<()> {
     let (window_w, window_h) = (
         core.game_state.system.window_w as f32,
         core.game_state.system.window_h as f32
     );
     
-    let go_score_num_pos = Point2::new(
-        (window_w - core.text.game_over_score_num.width() as f32) / 2.0,
-        (window_h - core.text.game_over_score_num.height() as f32 ) * 0.49,
+    graphics::set_color(ctx,
+                        core.assets.dialog.dim_color)?;
+    
+    graphics::rectangle(ctx,
+                        graphics::DrawMode::Fill,
+                        graphics::Rect::new(0.0, 0.0, window_w, window_h))?;
+    
+    let focus_lost_pos = Point2::new(
+        ((window_w - core.text.focus_lost.width() as f32) / 2.0).round(),
+        ((window_h - core.text.focus_lost.height() as f32) / 2.0).round(),
     );
     
+    graphics::set_color(ctx,
+                        core.assets.dialog.go_box_color)?;
+    
     graphics::draw(ctx,
-                   &core.text.game_over_score_num,
-                   go_score_num_pos,
+                   &core.text.focus_lost,
+                   focus_lost_pos,
                    0.0)?;
     
+    graphics::set_color(ctx,
+                        core.assets.dialog.default_color)?;
+    
+    Ok(())
+}
+
+/// ウィンドウの大きさに合わせて、ゲーム画面を拡大・中央寄せする
+///
+/// ウィンドウとゲーム画面の縦横比が違う場合、余った部分が黒帯になる
+pub fn set_letterbox(core: &mut CoreState,
+                     ctx: &mut Context) -> GameResult<()> {
+    let screen = letterbox_rect(core, ctx);
+    graphics::set_screen_coordinates(ctx, screen)?;
+    
+    Ok(())
+}
+
+/// ゲーム画面の外側を黒帯で塗りつぶす
+///
+/// 画面外から降ってくる岩などが、黒帯部分に見えないように最後に描画する
+pub fn render_letterbox(core: &mut CoreState,
+                        ctx: &mut Context) -> GameResult<()> {
+    let screen = letterbox_rect(core, ctx);
+    let (window_w, window_h) = (
+        core.game_state.system.window_w as f32,
+        core.game_state.system.window_h as f32
+    );
+    
+    // 左右・上下の余白。ぴったり収まっていれば0になる
+    let (margin_x, margin_y) = (-screen.x, -screen.y);
+    
+    if margin_x <= 0.0 && margin_y <= 0.0 {
+        return Ok(());
+    }
+    
+    let bars = [
+        // 左
+        Rect::new(screen.x, screen.y, margin_x, screen.h),
+        // 右
+        Rect::new(window_w, screen.y, margin_x, screen.h),
+        // 上
+        Rect::new(0.0, screen.y, window_w, margin_y),
+        // 下
+        Rect::new(0.0, window_h, window_w, margin_y),
+    ];
+    
+    graphics::set_color(ctx,
+                        core.assets.dialog.letterbox_color)?;
+    
+    for bar in bars.iter().filter(|b| b.w > 0.0 && b.h > 0.0) {
+        graphics::rectangle(ctx,
+                            graphics::DrawMode::Fill,
+                            *bar)?;
+    }
+    
+    graphics::set_color(ctx,
+                        core.assets.dialog.default_color)?;
+    
     Ok(())
 }
 
+/// 内部用。ウィンドウ全体を、ゲーム画面の座標系で表した矩形
+///
+/// (0, 0)からゲーム画面の大きさまでが、ウィンドウ中央に来るようにする
+fn letterbox_rect(core: &CoreState, ctx: &Context) -> Rect {
+    let (window_w, window_h) = (
+        core.game_state.system.window_w as f32,
+        core.game_state.system.window_h as f32
+    );
+    let (drawable_w, drawable_h) = graphics::get_drawable_size(ctx);
+    let (drawable_w, drawable_h) = (drawable_w as f32, drawable_h as f32);
+    
+    // 起動直後などで大きさが取れない時は、そのまま表示
+    if drawable_w <= 0.0 || drawable_h <= 0.0 {
+        return Rect::new(0.0, 0.0, window_w, window_h);
+    }
+    
+    // 縦横比を保ったまま、収まる最大の倍率
+    let mut scale = (drawable_w / window_w).min(drawable_h / window_h);
+    
+    // 整数倍指定があれば切り捨てる。等倍より小さい時は仕方がないのでそのまま
+    if core.game_conf.window.integer_scale && scale >= 1.0 {
+        scale = scale.floor();
+    }
+    
+    let (view_w, view_h) = (drawable_w / scale, drawable_h / scale);
+    
+    Rect::new(
+        -((view_w - window_w) / 2.0).round(),
+        -((view_h - window_h) / 2.0).round(),
+        view_w,
+        view_h,
+    )
+}
+
 /// 複数行に渡るテキストを描画するために、Vec<Point2>を作る
 fn calc_ml_text_pos(in_vec: &Vec<Text>,
-                    window_w: f32,
-                    window_h: f32,
+                    layout: &Rect,
                     width_adjust: f32,
                     height_adjust: f32,) -> Vec<Point2> {
-    // window_w: 画面の横サイズ
-    // window_h: 画面の縦サイズ
+    // layout: 配置の基準にする領域。Dialog::new()で計算したもの
     // width_adjust: 0.5で中央に表示
     // height_adjust: 0.5で中央に表示
     
@@ -321,8 +684,8 @@ fn calc_ml_text_pos(in_vec: &Vec<Text>,
     // 横については一行目を基準に左寄せ。
     for i in 0..in_vec.len() {
         let tmp_pos = Point2::new(
-            ((window_w - in_vec[0].width() as f32) * width_adjust).round(),
-            ((window_h - in_vec[i].height() as f32 + tmp_height) * height_adjust).round(),
+            layout.x + ((layout.w - in_vec[0].width() as f32) * width_adjust).round(),
+            layout.y + ((layout.h - in_vec[i].height() as f32 + tmp_height) * height_adjust).round(),
         );
        
         // 予幅を付けておいたほうが綺麗に表示できるはず  
@@ -0,0 +1,437 @@
+/*-------------------------------
+            waves.rs
+
+  岩の出方と速さの上がり方を、経過秒数ごとの区切り（ウェーブ）で表す
+
+  中身はassetsフォルダのwaves.tomlから読み込むので、
+  再ビルドせずに難易度を調整できる。書き方:
+
+    start_speed = 60.0      # ゲーム開始時の岩の速さ（ピクセル毎秒）
+    max_speed = 600.0       # 岩の速さの上限。省略したら上限なし
+
+    [[wave]]
+    start = 0.0             # この区切りが始まる経過秒数
+    spawn_interval = 4.0    # 何秒ごとに岩を一体増やすか
+    accel = 12.0            # 岩の速さの上昇量（ピクセル毎秒を毎秒）
+    kinds = { block = 6, diagonal = 3 }   # 出てくる種類と重み
+
+    [[burst]]
+    at = 60.0               # 最初に起きる秒数
+    every = 30.0            # 以降の間隔。省略したら一度だけ
+    count = 3               # 一度に出す岩の数
+    kind = "zigzag"         # 省略したら、その時の区切りのkindsから選ぶ
+
+  区切りは一つ以上必要で、最初の区切りはstart = 0.0、以降はstartの小さい順に並べる
+
+  * struct Wave : 区切り一つ分
+  * struct Burst: まとめて岩を出すイベント一つ分
+  * struct Waves: waves.toml一つ分
+
+  * impl Wave:
+    * pick()   : 重みを付けて、出てくる種類を乱数で一つ選ぶ
+
+  * impl Burst:
+    * crossed(): 前のフレームから今のフレームまでに、何回起きたか
+
+  * impl Waves:
+    * load()    : tomlファイルから読み込む。ファイルがなければ組み込みの初期値
+    * from_str(): tomlの文字列から作る。おかしな値ならエラー
+    * wave_at() : 経過秒数に合った区切り
+-------------------------------*/
+use std::collections::BTreeMap;
+use std::io::{ self, Result };
+use std::path::Path;
+
+use rand::{ Rng, XorShiftRng };
+use toml;
+
+use enemy::EnemyKind;
+use etc;
+
+/// 組み込みの初期値。assets/waves.tomlと同じ内容
+const DEFAULT_WAVES: &'static str = include_str!("../assets/waves.toml");
+
+#[derive(Clone, Debug, PartialEq)]
+/// 経過秒数の区切り一つ分
+pub struct Wave {
+    /// この区切りが始まる経過秒数
+    pub start: f64,
+    /// 何秒ごとに岩を一体増やすか
+    pub spawn_interval: f64,
+    /// 岩の速さの上昇量（ピクセル毎秒を毎秒）
+    pub accel: f32,
+    /// 出てくる種類と、その重み
+    pub kinds: Vec<(EnemyKind, u32)>,
+}
+
+impl Wave {
+    /// 重みを付けて、出てくる種類を乱数で一つ選ぶ
+    pub fn pick(&self, rng: &mut XorShiftRng) -> EnemyKind {
+        let total: u32 = self.kinds.iter().map(|&(_, w)| w).sum();
+        let mut n = rng.gen_range(0, total);
+
+        for &(kind, weight) in &self.kinds {
+            if n < weight {
+                return kind;
+            }
+            n -= weight;
+        }
+
+        EnemyKind::Block
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// まとめて岩を出すイベント一つ分
+pub struct Burst {
+    /// 最初に起きる秒数
+    pub at: f64,
+    /// 以降の間隔（秒）。0なら一度だけ
+    pub every: f64,
+    /// 一度に出す岩の数
+    pub count: u32,
+    /// 出す岩の種類。Noneならその時の区切りから選ぶ
+    pub kind: Option<EnemyKind>,
+}
+
+impl Burst {
+    /// 経過秒数が`prev`から`now`に進む間に、何回起きたか
+    pub fn crossed(&self, prev: f64, now: f64) -> usize {
+        // `t`秒までに起きた回数
+        let times = |t: f64| -> usize {
+            if t < self.at {
+                0
+            } else if self.every > 0.0 {
+                ((t - self.at) / self.every).floor() as usize + 1
+            } else {
+                1
+            }
+        };
+
+        times(now).saturating_sub(times(prev))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// waves.toml一つ分
+pub struct Waves {
+    /// ゲーム開始時の岩の速さ（ピクセル毎秒）
+    pub start_speed: f32,
+    /// 岩の速さの上限。Noneなら上限なし
+    pub max_speed: Option<f32>,
+    /// 区切りの一覧。startの小さい順
+    pub waves: Vec<Wave>,
+    pub bursts: Vec<Burst>,
+    /// 元になったtomlの文字列のハッシュ値。リプレイが同じwaves.tomlで記録されたかを見る
+    pub digest: u64,
+}
+
+/// 内部用。tomlファイルの中身
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WavesFile {
+    start_speed: f32,
+    #[serde(default)]
+    max_speed: Option<f32>,
+    wave: Vec<WaveFile>,
+    #[serde(default)]
+    burst: Vec<BurstFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WaveFile {
+    start: f64,
+    spawn_interval: f64,
+    accel: f32,
+    kinds: BTreeMap<String, u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BurstFile {
+    at: f64,
+    #[serde(default)]
+    every: f64,
+    count: u32,
+    #[serde(default)]
+    kind: Option<String>,
+}
+
+impl Default for Waves {
+    /// assets/waves.tomlと同じ内容。ファイルがない時や、ウィンドウなしで動かす時用
+    fn default() -> Self {
+        Waves::from_str(DEFAULT_WAVES).expect("組み込みのwaves.tomlが正しくありません")
+    }
+}
+
+impl Waves {
+    /// tomlファイルから読み込む
+    ///
+    /// ファイルがなければ、組み込みの初期値を使う
+    pub fn load<'a>(path: &'a Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Waves::default());
+        }
+
+        let text = etc::File::read_to_string(path)?;
+
+        Waves::from_str(&text).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData,
+                           format!("{}: {}", path.display(), e))
+        })
+    }
+
+    /// tomlの文字列から作る
+    ///
+    /// 知らない種類名、区切りの並び順がおかしいなどの場合はエラー
+    pub fn from_str<'a>(text: &'a str) -> ::std::result::Result<Self, String> {
+        let file: WavesFile = toml::de::from_str(text).map_err(|e| e.to_string())?;
+
+        if !(file.start_speed >= 0.0) {
+            return Err(format!("start_speedは0以上にしてください (start_speed = {})",
+                               file.start_speed));
+        }
+
+        if let Some(max) = file.max_speed {
+            if !(max >= file.start_speed) {
+                return Err(format!("max_speedはstart_speed以上にしてください (max_speed = {})",
+                                   max));
+            }
+        }
+
+        if file.wave.is_empty() {
+            return Err("[[wave]]が一つもありません".to_string());
+        }
+
+        let mut waves = Vec::with_capacity(file.wave.len());
+
+        for (i, w) in file.wave.into_iter().enumerate() {
+            let at = format!("{}番目の[[wave]]", i + 1);
+
+            let prev_start = waves.last().map(|prev: &Wave| prev.start);
+            match prev_start {
+                None if w.start != 0.0 => {
+                    return Err(format!("{}: 最初の区切りはstart = 0.0にしてください", at));
+                }
+                Some(prev) if !(w.start > prev) => {
+                    return Err(format!("{}: startは前の区切り({})より大きくしてください",
+                                       at, prev));
+                }
+                _ => (),
+            }
+
+            if !(w.spawn_interval > 0.0) {
+                return Err(format!("{}: spawn_intervalは0より大きくしてください", at));
+            }
+
+            let kinds = kinds_from_names(&w.kinds).map_err(|e| format!("{}: {}", at, e))?;
+
+            if kinds.iter().all(|&(_, weight)| weight == 0) {
+                return Err(format!("{}: kindsには重みが1以上の種類を一つ以上書いてください", at));
+            }
+
+            waves.push(Wave {
+                start: w.start,
+                spawn_interval: w.spawn_interval,
+                accel: w.accel,
+                kinds: kinds,
+            });
+        }
+
+        let mut bursts = Vec::with_capacity(file.burst.len());
+
+        for (i, b) in file.burst.into_iter().enumerate() {
+            let at = format!("{}番目の[[burst]]", i + 1);
+
+            if !(b.at >= 0.0) || !(b.every >= 0.0) {
+                return Err(format!("{}: atとeveryは0以上にしてください", at));
+            }
+
+            let kind = match b.kind {
+                Some(ref name) => Some(kind_from_name(name).map_err(|e| format!("{}: {}", at, e))?),
+                None => None,
+            };
+
+            bursts.push(Burst {
+                at: b.at,
+                every: b.every,
+                count: b.count,
+                kind: kind,
+            });
+        }
+
+        Ok(Waves {
+            start_speed: file.start_speed,
+            max_speed: file.max_speed,
+            waves: waves,
+            bursts: bursts,
+            digest: etc::fnv1a(text.as_bytes()),
+        })
+    }
+
+    /// 経過秒数`elapsed`に合った区切り
+    pub fn wave_at(&self, elapsed: f64) -> &Wave {
+        self.waves.iter()
+            .rev()
+            .find(|w| w.start <= elapsed)
+            .unwrap_or(&self.waves[0])
+    }
+}
+
+/// 内部用。種類名から種類を引く。知らない名前ならエラー
+fn kind_from_name<'a>(name: &'a str) -> ::std::result::Result<EnemyKind, String> {
+    EnemyKind::from_name(name).ok_or_else(|| {
+        let names: Vec<&str> = EnemyKind::all().iter().map(|k| k.name()).collect();
+        format!("\"{}\"という岩の種類はありません（書けるもの: {}）", name, names.join(", "))
+    })
+}
+
+/// 内部用。種類名と重みの表を、種類と重みの一覧にする
+///
+/// 並び順は、乱数の結果が変わらないようEnemyKind::all()の順に揃える
+fn kinds_from_names<'a>(src: &'a BTreeMap<String, u32>)
+    -> ::std::result::Result<Vec<(EnemyKind, u32)>, String> {
+    let mut out_vec = Vec::with_capacity(src.len());
+
+    for (name, &weight) in src {
+        out_vec.push((kind_from_name(name)?, weight));
+    }
+
+    out_vec.sort_by_key(|&(kind, _)| kind.index());
+
+    Ok(out_vec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// [[wave]]を一つだけ持つwaves.tomlの文字列
+    fn one_wave(kinds: &str) -> String {
+        format!("start_speed = 60.0\n\
+                 [[wave]]\nstart = 0.0\nspawn_interval = 4.0\naccel = 12.0\nkinds = {}\n",
+                kinds)
+    }
+
+    #[test]
+    fn default_waves_parse() {
+        let waves = Waves::default();
+
+        assert_eq!(waves.start_speed, 60.0);
+        assert_eq!(waves.waves[0].start, 0.0);
+        assert!(waves.waves.windows(2).all(|w| w[0].start < w[1].start));
+    }
+
+    #[test]
+    fn waves_must_start_at_zero_and_be_ordered() {
+        let late_start = "start_speed = 60.0\n\
+                          [[wave]]\nstart = 5.0\nspawn_interval = 4.0\naccel = 1.0\n\
+                          kinds = { block = 1 }\n";
+        let err = Waves::from_str(late_start).unwrap_err();
+        assert!(err.contains("start = 0.0"), "{}", err);
+
+        let unordered = "start_speed = 60.0\n\
+                         [[wave]]\nstart = 0.0\nspawn_interval = 4.0\naccel = 1.0\n\
+                         kinds = { block = 1 }\n\
+                         [[wave]]\nstart = 20.0\nspawn_interval = 4.0\naccel = 1.0\n\
+                         kinds = { block = 1 }\n\
+                         [[wave]]\nstart = 20.0\nspawn_interval = 4.0\naccel = 1.0\n\
+                         kinds = { block = 1 }\n";
+        let err = Waves::from_str(unordered).unwrap_err();
+        assert!(err.starts_with("3番目の[[wave]]"), "{}", err);
+
+        let err = Waves::from_str("start_speed = 60.0\nwave = []\n").unwrap_err();
+        assert!(err.contains("[[wave]]が一つもありません"), "{}", err);
+    }
+
+    #[test]
+    fn wave_at_switches_exactly_at_start() {
+        let waves = Waves::default();
+        let second = waves.waves[1].start;
+
+        assert_eq!(waves.wave_at(0.0).start, 0.0);
+        assert_eq!(waves.wave_at(second - 0.001).start, 0.0);
+        assert_eq!(waves.wave_at(second).start, second);
+        // 最後の区切りはずっと続く
+        assert_eq!(waves.wave_at(1.0e9), waves.waves.last().unwrap());
+        // 負の時刻でも最初の区切り
+        assert_eq!(waves.wave_at(-1.0).start, 0.0);
+    }
+
+    #[test]
+    fn pick_follows_weights() {
+        let waves = Waves::from_str(&one_wave("{ block = 3, heavy = 1, zigzag = 0 }")).unwrap();
+        let wave = &waves.waves[0];
+        // 並びは種類名の順ではなく、EnemyKind::all()の順
+        assert_eq!(wave.kinds, vec![(EnemyKind::Block, 3),
+                                    (EnemyKind::ZigZag, 0),
+                                    (EnemyKind::Heavy, 1)]);
+
+        let mut rng = etc::seeded_rng(7);
+        let picks: Vec<EnemyKind> = (0..4000).map(|_| wave.pick(&mut rng)).collect();
+        let heavy = picks.iter().filter(|&&k| k == EnemyKind::Heavy).count();
+
+        // 重み0の種類は出てこない
+        assert!(picks.iter().all(|&k| k != EnemyKind::ZigZag));
+        // 1/4前後
+        assert!(heavy > 800 && heavy < 1200, "heavy = {}", heavy);
+    }
+
+    #[test]
+    fn all_zero_weights_are_errors() {
+        let err = Waves::from_str(&one_wave("{ block = 0, heavy = 0 }")).unwrap_err();
+        assert!(err.contains("重みが1以上"), "{}", err);
+
+        let err = Waves::from_str(&one_wave("{}")).unwrap_err();
+        assert!(err.contains("重みが1以上"), "{}", err);
+    }
+
+    #[test]
+    fn unknown_kind_names_are_errors() {
+        let err = Waves::from_str(&one_wave("{ block = 1, boulder = 2 }")).unwrap_err();
+        assert!(err.contains("\"boulder\""), "{}", err);
+        assert!(err.contains("block, zigzag"), "{}", err);
+
+        let burst = format!("{}[[burst]]\nat = 1.0\ncount = 1\nkind = \"boulder\"\n",
+                            one_wave("{ block = 1 }"));
+        let err = Waves::from_str(&burst).unwrap_err();
+        assert!(err.starts_with("1番目の[[burst]]"), "{}", err);
+    }
+
+    #[test]
+    fn max_speed_must_not_be_below_start_speed() {
+        let text = one_wave("{ block = 1 }").replace("start_speed = 60.0",
+                                                     "start_speed = 60.0\nmax_speed = 50.0");
+        assert!(Waves::from_str(&text).is_err());
+
+        let text = one_wave("{ block = 1 }").replace("start_speed = 60.0",
+                                                     "start_speed = 60.0\nmax_speed = 80.0");
+        assert_eq!(Waves::from_str(&text).unwrap().max_speed, Some(80.0));
+    }
+
+    #[test]
+    fn burst_counts_each_crossing_once() {
+        let burst = Burst { at: 60.0, every: 30.0, count: 3, kind: None };
+
+        assert_eq!(burst.crossed(59.9, 60.0), 1);
+        assert_eq!(burst.crossed(60.0, 60.1), 0);
+        assert_eq!(burst.crossed(89.9, 90.0), 1);
+        // 一フレームで何度もまたいだら、その回数
+        assert_eq!(burst.crossed(0.0, 150.0), 4);
+
+        let once = Burst { at: 10.0, every: 0.0, count: 1, kind: None };
+        assert_eq!(once.crossed(9.0, 11.0), 1);
+        assert_eq!(once.crossed(11.0, 100.0), 0);
+    }
+
+    #[test]
+    fn digest_follows_the_text() {
+        let a = Waves::from_str(&one_wave("{ block = 1 }")).unwrap();
+        let b = Waves::from_str(&one_wave("{ block = 1 }")).unwrap();
+        let c = Waves::from_str(&one_wave("{ block = 2 }")).unwrap();
+
+        assert_eq!(a.digest, b.digest);
+        assert_ne!(a.digest, c.digest);
+    }
+}